@@ -0,0 +1,955 @@
+use std::borrow::Cow;
+use std::cmp;
+use std::collections::{BTreeSet, HashMap};
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::io::prelude::*;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::path::Path;
+
+use chrono::{NaiveDate, NaiveTime, Utc};
+use clap::{Args, ValueEnum};
+use log::{debug, warn};
+use smartstring::alias::String;
+
+use crate::{Band, Call, Frequency, Qso};
+use crate::decodes::LOCAL_ID;
+use crate::input;
+use crate::locator;
+#[cfg(feature = "fetch")]
+use crate::pskreporter::PSK_REPORTER_ID;
+use crate::template::{self, Template};
+
+/// Station details of the operator included in every record
+#[derive(Args, Clone, Debug, Default)]
+pub struct Station {
+	/// Call sign used over the air, if different from the operator
+	#[arg(long, value_name = "CALL", env = "WSPRSPOTS_STATION_CALLSIGN")]
+	pub station_callsign: Option<Call>,
+
+	/// Call sign of the operator, if different from the call sign in the spots
+	#[arg(long, value_name = "CALL", env = "WSPRSPOTS_OPERATOR")]
+	pub operator: Option<Call>,
+
+	/// Call sign of the owner of the station
+	#[arg(long, value_name = "CALL", env = "WSPRSPOTS_OWNER_CALLSIGN")]
+	pub owner_callsign: Option<Call>,
+
+	/// Call sign fields to include, separated by commas, the call sign in the spots standing in for
+	/// those not given; by default OPERATOR, STATION_CALLSIGN if it differs and OWNER_CALLSIGN if given
+	#[arg(long, value_name = "FIELD", value_delimiter = ',', env = "WSPRSPOTS_CALLSIGN_FIELDS")]
+	pub callsign_fields: Vec<CallsignField>,
+
+	/// Operator name
+	#[arg(long, value_name = "NAME", env = "WSPRSPOTS_MY_NAME")]
+	pub my_name: Option<String>,
+
+	/// City of the station
+	#[arg(long, value_name = "CITY", env = "WSPRSPOTS_MY_CITY")]
+	pub my_city: Option<String>,
+
+	/// Country of the station
+	#[arg(long, value_name = "COUNTRY", env = "WSPRSPOTS_MY_COUNTRY")]
+	pub my_country: Option<String>,
+
+	/// DXCC entity code of the station
+	#[arg(long, value_name = "CODE", env = "WSPRSPOTS_MY_DXCC")]
+	pub my_dxcc: Option<u16>,
+
+	/// CQ zone of the station
+	#[arg(long, value_name = "ZONE", value_parser = clap::value_parser!(u8).range(1..=40), env = "WSPRSPOTS_MY_CQ_ZONE")]
+	pub my_cq_zone: Option<u8>,
+
+	/// ITU zone of the station
+	#[arg(long, value_name = "ZONE", value_parser = clap::value_parser!(u8).range(1..=90), env = "WSPRSPOTS_MY_ITU_ZONE")]
+	pub my_itu_zone: Option<u8>,
+
+	/// Description of the station’s equipment
+	#[arg(long, value_name = "RIG", env = "WSPRSPOTS_MY_RIG")]
+	pub my_rig: Option<String>,
+
+	/// Description of the station’s antenna
+	#[arg(long, value_name = "ANTENNA", env = "WSPRSPOTS_MY_ANTENNA")]
+	pub my_antenna: Option<String>,
+
+	#[command(flatten)]
+	pub activation: Activation,
+}
+
+/// References of a station operating portable in the SOTA, POTA and WWFF award programs
+#[derive(Args, Clone, Debug, Default)]
+pub struct Activation {
+	/// SOTA reference of the summit the station is on, such as W7W/KG-124
+	#[arg(id = "my_sota", long = "my-sota", value_name = "REF", value_parser = parse_reference, env = "WSPRSPOTS_MY_SOTA")]
+	pub sota: Option<String>,
+
+	/// POTA references of the parks the station is in, separated by commas, such as K-0001
+	#[arg(id = "my_pota", long = "my-pota", value_name = "REF", value_delimiter = ',', value_parser = parse_reference,
+	      env = "WSPRSPOTS_MY_POTA")]
+	pub pota: Vec<String>,
+
+	/// WWFF reference of the area the station is in, such as DLFF-0001
+	#[arg(id = "my_wwff", long = "my-wwff", value_name = "REF", value_parser = parse_reference, env = "WSPRSPOTS_MY_WWFF")]
+	pub wwff: Option<String>,
+}
+
+impl Activation {
+	/// Program and reference given as SIG and SIG_INFO, the first of SOTA, POTA and WWFF
+	fn sig(&self) -> Option<(&'static str, std::string::String)> {
+		if let Some(sota) = &self.sota {
+			Some(("SOTA", sota.to_string()))
+		} else if !self.pota.is_empty() {
+			Some(("POTA", self.pota.join(",")))
+		} else {
+			self.wwff.as_ref().map(|wwff| ("WWFF", wwff.to_string()))
+		}
+	}
+}
+
+/// Parse a SOTA, POTA or WWFF reference
+fn parse_reference(reference: &str) -> Result<String, std::string::String> {
+	if reference.is_empty() || !reference.chars().all(|ch| ch.is_ascii_alphanumeric() || ch == '-' || ch == '/') {
+		return Err("expected letters, digits, hyphens and slashes".into());
+	}
+
+	Ok(reference.to_ascii_uppercase().into())
+}
+
+/// References of contacts operating portable, by call sign
+pub type References = HashMap<Call, Activation>;
+
+/// Read the references of contacts, one per line as `CALL PROGRAM REF`, with `#` starting a comment
+pub fn read_references(path: &str) -> Result<References, std::string::String> {
+	let text = fs::read_to_string(path).map_err(|err| format!("{path}: {err}"))?;
+	let mut references = References::new();
+
+	for (idx, line) in text.lines().enumerate() {
+		let line = line.split('#').next().unwrap_or_default();
+		let fields: Vec<_> = line.split_whitespace().collect();
+		let [call, program, reference] = fields[..] else {
+			if fields.is_empty() {
+				continue;
+			}
+
+			return Err(format!("{path}:{}: expected CALL PROGRAM REF", idx + 1));
+		};
+
+		let reference = parse_reference(reference).map_err(|err| format!("{path}:{}: {err}", idx + 1))?;
+		let activation = references.entry(Call::new(call.into())).or_default();
+		match program.to_ascii_uppercase().as_str() {
+			"SOTA" => activation.sota = Some(reference),
+			"POTA" => activation.pota.push(reference),
+			"WWFF" => activation.wwff = Some(reference),
+			_ => return Err(format!("{path}:{}: unknown program {program}, expected SOTA, POTA or WWFF", idx + 1)),
+		}
+	}
+
+	Ok(references)
+}
+
+/// ADIF field holding a call sign of the operator’s station
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum CallsignField {
+	/// OPERATOR, the call sign of the person operating
+	Operator,
+	/// STATION_CALLSIGN, the call sign used over the air, as matched by LoTW
+	StationCallsign,
+	/// OWNER_CALLSIGN, the call sign of the owner of the station
+	OwnerCallsign,
+}
+
+impl Station {
+	/// Value of a call sign field for a QSO, if it is to be included
+	fn callsign<'a>(&'a self, field: CallsignField, qso: &'a Qso) -> Option<&'a Call> {
+		let value = match field {
+			CallsignField::Operator => &self.operator,
+			CallsignField::StationCallsign => &self.station_callsign,
+			CallsignField::OwnerCallsign => &self.owner_callsign,
+		};
+
+		if self.callsign_fields.is_empty() {
+			// The call sign in the spots is that of the station once another operator is given
+			let spotted = match field {
+				CallsignField::Operator => true,
+				CallsignField::StationCallsign => self.operator.is_some(),
+				CallsignField::OwnerCallsign => false,
+			};
+
+			value.as_ref().or(spotted.then_some(&qso.call_op))
+		} else if self.callsign_fields.contains(&field) {
+			Some(value.as_ref().unwrap_or(&qso.call_op))
+		} else {
+			None
+		}
+	}
+}
+
+/// Free‐text messages included in every record
+#[derive(Args, Clone, Debug)]
+pub struct Messages {
+	/// Template for the QSL message, with placeholders {band}, {power}, {power_dbm}, {snr}, {my_snr},
+	/// {drift}, {distance}, {spots}, {call}, {my_call}, {grid} and {my_grid}
+	#[arg(long, value_name = "TEMPLATE", default_value = template::DEFAULT, env = "WSPRSPOTS_QSLMSG")]
+	pub qslmsg: Template,
+
+	/// Template for the comment, with the same placeholders as for the QSL message
+	#[arg(long, value_name = "TEMPLATE", default_value = template::DEFAULT, env = "WSPRSPOTS_COMMENT")]
+	pub comment: Template,
+
+	/// Omit the QSL message
+	#[arg(long, env = "WSPRSPOTS_NO_QSLMSG")]
+	pub no_qslmsg: bool,
+
+	/// Omit the comment
+	#[arg(long, env = "WSPRSPOTS_NO_COMMENT")]
+	pub no_comment: bool,
+}
+
+/// Record contents beyond the QSO itself
+#[derive(Args, Clone, Debug)]
+pub struct RecordOptions {
+	/// Names of the columns following the standard WSPRnet columns, separated by commas, to include
+	/// as APP_WSPRSPOTS_ fields
+	#[arg(long, value_name = "NAME", value_delimiter = ',', value_parser = parse_field_name, env = "WSPRSPOTS_EXTRA_COLUMNS")]
+	pub extra_columns: Vec<String>,
+
+	/// Optional fields to include, separated by commas: LAT, LON, MY_LAT and MY_LON
+	#[arg(long, value_name = "FIELD", value_delimiter = ',', value_parser = parse_optional_field, env = "WSPRSPOTS_FIELDS")]
+	pub fields: Vec<String>,
+
+	/// Fields to leave out, separated by commas, for loggers that reject them
+	#[arg(long, value_name = "FIELD", value_delimiter = ',', value_parser = parse_omitted_field, env = "WSPRSPOTS_OMIT_FIELDS")]
+	pub omit_fields: Vec<String>,
+
+	/// File of SOTA, POTA and WWFF references of contacts operating portable, one per line as
+	/// CALL PROGRAM REF
+	#[arg(long, value_name = "FILE", value_parser = read_references, env = "WSPRSPOTS_REFERENCES")]
+	pub references: Option<References>,
+
+	/// SNR reported as RST_RCVD and RST_SENT
+	#[arg(long, value_name = "STATISTIC", default_value = "max", env = "WSPRSPOTS_RST_SNR")]
+	pub rst_snr: SnrStatistic,
+
+	/// Shorten locators to this many characters, as some loggers only take four or six; those longer
+	/// than eight are split into GRIDSQUARE and GRIDSQUARE_EXT
+	#[arg(long, value_name = "N", default_value = "12", value_parser = parse_grid_length, env = "WSPRSPOTS_MAX_GRID_LENGTH")]
+	pub max_grid_length: usize,
+
+	/// Keep characters beyond ASCII in fields, encoded as UTF‐8 with lengths counting characters,
+	/// instead of replacing them as ADIF requires
+	#[arg(long, env = "WSPRSPOTS_ADIF_UTF8")]
+	pub adif_utf8: bool,
+
+	/// File with the template of logs in the template format, rendered for every QSO with the
+	/// placeholders of the QSL message and more
+	#[arg(long, value_name = "FILE", value_parser = template::read, env = "WSPRSPOTS_TEMPLATE")]
+	pub template: Option<Template>,
+
+	#[command(flatten, next_help_heading = "Station details")]
+	pub station: Station,
+
+	#[command(flatten, next_help_heading = "Messages")]
+	pub messages: Messages,
+}
+
+/// Statistic of the SNR over the spots in one direction
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum SnrStatistic {
+	/// Best SNR
+	Max,
+	/// Worst SNR
+	Min,
+	/// Mean SNR, rounded to whole decibels
+	Mean,
+}
+
+/// Parse the name of an application‐defined field
+fn parse_field_name(name: &str) -> Result<String, std::string::String> {
+	if name.is_empty() || !name.chars().all(|ch| ch.is_ascii_alphanumeric() || ch == '_') {
+		return Err("expected letters, digits and underscores".into());
+	}
+
+	Ok(name.to_ascii_uppercase().into())
+}
+
+/// Fields only included when asked for
+const OPTIONAL_FIELDS: &[&str] = &["LAT", "LON", "MY_LAT", "MY_LON"];
+
+/// Fields every record needs to be imported or read back
+const REQUIRED_FIELDS: &[&str] = &["CALL", "MODE", "QSO_DATE", "TIME_ON"];
+
+/// Parse the name of an optional field
+fn parse_optional_field(name: &str) -> Result<String, std::string::String> {
+	let name = name.to_ascii_uppercase();
+	if !OPTIONAL_FIELDS.contains(&name.as_str()) {
+		return Err(format!("expected one of {}", OPTIONAL_FIELDS.join(", ")));
+	}
+
+	Ok(name.into())
+}
+
+/// Parse the name of a field to leave out
+fn parse_omitted_field(name: &str) -> Result<String, std::string::String> {
+	let name = parse_field_name(name)?;
+	if REQUIRED_FIELDS.contains(&name.as_str()) {
+		return Err(format!("{name} is required in every record"));
+	}
+
+	Ok(name)
+}
+
+impl RecordOptions {
+	/// Whether a field is to be included in the records
+	fn includes(&self, name: &str) -> bool {
+		if OPTIONAL_FIELDS.contains(&name) && !self.fields.iter().any(|field| field == name) {
+			return false;
+		}
+
+		!self.omit_fields.iter().any(|field| field == name)
+	}
+}
+
+/// ASCII replacement of a Latin letter with diacritics or ligature
+fn transliterate(ch: char) -> Option<&'static str> {
+	Some(match ch {
+		'À'..='Å' | 'Ā' | 'Ă' | 'Ą' => "A",
+		'à'..='å' | 'ā' | 'ă' | 'ą' => "a",
+		'Æ' => "AE",
+		'æ' => "ae",
+		'Ç' | 'Ć' | 'Č' => "C",
+		'ç' | 'ć' | 'č' => "c",
+		'Ð' | 'Ď' | 'Đ' => "D",
+		'ð' | 'ď' | 'đ' => "d",
+		'È'..='Ë' | 'Ē' | 'Ė' | 'Ę' | 'Ě' => "E",
+		'è'..='ë' | 'ē' | 'ė' | 'ę' | 'ě' => "e",
+		'Ğ' => "G",
+		'ğ' => "g",
+		'Ì'..='Ï' | 'Ī' | 'İ' => "I",
+		'ì'..='ï' | 'ī' | 'ı' => "i",
+		'Ł' => "L",
+		'ł' => "l",
+		'Ñ' | 'Ń' | 'Ň' => "N",
+		'ñ' | 'ń' | 'ň' => "n",
+		'Ò'..='Ö' | 'Ø' | 'Ő' => "O",
+		'ò'..='ö' | 'ø' | 'ő' => "o",
+		'Œ' => "OE",
+		'œ' => "oe",
+		'Ř' => "R",
+		'ř' => "r",
+		'Ś' | 'Š' | 'Ş' => "S",
+		'ś' | 'š' | 'ş' => "s",
+		'ß' => "ss",
+		'Ť' | 'Ţ' => "T",
+		'ť' | 'ţ' => "t",
+		'Þ' => "TH",
+		'þ' => "th",
+		'Ù'..='Ü' | 'Ū' | 'Ů' | 'Ű' => "U",
+		'ù'..='ü' | 'ū' | 'ů' | 'ű' => "u",
+		'Ý' | 'Ÿ' => "Y",
+		'ý' | 'ÿ' => "y",
+		'Ź' | 'Ż' | 'Ž' => "Z",
+		'ź' | 'ż' | 'ž' => "z",
+		'‐' | '‑' | '–' | '—' => "-",
+		'‘' | '’' => "'",
+		'“' | '”' => "\"",
+		_ => return None,
+	})
+}
+
+/// Data of a field as allowed in ADI files
+///
+/// Control characters, which would break lines, are replaced by spaces and angle brackets, which
+/// readers may take for the start of the next field, by parentheses. Unless kept, characters
+/// beyond ASCII are transliterated where possible and replaced by question marks otherwise.
+fn sanitize(value: &str, utf8: bool) -> Cow<'_, str> {
+	if value.chars().all(|ch| (ch.is_ascii_graphic() || ch == ' ' || (utf8 && !ch.is_ascii() && !ch.is_control())) && ch != '<' && ch != '>') {
+		return Cow::Borrowed(value);
+	}
+
+	let mut clean = std::string::String::with_capacity(value.len());
+	for ch in value.chars() {
+		match ch {
+			'<' => clean.push('('),
+			'>' => clean.push(')'),
+			ch if ch.is_control() => clean.push(' '),
+			ch if ch.is_ascii() || utf8 => clean.push(ch),
+			ch => clean.push_str(transliterate(ch).unwrap_or("?")),
+		}
+	}
+
+	Cow::Owned(clean)
+}
+
+/// Split a valid locator into the part of up to eight characters for GRIDSQUARE and the rest for
+/// GRIDSQUARE_EXT, shortening it to the given length first
+fn split_grid(grid: &str, max_len: usize) -> (&str, Option<&str>) {
+	let grid = &grid[..cmp::min(grid.len(), max_len)];
+	match grid.split_at_checked(8) {
+		Some((grid, ext)) if !ext.is_empty() => (grid, Some(ext)),
+		_ => (grid, None),
+	}
+}
+
+/// Parse the length locators are shortened to
+fn parse_grid_length(len: &str) -> Result<usize, std::string::String> {
+	match len.parse() {
+		Ok(len @ (4 | 6 | 8 | 10 | 12)) => Ok(len),
+		_ => Err("expected 4, 6, 8, 10 or 12".into()),
+	}
+}
+
+/// ADIF coordinate of a latitude or longitude in degrees, such as N051 30.000
+fn coordinate(degrees: f64, positive: char, negative: char) -> String {
+	let sign = if degrees < 0.0 { negative } else { positive };
+	let minutes = (degrees.abs() * 60.0 * 1000.0).round() / 1000.0;
+	format!("{sign}{:03} {:06.3}", (minutes / 60.0).floor(), minutes % 60.0).into()
+}
+
+/// ADIF 3.1.4 enumeration value of the band containing a frequency, if any
+fn band(freq: Frequency) -> Option<String> {
+	let band = Band::try_from(freq).ok()?;
+	match (&*band.0, band.1) {
+		// Named after the upper end of the band
+		("2200", "m") => Some("2190m".into()),
+		// Not enumerated, so only the frequency is given
+		(_, "kHz") => None,
+		(num, unit) => Some(format!("{num}{unit}").into()),
+	}
+}
+
+/// ADIF record of a QSO
+pub struct Record<'a> {
+	pub qso: &'a Qso,
+	pub options: &'a RecordOptions,
+}
+
+impl fmt::Display for Record<'_> {
+	fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+		macro_rules! adif {
+			($name:tt, $($arg:tt)*) => {{
+				let name = $name;
+				if self.options.includes(&name) {
+					let value = format!($($arg)*);
+					let value = sanitize(&value, self.options.adif_utf8);
+					let len = if self.options.adif_utf8 { value.chars().count() } else { value.len() };
+					write!(fmtr, "<{}:{}>{}", name, len, value)
+				} else {
+					Ok(())
+				}
+			}}
+		}
+
+		macro_rules! adif_opt {
+			($name:tt, $value:expr) => {
+				if let Some(value) = &$value {
+					adif!($name, "{}", value)?;
+				}
+			}
+		}
+
+		/// Whether a spot ID was assigned locally instead of by WSPRnet
+		fn is_synthetic(id: u64) -> bool {
+			#[cfg(feature = "fetch")]
+			if id & PSK_REPORTER_ID != 0 {
+				return true;
+			}
+
+			id & LOCAL_ID != 0
+		}
+
+		fn fmt_spots(set: &BTreeSet<u64>) -> String {
+			let (local, wsprnet): (Vec<&u64>, Vec<&u64>) = set.iter().partition(|&&id| id & LOCAL_ID != 0);
+			#[cfg(feature = "fetch")]
+			let (psk, wsprnet): (Vec<&u64>, Vec<&u64>) = wsprnet.into_iter().partition(|&&id| id & PSK_REPORTER_ID != 0);
+			let mut st = String::new();
+
+			if !wsprnet.is_empty() {
+				st.push_str("WSPRnet spot IDs ");
+				for (idx, id) in wsprnet.iter().enumerate() {
+					if idx > 0 {
+						st.push_str(", ");
+					}
+
+					st.push_str(&id.to_string());
+				}
+			}
+
+			if !local.is_empty() {
+				if !st.is_empty() {
+					st.push_str(" and ");
+				}
+
+				st.push_str(&format!("{} local decode{}", local.len(), if local.len() == 1 { "" } else { "s" }));
+			}
+
+			#[cfg(feature = "fetch")]
+			if !psk.is_empty() {
+				if !st.is_empty() {
+					st.push_str(" and ");
+				}
+
+				st.push_str(&format!("{} PSK Reporter report{}", psk.len(), if psk.len() == 1 { "" } else { "s" }));
+			}
+
+			st
+		}
+
+		let qso = self.qso;
+		let station = &self.options.station;
+		let messages = &self.options.messages;
+
+		adif!("QSO_DATE", "{}", qso.datetime_on().format("%Y%m%d"))?;
+		adif!("TIME_ON", "{}", qso.datetime_on().format("%H%M"))?;
+		adif!("QSO_DATE_OFF", "{}", qso.datetime_off().format("%Y%m%d"))?;
+		adif!("TIME_OFF", "{}", qso.datetime_off().format("%H%M"))?;
+		adif_opt!("OPERATOR", station.callsign(CallsignField::Operator, qso));
+		adif_opt!("STATION_CALLSIGN", station.callsign(CallsignField::StationCallsign, qso));
+		adif_opt!("OWNER_CALLSIGN", station.callsign(CallsignField::OwnerCallsign, qso));
+		adif!("CALL", "{}", qso.call_ct)?;
+		// Malformed locators are rejected by importers
+		if locator::position(&qso.grid_op).is_some() {
+			let (grid, ext) = split_grid(&qso.grid_op, self.options.max_grid_length);
+			adif!("MY_GRIDSQUARE", "{}", grid)?;
+			adif_opt!("MY_GRIDSQUARE_EXT", ext);
+		}
+
+		if locator::position(&qso.grid_ct).is_some() {
+			let (grid, ext) = split_grid(&qso.grid_ct, self.options.max_grid_length);
+			adif!("GRIDSQUARE", "{}", grid)?;
+			adif_opt!("GRIDSQUARE_EXT", ext);
+		}
+
+		if let Some((lat, lon)) = locator::position(&qso.grid_op) {
+			adif!("MY_LAT", "{}", coordinate(lat, 'N', 'S'))?;
+			adif!("MY_LON", "{}", coordinate(lon, 'E', 'W'))?;
+		}
+
+		if let Some((lat, lon)) = locator::position(&qso.grid_ct) {
+			adif!("LAT", "{}", coordinate(lat, 'N', 'S'))?;
+			adif!("LON", "{}", coordinate(lon, 'E', 'W'))?;
+		}
+
+		adif_opt!("MY_NAME", station.my_name);
+		adif_opt!("MY_CITY", station.my_city);
+		adif_opt!("MY_COUNTRY", station.my_country);
+		adif_opt!("MY_DXCC", station.my_dxcc);
+		adif_opt!("MY_CQ_ZONE", station.my_cq_zone);
+		adif_opt!("MY_ITU_ZONE", station.my_itu_zone);
+		adif_opt!("MY_RIG", station.my_rig);
+		adif_opt!("MY_ANTENNA", station.my_antenna);
+
+		// Award references, with the first also as special interest activity
+		let activations = [
+			("MY_", Some(&station.activation)),
+			("", self.options.references.as_ref().and_then(|references| references.get(&qso.call_ct))),
+		];
+		for (prefix, activation) in activations {
+			let Some(activation) = activation else {
+				continue;
+			};
+
+			let [sota, pota, wwff, sig, sig_info] = ["SOTA_REF", "POTA_REF", "WWFF_REF", "SIG", "SIG_INFO"]
+				.map(|name| format!("{prefix}{name}"));
+			adif_opt!(sota, activation.sota);
+			if !activation.pota.is_empty() {
+				adif!(pota, "{}", activation.pota.join(","))?;
+			}
+
+			adif_opt!(wwff, activation.wwff);
+			if let Some((program, info)) = activation.sig() {
+				adif!(sig, "{}", program)?;
+				adif!(sig_info, "{}", info)?;
+			}
+		}
+
+		let (rst_rcvd, rst_sent) = match self.options.rst_snr {
+			SnrStatistic::Max => (qso.snr_op, qso.snr_ct),
+			SnrStatistic::Min => (qso.snr_op_min, qso.snr_ct_min),
+			SnrStatistic::Mean => (qso.snr_op_mean().round() as i8, qso.snr_ct_mean().round() as i8),
+		};
+
+		adif!("RST_RCVD", "{:+03}", rst_rcvd)?;
+		adif!("RST_SENT", "{:+03}", rst_sent)?;
+		adif!("FREQ", "{:.6}", qso.freq_op.mhz())?;
+		adif!("RX_FREQ", "{:.6}", qso.freq_ct.mhz())?;
+
+		adif_opt!("BAND", band(qso.freq_op));
+		adif_opt!("BAND_RX", band(qso.freq_ct));
+
+		if qso.power_op.is_known() {
+			adif!("TX_PWR", "{:.4}", qso.power_op.watts())?;
+		}
+
+		if qso.power_ct.is_known() {
+			adif!("RX_PWR", "{:.4}", qso.power_ct.watts())?;
+		}
+		adif!("DISTANCE", "{}", qso.distance)?;
+		if let Some(azimuth) = locator::bearing(&qso.grid_op, &qso.grid_ct) {
+			adif!("ANT_AZ", "{:.0}", azimuth)?;
+			adif!("ANT_PATH", "S")?;
+		}
+
+		if !messages.no_qslmsg {
+			adif!("QSLMSG", "{}", messages.qslmsg.render(qso))?;
+		}
+
+		if !messages.no_comment {
+			adif!("COMMENT", "{}", messages.comment.render(qso))?;
+		}
+
+		adif!("NOTES", "{}", fmt_spots(&qso.spots))?;
+
+		// Provenance of the QSO for other tools
+		let ids = qso.spots.iter()
+			.filter(|&&id| !is_synthetic(id))
+			.map(u64::to_string)
+			.collect::<Vec<_>>();
+		if !ids.is_empty() {
+			adif!("APP_WSPRSPOTS_SPOT_IDS", "{}", ids.join(","))?;
+		}
+
+		adif!("APP_WSPRSPOTS_QSO_ID", "{}", qso.id())?;
+		adif!("APP_WSPRSPOTS_SERIAL", "{}", qso.serial)?;
+		adif!("APP_WSPRSPOTS_SPOT_COUNT", "{}", qso.spots.len())?;
+		adif!("APP_WSPRSPOTS_RX_SPOTS", "{}", qso.num_rx)?;
+		adif!("APP_WSPRSPOTS_TX_SPOTS", "{}", qso.num_tx)?;
+		adif!("APP_WSPRSPOTS_MY_SNR_MIN", "{}", qso.snr_op_min)?;
+		adif!("APP_WSPRSPOTS_MY_SNR_MAX", "{}", qso.snr_op)?;
+		adif!("APP_WSPRSPOTS_MY_SNR_MEAN", "{:.1}", qso.snr_op_mean())?;
+		adif!("APP_WSPRSPOTS_SNR_MIN", "{}", qso.snr_ct_min)?;
+		adif!("APP_WSPRSPOTS_SNR_MAX", "{}", qso.snr_ct)?;
+		adif!("APP_WSPRSPOTS_SNR_MEAN", "{:.1}", qso.snr_ct_mean())?;
+
+		// Extra columns as measured by the operator and the contact, respectively
+		for (idx, name) in self.options.extra_columns.iter().enumerate() {
+			if let Some(value) = qso.extra_op.get(idx).filter(|value| !value.is_empty()) {
+				let field = format!("APP_WSPRSPOTS_MY_{name}");
+				adif!(field, "{}", value)?;
+			}
+
+			if let Some(value) = qso.extra_ct.get(idx).filter(|value| !value.is_empty()) {
+				let field = format!("APP_WSPRSPOTS_{name}");
+				adif!(field, "{}", value)?;
+			}
+		}
+
+
+		if qso.mode.is_fst4w() {
+			adif!("MODE", "MFSK")?;
+			adif!("SUBMODE", "FST4W")?;
+		} else {
+			adif!("MODE", "WSPR")?;
+		}
+
+		adif!("QSO_RANDOM", "Y")?;
+		write!(fmtr, "<EOR>")
+	}
+}
+
+/// ADIF log writer
+pub struct Adif<W: Write> {
+	out: W,
+}
+
+impl<W: Write> Adif<W> {
+	/// Start a new log, writing the ADIF header
+	pub fn new(mut out: W, title: &str) -> io::Result<Self> {
+		let pkg_name = env!("CARGO_PKG_NAME");
+		let pkg_version = env!("CARGO_PKG_VERSION");
+		writeln!(out, "{}\n\
+		         <ADIF_VER:5>3.1.4\
+		         <CREATED_TIMESTAMP:15>{}\
+		         <PROGRAMID:{}>{}\
+		         <PROGRAMVERSION:{}>{}\
+		         <EOH>",
+		         sanitize(title, true), Utc::now().format("%Y%m%d %H%M%S"), pkg_name.len(), pkg_name, pkg_version.len(), pkg_version)?;
+
+		Ok(Adif { out })
+	}
+
+	/// Continue an existing log that already has a header
+	pub fn resume(out: W) -> Self {
+		Adif { out }
+	}
+
+	/// Write a QSO record
+	pub fn write(&mut self, qso: &Qso, options: &RecordOptions) -> io::Result<()> {
+		writeln!(self.out, "{}", Record { qso, options })
+	}
+
+	pub fn into_inner(self) -> W {
+		self.out
+	}
+}
+
+/// Sender of ADIF records over UDP to logging programs as QSOs are completed
+///
+/// Every record is sent in a datagram of its own, as logging programs such as N1MM Logger+ and
+/// Log4OM expect from ADIF broadcasts. Failures are only warned about, so that a logging program
+/// that is not running does not stop the run.
+pub struct Broadcast {
+	socket: UdpSocket,
+	addr: SocketAddr,
+	options: RecordOptions,
+}
+
+impl Broadcast {
+	/// Send records to the given host and port, which may be a broadcast address
+	pub fn new(addr: &str, options: RecordOptions) -> io::Result<Self> {
+		let addr = addr.to_socket_addrs()
+			.map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, format!("Cannot resolve {addr}: {err}")))?
+			.next()
+			.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("No address found for {addr}")))?;
+
+		let socket = match addr {
+			SocketAddr::V4(_) => {
+				let socket = UdpSocket::bind("0.0.0.0:0")?;
+				socket.set_broadcast(true)?;
+				socket
+			},
+			SocketAddr::V6(_) => UdpSocket::bind("[::]:0")?,
+		};
+
+		Ok(Broadcast { socket, addr, options })
+	}
+
+	/// Send the record of a QSO
+	pub fn send(&self, qso: &Qso) {
+		let record = Record { qso, options: &self.options }.to_string();
+		if let Err(err) = self.socket.send_to(record.as_bytes(), self.addr) {
+			warn!("Cannot send QSO between {} and {} to {}: {err}", qso.call_op, qso.call_ct, self.addr);
+		}
+	}
+}
+
+/// Number of bytes of a field value of the given length at the start of the text
+///
+/// Lengths count characters in UTF‐8 logs as written with `--adif-utf8`, and bytes in logs that
+/// are not valid UTF‐8, such as those in Latin‐1.
+fn value_len(text: &[u8], len: usize) -> usize {
+	let text = &text[..cmp::min(len.saturating_mul(4), text.len())];
+	let valid = match std::str::from_utf8(text) {
+		Ok(valid) => valid,
+		Err(err) => std::str::from_utf8(&text[..err.valid_up_to()]).unwrap_or_default(),
+	};
+
+	match valid.char_indices().nth(len) {
+		Some((idx, _)) => idx,
+		None if valid.len() == text.len() => valid.len(),
+		None => cmp::min(len, text.len()),
+	}
+}
+
+/// Fields of the records in an ADIF log, by names in upper case
+///
+/// The header is skipped, as are fields without a length.
+pub fn records(text: &[u8]) -> io::Result<Vec<HashMap<String, String>>> {
+	let mut records = Vec::new();
+	let mut record = HashMap::new();
+	// Logs without header start with a field
+	let mut header = text.trim_ascii_start().first() != Some(&b'<');
+	let mut pos = 0;
+
+	while let Some(start) = text[pos..].iter().position(|&ch| ch == b'<').map(|offset| pos + offset) {
+		let Some(end) = text[start..].iter().position(|&ch| ch == b'>').map(|offset| start + offset) else {
+			break;
+		};
+
+		let spec = std::string::String::from_utf8_lossy(&text[start + 1..end]);
+		let mut parts = spec.split(':');
+		let name = parts.next().unwrap_or_default().trim().to_ascii_uppercase();
+		pos = end + 1;
+
+		match name.as_str() {
+			"EOH" => {
+				header = false;
+				record.clear();
+				continue;
+			},
+			"EOR" => {
+				records.push(std::mem::take(&mut record));
+				continue;
+			},
+			_ => (),
+		}
+
+		let Some(len) = parts.next() else {
+			continue;
+		};
+
+		let len: usize = len.trim().parse().map_err(|_| io::Error::new(io::ErrorKind::InvalidData,
+			format!("Invalid length of field {name} at byte {start}")))?;
+		let value = &text[pos..pos + value_len(&text[pos..], len)];
+		pos += value.len();
+
+		if !header {
+			record.insert(name.into(), std::string::String::from_utf8_lossy(value).trim().into());
+		}
+	}
+
+	Ok(records)
+}
+
+/// QSO of an existing log
+#[derive(Debug)]
+struct Entry {
+	/// Start time
+	start: u64,
+	/// Operator and station call signs, if given
+	operators: Vec<Call>,
+}
+
+/// QSOs of existing logs by call sign and band
+#[derive(Debug, Default)]
+pub struct Logged {
+	entries: HashMap<(Call, String), Vec<Entry>>,
+	/// Largest difference of start times of the same QSO, in seconds
+	tolerance: u64,
+}
+
+impl Logged {
+	/// Consider QSOs starting at most the given number of seconds apart to be the same
+	pub fn new(tolerance: u64) -> Self {
+		Logged { entries: HashMap::new(), tolerance }
+	}
+
+	/// Add the QSOs of an ADIF log, which may be compressed
+	///
+	/// Records are identified by the CALL, BAND or FREQ, QSO_DATE and TIME_ON fields, and skipped if
+	/// any of them is missing. If given, OPERATOR or STATION_CALLSIGN has to match as well.
+	pub fn read(&mut self, path: &Path) -> io::Result<()> {
+		let mut text = Vec::new();
+		fs::File::open(path)
+			.and_then(|file| input::decompress(io::BufReader::new(file)))
+			.and_then(|mut reader| reader.read_to_end(&mut text))
+			.map_err(|err| io::Error::new(err.kind(), format!("{}: {}", path.display(), err)))?;
+		let records = records(&text)
+			.map_err(|err| io::Error::new(err.kind(), format!("{}: {}", path.display(), err)))?;
+
+		let mut num_skipped = 0;
+		for record in &records {
+			let band = match (record.get("BAND"), record.get("FREQ")) {
+				(Some(band), _) => Some(band.to_lowercase().into()),
+				(None, Some(freq)) => freq.parse().ok().and_then(|mhz| band(Frequency::from_mhz(mhz))),
+				(None, None) => None,
+			};
+
+			let date = record.get("QSO_DATE").and_then(|date| NaiveDate::parse_from_str(date, "%Y%m%d").ok());
+			let time = record.get("TIME_ON").and_then(|time| match time.len() {
+				4 => NaiveTime::parse_from_str(time, "%H%M").ok(),
+				_ => NaiveTime::parse_from_str(time, "%H%M%S").ok(),
+			});
+
+			let (Some(call), Some(band), Some(date), Some(time)) = (record.get("CALL"), band, date, time) else {
+				num_skipped += 1;
+				continue;
+			};
+
+			let Ok(start) = u64::try_from(date.and_time(time).and_utc().timestamp()) else {
+				num_skipped += 1;
+				continue;
+			};
+
+			let operators = ["OPERATOR", "STATION_CALLSIGN"].iter()
+				.filter_map(|name| record.get(*name))
+				.map(|call| Call::new(call.clone()))
+				.collect();
+
+			self.entries.entry((Call::new(call.clone()), band)).or_default().push(Entry { start, operators });
+		}
+
+		debug!("Read {} QSOs from {}, skipping {num_skipped} incomplete records", records.len() - num_skipped, path.display());
+		Ok(())
+	}
+
+	/// Whether a QSO of the same operator with the same contact on the same band starting about the
+	/// same time was logged
+	pub fn contains(&self, qso: &Qso) -> bool {
+		let Some(band) = band(qso.freq_op) else {
+			return false;
+		};
+
+		self.entries.get(&(qso.call_ct.clone(), band))
+			.is_some_and(|entries| entries.iter().any(|entry| {
+				entry.start.abs_diff(qso.time_first) <= self.tolerance &&
+				(entry.operators.is_empty() || entry.operators.contains(&qso.call_op))
+			}))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::Spot;
+
+	#[test]
+	fn sanitizes_field_data() {
+		assert!(matches!(sanitize("Frank, 5 W", false), Cow::Borrowed(_)));
+		assert_eq!(sanitize("<b>Jürgen</b>\nŁódź – 73", false), "(b)Jurgen(/b) Lodz - 73");
+		assert_eq!(sanitize("Jürgen 日本", false), "Jurgen ??");
+		assert_eq!(sanitize("Jürgen <日本>\t", true), "Jürgen (日本) ");
+	}
+
+	#[test]
+	fn reads_field_lengths() {
+		assert_eq!(value_len("Jürgen<EOR>".as_bytes(), 6), "Jürgen".len());
+		assert_eq!(value_len(b"J\xfcrgen<EOR>", 6), 6);
+		assert_eq!(value_len(b"DO5EU", 10), 5);
+
+		let records = records("<NAME:6>Jürgen <CALL:5>DO5EU <EOR>\n<NAME:5>Frank<CALL:5>K1ABC<EOR>".as_bytes()).unwrap();
+		let names: Vec<_> = records.iter().map(|record| (record["NAME"].as_str(), record["CALL"].as_str())).collect();
+		assert_eq!(names, [("Jürgen", "DO5EU"), ("Frank", "K1ABC")]);
+	}
+
+	#[test]
+	fn splits_long_locators() {
+		assert_eq!(split_grid("JO62qm", 12), ("JO62qm", None));
+		assert_eq!(split_grid("JO62qm54", 12), ("JO62qm54", None));
+		assert_eq!(split_grid("JO62qm54ab12", 12), ("JO62qm54", Some("ab12")));
+		assert_eq!(split_grid("JO62qm54ab12", 10), ("JO62qm54", Some("ab")));
+		assert_eq!(split_grid("JO62qm54ab12", 4), ("JO62", None));
+	}
+
+	#[test]
+	fn names_bands_as_in_adif() {
+		assert_eq!(band(Frequency::from_mhz(14.0971)).as_deref(), Some("20m"));
+		assert_eq!(band(Frequency::from_mhz(0.1375)).as_deref(), Some("2190m"));
+		assert_eq!(band(Frequency::from_mhz(0.4756)).as_deref(), Some("630m"));
+		assert_eq!(band(Frequency::from_mhz(432.3)).as_deref(), Some("70cm"));
+		assert_eq!(band(Frequency::from_mhz(0.0135)), None);
+		assert_eq!(band(Frequency::from_mhz(0.1775)), None);
+		assert_eq!(band(Frequency::from_mhz(15.0)), None);
+	}
+
+	/// QSO of the operator with the contact on the given frequency, starting at the given time
+	fn qso(call_op: &str, call_ct: &str, mhz: f64, timestamp: u64) -> Qso {
+		let spot = |call_rx: &str, call_tx: &str| -> Spot {
+			format!("1,{timestamp},{call_rx},JO62,-10,{mhz:.6},{call_tx},FN42,37,0,6000").parse().unwrap()
+		};
+
+		Qso::new(&spot(call_op, call_ct), &spot(call_ct, call_op))
+	}
+
+	#[test]
+	fn finds_logged_qsos() {
+		let dir = tempfile::tempdir().unwrap();
+		let path = dir.path().join("wspr.adi");
+		fs::write(&path, "<ADIF_VER:5>3.1.4 <CALL:4>TEST <EOH>\n\
+			<CALL:6>DL1ABC<BAND:3>20M<QSO_DATE:8>20210112<TIME_ON:4>2120<OPERATOR:5>K1ABC<EOR>\n\
+			<CALL:6>DL2XYZ<FREQ:7>7.04002<QSO_DATE:8>20210112<TIME_ON:6>212000<EOR>\n\
+			<CALL:6>DL3AAA<BAND:3>20m<TIME_ON:4>2120<EOR>\n").unwrap();
+
+		let mut logged = Logged::new(120);
+		logged.read(&path).unwrap();
+
+		assert!(logged.contains(&qso("K1ABC", "DL1ABC", 14.0971, 1610486400 + 120)));
+		assert!(!logged.contains(&qso("K1ABC", "DL1ABC", 14.0971, 1610486400 + 240)));
+		assert!(!logged.contains(&qso("K1ABC", "DL1ABC", 7.0400, 1610486400)));
+		assert!(!logged.contains(&qso("K2ABC", "DL1ABC", 14.0971, 1610486400)));
+		assert!(logged.contains(&qso("K2ABC", "DL2XYZ", 7.0400, 1610486400)));
+		assert!(!logged.contains(&qso("K1ABC", "DL3AAA", 14.0971, 1610486400)));
+		assert!(!logged.contains(&qso("K1ABC", "TEST", 14.0971, 1610486400)));
+	}
+}
@@ -0,0 +1,141 @@
+use std::borrow::Cow;
+use std::fs;
+use std::io;
+use std::ops::RangeInclusive;
+use std::sync::OnceLock;
+
+use toml::{Table, Value};
+
+use crate::{Band, Frequency};
+use crate::cli::parse_band;
+
+/// Frequency ranges of bands, the first containing a frequency taking precedence
+pub type BandPlan = Vec<(RangeInclusive<Frequency>, Band)>;
+
+/// Band plan given by the user, taking precedence over the built‐in bands
+static PLAN: OnceLock<BandPlan> = OnceLock::new();
+
+/// Width in Hz of the frequency buckets taken as bands for frequencies outside any band, if any
+static BUCKET_WIDTH: OnceLock<u64> = OnceLock::new();
+
+/// Frequency in MHz of a band, given as a float or integer
+fn frequency(band: &Table, key: &str) -> Result<Frequency, std::string::String> {
+	match band.get(key) {
+		Some(Value::Float(mhz)) if *mhz >= 0.0 => Ok(Frequency::from_mhz(*mhz)),
+		Some(Value::Integer(mhz)) if *mhz >= 0 => Ok(Frequency::from_mhz(*mhz as f64)),
+		_ => Err(format!("expected {key} frequency in MHz")),
+	}
+}
+
+/// Parse a band of a band plan
+fn parse(band: &Value) -> Result<(RangeInclusive<Frequency>, Band), std::string::String> {
+	let Some(band) = band.as_table() else {
+		return Err("expected [[band]] tables".into());
+	};
+
+	let Some(name) = band.get("name").and_then(Value::as_str) else {
+		return Err("expected band name".into());
+	};
+
+	let (lower, upper) = (frequency(band, "lower")?, frequency(band, "upper")?);
+	if lower > upper {
+		return Err(format!("lower frequency of {name} above upper frequency"));
+	}
+
+	Ok((lower..=upper, parse_band(name)?))
+}
+
+/// Read a band plan from a TOML file with a `[[band]]` table per band, giving its name and
+/// lower and upper frequency in MHz
+pub fn read(path: &str) -> Result<BandPlan, std::string::String> {
+	let text = fs::read_to_string(path).map_err(|err: io::Error| format!("{path}: {err}"))?;
+	let table: Table = text.parse().map_err(|err: toml::de::Error| format!("{path}: {}", err.message()))?;
+
+	match table.get("band") {
+		Some(Value::Array(bands)) => bands.iter()
+			.map(parse)
+			.collect::<Result<_, _>>()
+			.map_err(|err| format!("{path}: {err}")),
+		_ => Err(format!("{path}: expected [[band]] tables")),
+	}
+}
+
+/// Use a band plan in addition to the built‐in bands for the rest of the run
+pub fn set(plan: BandPlan) {
+	let _ = PLAN.set(plan);
+}
+
+/// Take frequencies outside any band to be on a band of their own for every given number of Hz
+pub fn set_buckets(width: u64) {
+	let _ = BUCKET_WIDTH.set(width);
+}
+
+/// Frequency bucket of the given width in Hz as a band named after its lower edge in kHz
+fn bucket_of(freq: Frequency, width: u64) -> Band {
+	let lower = freq.0 / width * width;
+	Band(Cow::Owned((lower as f64 / 1e3).to_string()), "kHz")
+}
+
+/// Frequency bucket of a frequency outside any band as a band named after its lower edge in kHz,
+/// if enabled
+pub fn bucket(freq: Frequency) -> Option<Band> {
+	BUCKET_WIDTH.get().map(|&width| bucket_of(freq, width))
+}
+
+/// Band of a frequency according to a band plan
+fn find(plan: &BandPlan, freq: Frequency) -> Option<Band> {
+	plan.iter()
+		.find(|(range, _)| range.contains(&freq))
+		.map(|(_, band)| band.clone())
+}
+
+/// Band of a frequency according to the band plan given by the user, if any
+pub fn lookup(freq: Frequency) -> Option<Band> {
+	find(PLAN.get()?, freq)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn plan(text: &str) -> Result<BandPlan, std::string::String> {
+		let dir = tempfile::tempdir().unwrap();
+		let path = dir.path().join("bands.toml");
+		fs::write(&path, text).unwrap();
+		read(path.to_str().unwrap()).map_err(|err| err.rsplit(": ").next().unwrap().to_owned())
+	}
+
+	#[test]
+	fn reads_band_plans() {
+		let plan = plan("[[band]]\nname = \"22000m\"\nlower = 0.0136\nupper = 0.0137\n\n\
+			[[band]]\nname = \"60m\"\nlower = 5.25\nupper = 5.45\n").unwrap();
+		assert_eq!(find(&plan, Frequency::from_mhz(0.01365)), Some(Band::new("22000", "m")));
+		assert_eq!(find(&plan, Frequency::from_mhz(5.45)), Some(Band::new("60", "m")));
+		assert_eq!(find(&plan, Frequency::from_mhz(5.4501)), None);
+	}
+
+	#[test]
+	fn takes_first_band() {
+		let plan = plan("[[band]]\nname = \"60m\"\nlower = 5.3\nupper = 5.4\n\n\
+			[[band]]\nname = \"58m\"\nlower = 5.0\nupper = 5.5\n").unwrap();
+		assert_eq!(find(&plan, Frequency::from_mhz(5.3665)), Some(Band::new("60", "m")));
+		assert_eq!(find(&plan, Frequency::from_mhz(5.1)), Some(Band::new("58", "m")));
+	}
+
+	#[test]
+	fn rejects_invalid_bands() {
+		assert_eq!(plan("band = 1").unwrap_err(), "expected [[band]] tables");
+		assert_eq!(plan("[[band]]\nlower = 1\nupper = 2").unwrap_err(), "expected band name");
+		assert!(plan("[[band]]\nname = \"Top\"\nlower = 1\nupper = 2").unwrap_err().starts_with("invalid band top"));
+		assert_eq!(plan("[[band]]\nname = \"60m\"\nlower = 5.45").unwrap_err(), "expected upper frequency in MHz");
+		assert_eq!(plan("[[band]]\nname = \"60m\"\nlower = 5.45\nupper = 5.25").unwrap_err(), "lower frequency of 60m above upper frequency");
+	}
+
+	#[test]
+	fn names_buckets_after_lower_edge() {
+		assert_eq!(bucket_of(Frequency::from_mhz(5.3665), 100_000).name(), "5300kHz");
+		assert_eq!(bucket_of(Frequency::from_mhz(0.0772), 1_000).name(), "77kHz");
+		assert_eq!(bucket_of(Frequency::from_mhz(0.0137255), 500).name(), "13.5kHz");
+		assert_eq!(bucket_of(Frequency::from_mhz(0.0137255), 500), bucket_of(Frequency::from_mhz(0.013999), 500));
+	}
+}
@@ -0,0 +1,145 @@
+use std::io;
+use std::io::prelude::*;
+
+use clap::Args;
+use smartstring::alias::String;
+
+use crate::{Band, Call, Qso};
+use crate::template::Template;
+
+/// Contest details of Cabrillo logs
+#[derive(Args, Clone, Debug)]
+pub struct CabrilloOptions {
+	/// Name of the activity event in the CONTEST header of Cabrillo logs
+	#[arg(long, value_name = "NAME", default_value = "WSPR", env = "WSPRSPOTS_CONTEST")]
+	pub contest: String,
+
+	/// Additional header line of Cabrillo logs, such as "CATEGORY-POWER: QRP" (may be repeated)
+	#[arg(long, value_name = "LINE", value_parser = parse_header, env = "WSPRSPOTS_CABRILLO_HEADER")]
+	pub cabrillo_header: Vec<String>,
+
+	/// Template for the exchange sent, with the same placeholders as for the QSL message
+	#[arg(long, value_name = "TEMPLATE", default_value = "{snr} {my_grid}", env = "WSPRSPOTS_EXCHANGE_SENT")]
+	pub exchange_sent: Template,
+
+	/// Template for the exchange received, with the same placeholders as for the QSL message
+	#[arg(long, value_name = "TEMPLATE", default_value = "{my_snr} {grid}", env = "WSPRSPOTS_EXCHANGE_RCVD")]
+	pub exchange_rcvd: Template,
+}
+
+/// Parse a header line given as `TAG: VALUE`
+fn parse_header(line: &str) -> Result<String, std::string::String> {
+	match line.split_once(':') {
+		Some((tag, _)) if !tag.is_empty() && tag.chars().all(|ch| ch.is_ascii_uppercase() || ch == '-') => Ok(line.into()),
+		_ => Err("expected TAG: VALUE with the tag in upper case".into()),
+	}
+}
+
+/// Frequency of a QSO as given in Cabrillo logs
+///
+/// This is in kHz below 30 MHz and for bands without designator, otherwise the band designator.
+fn frequency(qso: &Qso) -> std::string::String {
+	let Ok(band) = Band::try_from(qso.freq_op) else {
+		return (qso.freq_op.0 / 1000).to_string();
+	};
+
+	let designator = match (&*band.0, band.1) {
+		("6", "m") => "50",
+		("4", "m") => "70",
+		("2", "m") => "144",
+		("1.25", "m") => "222",
+		("70", "cm") => "432",
+		("33", "cm") => "902",
+		("23", "cm") => "1.2G",
+		("13", "cm") => "2.3G",
+		("9", "cm") => "3.4G",
+		("6", "cm") => "5.7G",
+		("3", "cm") => "10G",
+		("1.25", "cm") => "24G",
+		("6", "mm") => "47G",
+		("4", "mm") => "75G",
+		("2.5", "mm") => "122G",
+		("2", "mm") => "134G",
+		("1", "mm") => "241G",
+		_ => return (qso.freq_op.0 / 1000).to_string(),
+	};
+
+	designator.to_owned()
+}
+
+/// Cabrillo 3.0 log writer
+///
+/// The header is written along with the first QSO, taking the call sign and locator from it unless
+/// the station call sign is given.
+pub struct Cabrillo<W: Write> {
+	out: W,
+	options: CabrilloOptions,
+	/// Call sign used over the air, if different from the operator
+	station_callsign: Option<Call>,
+	/// Operator name
+	name: Option<String>,
+	/// Whether the header was written
+	started: bool,
+}
+
+impl<W: Write> Cabrillo<W> {
+	/// Start a new log
+	pub fn new(out: W, options: CabrilloOptions, station_callsign: Option<Call>, name: Option<String>) -> Self {
+		Cabrillo { out, options, station_callsign, name, started: false }
+	}
+
+	/// Write the header, with the details of the given QSO if any
+	fn start(&mut self, qso: Option<&Qso>) -> io::Result<()> {
+		let pkg_name = env!("CARGO_PKG_NAME");
+		let pkg_version = env!("CARGO_PKG_VERSION");
+		writeln!(self.out, "START-OF-LOG: 3.0")?;
+		writeln!(self.out, "CREATED-BY: {pkg_name} {pkg_version}")?;
+		writeln!(self.out, "CONTEST: {}", self.options.contest)?;
+
+		if let Some(call) = self.station_callsign.as_ref().or(qso.map(|qso| &qso.call_op)) {
+			writeln!(self.out, "CALLSIGN: {call}")?;
+		}
+
+		writeln!(self.out, "CATEGORY-MODE: DIGI")?;
+
+		if let Some(qso) = qso.filter(|qso| !qso.grid_op.is_empty()) {
+			writeln!(self.out, "GRID-LOCATOR: {}", qso.grid_op)?;
+		}
+
+		if let Some(name) = &self.name {
+			writeln!(self.out, "NAME: {name}")?;
+		}
+
+		for line in &self.options.cabrillo_header {
+			writeln!(self.out, "{line}")?;
+		}
+
+		self.started = true;
+		Ok(())
+	}
+
+	/// Write a QSO line
+	pub fn write(&mut self, qso: &Qso) -> io::Result<()> {
+		if !self.started {
+			self.start(Some(qso))?;
+		}
+
+		writeln!(self.out, "QSO: {:>5} DG {} {:<13} {} {:<13} {}",
+		         frequency(qso),
+		         qso.datetime_on().format("%Y-%m-%d %H%M"),
+		         self.station_callsign.as_ref().unwrap_or(&qso.call_op),
+		         self.options.exchange_sent.render(qso),
+		         qso.call_ct,
+		         self.options.exchange_rcvd.render(qso))
+	}
+
+	/// Write the end of the log
+	pub fn finish(mut self) -> io::Result<W> {
+		if !self.started {
+			self.start(None)?;
+		}
+
+		writeln!(self.out, "END-OF-LOG:")?;
+		Ok(self.out)
+	}
+}
@@ -0,0 +1,534 @@
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::fs::File;
+use std::hash::Hash;
+use std::io;
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+
+use smartstring::alias::String;
+use tempfile::NamedTempFile;
+use unicase::Ascii;
+
+use crate::{Band, Frequency, Mode, Power, Qso, Spot, Tally};
+
+/// Leading bytes of checkpoint files, ending in the format version
+const MAGIC: &[u8; 8] = b"WSPRCKP\x0b";
+
+/// Binary encoding of state saved to a checkpoint
+pub trait Encode {
+	fn encode(&self, out: &mut dyn Write) -> io::Result<()>;
+}
+
+/// Binary decoding of state restored from a checkpoint
+pub trait Decode: Sized {
+	fn decode(input: &mut dyn Read) -> io::Result<Self>;
+}
+
+fn invalid(err: &str) -> io::Error {
+	io::Error::new(io::ErrorKind::InvalidData, format!("Corrupt checkpoint: {err}"))
+}
+
+/// Write a checkpoint, replacing the previous one only once complete
+pub fn save(path: &Path, write: impl FnOnce(&mut dyn Write) -> io::Result<()>) -> io::Result<()> {
+	let dir = match path.parent() {
+		Some(dir) if !dir.as_os_str().is_empty() => dir,
+		_ => Path::new("."),
+	};
+
+	let mut out = io::BufWriter::new(NamedTempFile::new_in(dir)?);
+	out.write_all(MAGIC)?;
+	write(&mut out)?;
+
+	let tmp = out.into_inner().map_err(io::IntoInnerError::into_error)?;
+	tmp.as_file().sync_all()?;
+	tmp.persist(path).map_err(|err| err.error)?;
+	Ok(())
+}
+
+/// Open a checkpoint for reading its state
+pub fn open(path: &Path) -> io::Result<io::BufReader<File>> {
+	let mut input = io::BufReader::new(File::open(path)?);
+
+	let mut magic = [0; MAGIC.len()];
+	input.read_exact(&mut magic)?;
+	if magic[..MAGIC.len() - 1] != MAGIC[..MAGIC.len() - 1] {
+		return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{} is not a checkpoint", path.display())));
+	}
+
+	if &magic != MAGIC {
+		return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{} was saved by another version", path.display())));
+	}
+
+	Ok(input)
+}
+
+/// Encode a sequence of the given length
+pub fn encode_seq<'a, T: Encode + 'a>(len: usize, items: impl Iterator<Item = &'a T>, out: &mut dyn Write) -> io::Result<()> {
+	len.encode(out)?;
+	items.into_iter().try_for_each(|item| item.encode(out))
+}
+
+/// Decode a sequence into any collection
+pub fn decode_seq<T: Decode, C: FromIterator<T>>(input: &mut dyn Read) -> io::Result<C> {
+	let len = usize::decode(input)?;
+	(0..len).map(|_| T::decode(input)).collect()
+}
+
+macro_rules! int {
+	($($ty:ty),*) => {$(
+		impl Encode for $ty {
+			fn encode(&self, out: &mut dyn Write) -> io::Result<()> {
+				out.write_all(&self.to_le_bytes())
+			}
+		}
+
+		impl Decode for $ty {
+			fn decode(input: &mut dyn Read) -> io::Result<Self> {
+				let mut buf = [0; size_of::<$ty>()];
+				input.read_exact(&mut buf)?;
+				Ok(<$ty>::from_le_bytes(buf))
+			}
+		}
+	)*};
+}
+
+int!(u8, i8, u16, i64, u64);
+
+impl Encode for bool {
+	fn encode(&self, out: &mut dyn Write) -> io::Result<()> {
+		u8::from(*self).encode(out)
+	}
+}
+
+impl Decode for bool {
+	fn decode(input: &mut dyn Read) -> io::Result<Self> {
+		match u8::decode(input)? {
+			0 => Ok(false),
+			1 => Ok(true),
+			_ => Err(invalid("invalid boolean")),
+		}
+	}
+}
+
+impl Encode for usize {
+	fn encode(&self, out: &mut dyn Write) -> io::Result<()> {
+		(*self as u64).encode(out)
+	}
+}
+
+impl Decode for usize {
+	fn decode(input: &mut dyn Read) -> io::Result<Self> {
+		usize::try_from(u64::decode(input)?).map_err(|_| invalid("length out of range"))
+	}
+}
+
+impl Encode for String {
+	fn encode(&self, out: &mut dyn Write) -> io::Result<()> {
+		self.len().encode(out)?;
+		out.write_all(self.as_bytes())
+	}
+}
+
+impl Decode for String {
+	fn decode(input: &mut dyn Read) -> io::Result<Self> {
+		let len = usize::decode(input)? as u64;
+		let mut buf = Vec::new();
+		if input.take(len).read_to_end(&mut buf)? as u64 != len {
+			return Err(io::ErrorKind::UnexpectedEof.into());
+		}
+
+		std::string::String::from_utf8(buf).map(String::from).map_err(|_| invalid("invalid UTF-8"))
+	}
+}
+
+impl Encode for PathBuf {
+	/// Paths are saved as UTF‐8, failing for others
+	fn encode(&self, out: &mut dyn Write) -> io::Result<()> {
+		match self.to_str() {
+			Some(path) => String::from(path).encode(out),
+			None => Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Path {} is not valid UTF-8", self.display()))),
+		}
+	}
+}
+
+impl Decode for PathBuf {
+	fn decode(input: &mut dyn Read) -> io::Result<Self> {
+		String::decode(input).map(|path| PathBuf::from(path.as_str()))
+	}
+}
+
+impl Encode for Ascii<String> {
+	fn encode(&self, out: &mut dyn Write) -> io::Result<()> {
+		(**self).encode(out)
+	}
+}
+
+impl Decode for Ascii<String> {
+	fn decode(input: &mut dyn Read) -> io::Result<Self> {
+		String::decode(input).map(Ascii::new)
+	}
+}
+
+impl<T: Encode + ?Sized> Encode for &T {
+	fn encode(&self, out: &mut dyn Write) -> io::Result<()> {
+		(**self).encode(out)
+	}
+}
+
+impl<T: Encode> Encode for Option<T> {
+	fn encode(&self, out: &mut dyn Write) -> io::Result<()> {
+		match self {
+			Some(value) => {
+				1u8.encode(out)?;
+				value.encode(out)
+			},
+			None => 0u8.encode(out),
+		}
+	}
+}
+
+impl<T: Decode> Decode for Option<T> {
+	fn decode(input: &mut dyn Read) -> io::Result<Self> {
+		match u8::decode(input)? {
+			0 => Ok(None),
+			1 => T::decode(input).map(Some),
+			_ => Err(invalid("invalid option")),
+		}
+	}
+}
+
+impl<A: Encode, B: Encode> Encode for (A, B) {
+	fn encode(&self, out: &mut dyn Write) -> io::Result<()> {
+		self.0.encode(out)?;
+		self.1.encode(out)
+	}
+}
+
+impl<A: Decode, B: Decode> Decode for (A, B) {
+	fn decode(input: &mut dyn Read) -> io::Result<Self> {
+		Ok((A::decode(input)?, B::decode(input)?))
+	}
+}
+
+impl<T: Encode> Encode for Box<[T]> {
+	fn encode(&self, out: &mut dyn Write) -> io::Result<()> {
+		encode_seq(self.len(), self.iter(), out)
+	}
+}
+
+impl<T: Decode> Decode for Box<[T]> {
+	fn decode(input: &mut dyn Read) -> io::Result<Self> {
+		decode_seq(input)
+	}
+}
+
+impl<T: Encode> Encode for VecDeque<T> {
+	fn encode(&self, out: &mut dyn Write) -> io::Result<()> {
+		encode_seq(self.len(), self.iter(), out)
+	}
+}
+
+impl<T: Decode> Decode for VecDeque<T> {
+	fn decode(input: &mut dyn Read) -> io::Result<Self> {
+		decode_seq(input)
+	}
+}
+
+impl<T: Encode> Encode for BTreeSet<T> {
+	fn encode(&self, out: &mut dyn Write) -> io::Result<()> {
+		encode_seq(self.len(), self.iter(), out)
+	}
+}
+
+impl<T: Decode + Ord> Decode for BTreeSet<T> {
+	fn decode(input: &mut dyn Read) -> io::Result<Self> {
+		decode_seq(input)
+	}
+}
+
+impl<T: Encode> Encode for HashSet<T> {
+	fn encode(&self, out: &mut dyn Write) -> io::Result<()> {
+		encode_seq(self.len(), self.iter(), out)
+	}
+}
+
+impl<T: Decode + Eq + Hash> Decode for HashSet<T> {
+	fn decode(input: &mut dyn Read) -> io::Result<Self> {
+		decode_seq(input)
+	}
+}
+
+impl<K: Encode, V: Encode> Encode for HashMap<K, V> {
+	fn encode(&self, out: &mut dyn Write) -> io::Result<()> {
+		self.len().encode(out)?;
+		self.iter().try_for_each(|(key, value)| {
+			key.encode(out)?;
+			value.encode(out)
+		})
+	}
+}
+
+impl<K: Decode + Eq + Hash, V: Decode> Decode for HashMap<K, V> {
+	fn decode(input: &mut dyn Read) -> io::Result<Self> {
+		decode_seq(input)
+	}
+}
+
+impl Encode for Frequency {
+	fn encode(&self, out: &mut dyn Write) -> io::Result<()> {
+		self.0.encode(out)
+	}
+}
+
+impl Decode for Frequency {
+	fn decode(input: &mut dyn Read) -> io::Result<Self> {
+		u64::decode(input).map(Frequency)
+	}
+}
+
+impl Encode for Power {
+	fn encode(&self, out: &mut dyn Write) -> io::Result<()> {
+		self.0.encode(out)
+	}
+}
+
+impl Decode for Power {
+	fn decode(input: &mut dyn Read) -> io::Result<Self> {
+		i8::decode(input).map(Power)
+	}
+}
+
+impl Encode for Mode {
+	fn encode(&self, out: &mut dyn Write) -> io::Result<()> {
+		let idx = Mode::NAMES.iter().position(|&(_, mode)| mode == *self).unwrap_or_default();
+		(idx as u8).encode(out)
+	}
+}
+
+impl Decode for Mode {
+	fn decode(input: &mut dyn Read) -> io::Result<Self> {
+		Mode::NAMES.get(usize::from(u8::decode(input)?))
+			.map(|&(_, mode)| mode)
+			.ok_or_else(|| invalid("unknown mode"))
+	}
+}
+
+impl Encode for Spot {
+	fn encode(&self, out: &mut dyn Write) -> io::Result<()> {
+		self.id.encode(out)?;
+		self.timestamp.encode(out)?;
+		self.call_rx.encode(out)?;
+		self.grid_rx.encode(out)?;
+		self.snr.encode(out)?;
+		self.frequency.encode(out)?;
+		self.call_tx.encode(out)?;
+		self.grid_tx.encode(out)?;
+		self.power.encode(out)?;
+		self.drift.encode(out)?;
+		self.distance.encode(out)?;
+		self.mode.encode(out)?;
+		self.extra.encode(out)
+	}
+}
+
+impl Decode for Spot {
+	fn decode(input: &mut dyn Read) -> io::Result<Self> {
+		Ok(Spot {
+			id: Decode::decode(input)?,
+			timestamp: Decode::decode(input)?,
+			call_rx: Decode::decode(input)?,
+			grid_rx: Decode::decode(input)?,
+			snr: Decode::decode(input)?,
+			frequency: Decode::decode(input)?,
+			call_tx: Decode::decode(input)?,
+			grid_tx: Decode::decode(input)?,
+			power: Decode::decode(input)?,
+			drift: Decode::decode(input)?,
+			distance: Decode::decode(input)?,
+			mode: Decode::decode(input)?,
+			extra: Decode::decode(input)?,
+		})
+	}
+}
+
+impl Encode for Qso {
+	fn encode(&self, out: &mut dyn Write) -> io::Result<()> {
+		self.call_op.encode(out)?;
+		self.call_ct.encode(out)?;
+		self.grid_op.encode(out)?;
+		self.grid_ct.encode(out)?;
+		self.time_first.encode(out)?;
+		self.time_last.encode(out)?;
+		self.snr_op.encode(out)?;
+		self.snr_ct.encode(out)?;
+		self.snr_op_min.encode(out)?;
+		self.snr_ct_min.encode(out)?;
+		self.snr_op_sum.encode(out)?;
+		self.snr_ct_sum.encode(out)?;
+		self.power_op.encode(out)?;
+		self.power_ct.encode(out)?;
+		self.freq_op.encode(out)?;
+		self.freq_ct.encode(out)?;
+		self.drift_op.encode(out)?;
+		self.drift_ct.encode(out)?;
+		self.distance.encode(out)?;
+		self.spots.encode(out)?;
+		self.num_rx.encode(out)?;
+		self.num_tx.encode(out)?;
+		self.mode.encode(out)?;
+		self.synchronous.encode(out)?;
+		self.extra_op.encode(out)?;
+		self.extra_ct.encode(out)
+	}
+}
+
+impl Decode for Qso {
+	fn decode(input: &mut dyn Read) -> io::Result<Self> {
+		Ok(Qso {
+			call_op: Decode::decode(input)?,
+			call_ct: Decode::decode(input)?,
+			grid_op: Decode::decode(input)?,
+			grid_ct: Decode::decode(input)?,
+			time_first: Decode::decode(input)?,
+			time_last: Decode::decode(input)?,
+			snr_op: Decode::decode(input)?,
+			snr_ct: Decode::decode(input)?,
+			snr_op_min: Decode::decode(input)?,
+			snr_ct_min: Decode::decode(input)?,
+			snr_op_sum: Decode::decode(input)?,
+			snr_ct_sum: Decode::decode(input)?,
+			power_op: Decode::decode(input)?,
+			power_ct: Decode::decode(input)?,
+			freq_op: Decode::decode(input)?,
+			freq_ct: Decode::decode(input)?,
+			drift_op: Decode::decode(input)?,
+			drift_ct: Decode::decode(input)?,
+			distance: Decode::decode(input)?,
+			spots: Decode::decode(input)?,
+			num_rx: Decode::decode(input)?,
+			num_tx: Decode::decode(input)?,
+			mode: Decode::decode(input)?,
+			synchronous: Decode::decode(input)?,
+			extra_op: Decode::decode(input)?,
+			extra_ct: Decode::decode(input)?,
+			// Open QSOs are not logged yet
+			serial: 0,
+		})
+	}
+}
+
+impl Encode for Tally {
+	/// Bands are saved by their frequency, from which they are determined again
+	fn encode(&self, out: &mut dyn Write) -> io::Result<()> {
+		self.contacts.encode(out)?;
+		encode_seq(self.bands.len(), self.bands.values(), out)?;
+		self.num_qsos.encode(out)?;
+		self.repeats.encode(out)
+	}
+}
+
+impl Decode for Tally {
+	fn decode(input: &mut dyn Read) -> io::Result<Self> {
+		let contacts = Decode::decode(input)?;
+		let bands = decode_seq::<Frequency, Vec<_>>(input)?
+			.into_iter()
+			.map(|freq| Ok((Band::try_from(freq)?, freq)))
+			.collect::<io::Result<_>>()?;
+
+		Ok(Tally { contacts, bands, num_qsos: Decode::decode(input)?, repeats: Decode::decode(input)? })
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::fs;
+
+	use super::*;
+	use crate::Call;
+
+	fn round_trip<T: Encode + Decode>(value: &T) -> T {
+		let mut buf = Vec::new();
+		value.encode(&mut buf).unwrap();
+
+		let mut input = buf.as_slice();
+		let decoded = T::decode(&mut input).unwrap();
+		assert!(input.is_empty());
+		decoded
+	}
+
+	fn spot(id: u64, call_rx: &str, call_tx: &str) -> Spot {
+		format!("{id},1610486400,{call_rx},JO62qm,-29,7.040022,{call_tx},IB59ui,27,0,13805,189,7,2.2.2,0,FST4W-120,a,b").parse().unwrap()
+	}
+
+	#[test]
+	fn round_trips_values() {
+		assert_eq!(round_trip(&-29i8), -29);
+		assert_eq!(round_trip(&u64::MAX), u64::MAX);
+		assert!(round_trip(&true));
+		assert_eq!(round_trip(&String::from("Jürgen")), "Jürgen");
+		assert_eq!(round_trip(&Some((1u16, String::from("DO5EU")))), Some((1, String::from("DO5EU"))));
+		assert_eq!(round_trip(&None::<u8>), None);
+		assert_eq!(round_trip(&PathBuf::from("logs/wspr.adi")), Path::new("logs/wspr.adi"));
+		assert_eq!(round_trip(&Mode::Fst4w900), Mode::Fst4w900);
+		assert_eq!(round_trip(&Power(-1)), Power(-1));
+	}
+
+	#[test]
+	fn round_trips_collections() {
+		let calls: HashSet<Call> = ["DO5EU", "dp0gvn"].into_iter().map(|call| Call::new(call.into())).collect();
+		assert_eq!(round_trip(&calls), calls);
+
+		let counts = HashMap::from([(1u64, 2u16), (3, 4)]);
+		assert_eq!(round_trip(&counts), counts);
+
+		let queue = VecDeque::from([3u8, 1, 2]);
+		assert_eq!(round_trip(&queue), queue);
+
+		let extra: Box<[String]> = Box::new([String::from("a"), String::new()]);
+		assert_eq!(round_trip(&extra), extra);
+	}
+
+	#[test]
+	fn round_trips_spots_and_qsos() {
+		let spot = spot(1, "DO5EU", "DP0GVN");
+		assert_eq!(format!("{:?}", round_trip(&spot)), format!("{spot:?}"));
+
+		let qso = Qso::new(&spot, &self::spot(2, "DP0GVN", "DO5EU"));
+		assert_eq!(format!("{:?}", round_trip(&qso)), format!("{qso:?}"));
+	}
+
+	#[test]
+	fn round_trips_tallies() {
+		let mut tally = Tally::default();
+		tally.bands.insert(Band::new("20", "m"), Frequency::from_mhz(14.0971));
+		tally.num_qsos = 3;
+
+		let tally = round_trip(&tally);
+		assert_eq!(tally.bands.keys().collect::<Vec<_>>(), [&Band::new("20", "m")]);
+		assert_eq!(tally.num_qsos, 3);
+	}
+
+	#[test]
+	fn rejects_invalid_data() {
+		assert_eq!(bool::decode(&mut [2u8].as_slice()).unwrap_err().kind(), io::ErrorKind::InvalidData);
+		assert_eq!(Mode::decode(&mut [99u8].as_slice()).unwrap_err().kind(), io::ErrorKind::InvalidData);
+		assert_eq!(String::decode(&mut [5u8, 0, 0, 0, 0, 0, 0, 0, b'a'].as_slice()).unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+	}
+
+	#[test]
+	fn saves_and_opens_checkpoints() {
+		let dir = tempfile::tempdir().unwrap();
+		let path = dir.path().join("wsprspots.ckp");
+
+		save(&path, |out| 42u64.encode(out)).unwrap();
+		assert_eq!(u64::decode(&mut open(&path).unwrap()).unwrap(), 42);
+
+		let mut other = fs::read(&path).unwrap();
+		other[MAGIC.len() - 1] ^= 1;
+		fs::write(&path, &other).unwrap();
+		assert!(open(&path).unwrap_err().to_string().ends_with("was saved by another version"));
+
+		fs::write(&path, b"<ADIF_VER:5>3.1.4").unwrap();
+		assert!(open(&path).unwrap_err().to_string().ends_with("is not a checkpoint"));
+	}
+}
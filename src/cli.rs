@@ -0,0 +1,748 @@
+use std::borrow::Cow;
+use std::convert::Infallible;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+use clap::{ArgAction, Args, Parser, Subcommand, ValueEnum};
+use log::LevelFilter;
+use regex::Regex;
+
+use crate::{Band, Call, Frequency, Grid, Mode};
+use crate::adif::RecordOptions;
+use crate::bandplan::{self, BandPlan};
+use crate::cabrillo::CabrilloOptions;
+#[cfg(feature = "fetch")]
+use crate::clublog::ClublogOptions;
+use crate::decodes::Decodes;
+use crate::diagnostics::LogFormat;
+#[cfg(feature = "fetch")]
+use crate::hrdlog::HrdlogOptions;
+use crate::input::{Input, InputFormat};
+use crate::locator;
+use crate::lotw::LotwOptions;
+#[cfg(feature = "fetch")]
+use crate::fetch::MonthRange;
+use crate::matcher::GridPolicy;
+use crate::output::{Format, Split, Target};
+use crate::schema::Schema;
+
+/// Create ADIF logs from WSPRnet spot database dumps
+///
+/// Without a subcommand, the arguments are interpreted as for `match`.
+#[derive(Parser, Debug)]
+#[command(version, about, subcommand_negates_reqs = true, args_conflicts_with_subcommands = true)]
+pub struct Cli {
+	#[command(subcommand)]
+	pub command: Option<Command>,
+
+	#[command(flatten)]
+	pub matching: MatchArgs,
+
+	#[command(flatten)]
+	pub verbosity: Verbosity,
+
+	/// TOML file of bands with a [[band]] table each, giving the name and lower and upper frequency
+	/// in MHz, which take precedence over the built‐in bands
+	#[arg(long, value_name = "FILE", value_parser = bandplan::read, global = true, env = "WSPRSPOTS_BAND_PLAN")]
+	pub band_plan: Option<BandPlan>,
+
+	/// Take spots outside any band to be on a band of their own for every this many kHz, named after
+	/// its lower edge, instead of skipping them
+	#[arg(long, value_name = "KHZ", value_parser = parse_bucket_width, global = true, env = "WSPRSPOTS_FREQUENCY_BUCKETS")]
+	pub frequency_buckets: Option<u64>,
+
+	/// TOML file giving options by their long names, for those not given on the command line or in
+	/// the environment [default: ~/.config/wsprspots/config.toml, if it exists]
+	#[arg(long, value_name = "FILE", global = true, env = "WSPRSPOTS_CONFIG")]
+	pub config: Option<PathBuf>,
+}
+
+/// Diagnostic output on standard error
+#[derive(Args, Debug)]
+pub struct Verbosity {
+	/// Print more diagnostics (may be repeated)
+	#[arg(short, long, action = ArgAction::Count, global = true, env = "WSPRSPOTS_VERBOSE")]
+	verbose: u8,
+
+	/// Print only errors and the final summary
+	#[arg(short, long, global = true, conflicts_with = "verbose", env = "WSPRSPOTS_QUIET")]
+	quiet: bool,
+
+	/// Format of diagnostics, as text for reading or as JSON lines for other programs
+	#[arg(long, value_name = "FORMAT", default_value = "text", global = true, env = "WSPRSPOTS_LOG_FORMAT")]
+	pub log_format: LogFormat,
+}
+
+impl Verbosity {
+	/// Maximum level of log messages to print
+	pub fn level(&self) -> LevelFilter {
+		if self.quiet {
+			return LevelFilter::Error;
+		}
+
+		match self.verbose {
+			0 => LevelFilter::Warn,
+			1 => LevelFilter::Info,
+			2 => LevelFilter::Debug,
+			_ => LevelFilter::Trace,
+		}
+	}
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+	/// Identify QSOs from mutual spots and write an ADIF log
+	Match(MatchArgs),
+	/// Identify QSOs from mutual spots between all pairs of stations
+	Pairs(PairsArgs),
+	/// Print statistics about a spot database dump
+	Stats(StatsArgs),
+	/// Write the spots matching the given criteria in WSPRnet CSV format
+	Filter(FilterArgs),
+	/// Write the spots of any input format in WSPRnet CSV format or as Parquet
+	Convert(ConvertArgs),
+	/// Download monthly WSPRnet archives and write their contents to standard output
+	#[cfg(feature = "fetch")]
+	Fetch(FetchArgs),
+}
+
+/// Options for QSO matching
+#[derive(Args, Debug)]
+pub struct MatchArgs {
+	/// Operator call signs, separated by commas
+	#[arg(value_name = "CALL", required_unless_present = "call")]
+	pub calls: Option<std::string::String>,
+
+	/// Operator call signs, separated by commas, instead of the first argument
+	#[arg(short, long = "call", value_name = "CALL", env = "WSPRSPOTS_CALL")]
+	pub call: Option<Calls>,
+
+	#[command(flatten)]
+	pub options: MatchOptions,
+}
+
+impl MatchArgs {
+	/// Operator call signs and remaining options
+	///
+	/// If the call signs are given with `--call`, the first argument is an input file instead.
+	pub fn into_parts(self) -> (Vec<Call>, MatchOptions) {
+		let MatchArgs { calls, call, mut options } = self;
+
+		match (call, calls) {
+			(Some(Calls(calls)), Some(file)) => {
+				options.input.files.insert(0, file.into());
+				(calls, options)
+			},
+			(Some(Calls(calls)), None) => (calls, options),
+			// Presence is enforced by the argument parser
+			(None, calls) => (calls.unwrap_or_default().parse::<Calls>().unwrap().0, options),
+		}
+	}
+}
+
+/// Options for QSO matching between all pairs of stations
+#[derive(Args, Debug)]
+pub struct PairsArgs {
+	#[command(flatten)]
+	pub options: MatchOptions,
+}
+
+/// Options common to all matching modes
+#[derive(Args, Debug)]
+pub struct MatchOptions {
+	/// Look back window for reciprocal spots, in cycles or in minutes with an `m` suffix, which are
+	/// taken as two‐minute cycles and stretched for modes with longer cycles
+	#[arg(short, long, value_name = "CYCLES", default_value = "2", value_parser = parse_lookback, env = "WSPRSPOTS_LOOKBACK")]
+	pub lookback: u64,
+
+	/// Close QSOs after this many minutes without spots, stretched like the look back window
+	/// [default: look back window]
+	#[arg(long, value_name = "MINUTES", value_parser = clap::value_parser!(u64).range(1..), env = "WSPRSPOTS_QSO_GAP")]
+	pub qso_gap: Option<u64>,
+
+	/// Split QSOs lasting longer than this many minutes
+	#[arg(long, value_name = "MINUTES", value_parser = clap::value_parser!(u64).range(1..), env = "WSPRSPOTS_MAX_QSO_DURATION")]
+	pub max_qso_duration: Option<u64>,
+
+	/// Only pair spots in both directions on the same band
+	#[arg(long, env = "WSPRSPOTS_SAME_BAND_ONLY")]
+	pub same_band_only: bool,
+
+	/// Compare Maidenhead locators of reciprocal spots only up to this many characters
+	#[arg(long, value_name = "CHARS", value_parser = clap::value_parser!(u8).range(2..=8), env = "WSPRSPOTS_GRID_PRECISION")]
+	pub grid_precision: Option<u8>,
+
+	/// Close open QSOs of a station as soon as it reports from a different locator
+	#[arg(long, env = "WSPRSPOTS_ROVER")]
+	pub rover: bool,
+
+	/// Match compound call signs such as DL1ABC/P or EA8/DL1ABC by their base call sign
+	#[arg(long, env = "WSPRSPOTS_NORMALIZE_CALLS")]
+	pub normalize_calls: bool,
+
+	/// Keep the distance reported in the spots instead of computing it from the most precise
+	/// locators of operator and contact
+	#[arg(long, env = "WSPRSPOTS_REPORTED_DISTANCE")]
+	pub reported_distance: bool,
+
+	/// Matching of spots with a locator that is blank, None or malformed, unless the station was
+	/// spotted at a valid one within the look back window
+	#[arg(long, value_name = "POLICY", default_value = "skip", env = "WSPRSPOTS_INVALID_GRID")]
+	pub invalid_grid: GridPolicy,
+
+	/// Locator of a station substituted for invalid ones with --invalid-grid substitute, as in
+	/// EA8/DL1ABC:IL18 (may be repeated)
+	#[arg(long, value_name = "CALL:GRID", env = "WSPRSPOTS_STATION_GRID", value_delimiter = ',')]
+	pub station_grid: Vec<StationGrid>,
+
+	/// Do not log QSOs with this call sign (may be repeated)
+	#[arg(short = 'x', long, value_name = "CALL", env = "WSPRSPOTS_EXCLUDE")]
+	pub exclude: Vec<Call>,
+
+	/// Do not log QSOs with the call signs listed in this file, one per line
+	#[arg(long, value_name = "FILE", env = "WSPRSPOTS_EXCLUDE_FILE")]
+	pub exclude_file: Vec<PathBuf>,
+
+	/// Only log QSOs with this call sign (may be repeated)
+	#[arg(long, value_name = "CALL", env = "WSPRSPOTS_ONLY")]
+	pub only: Vec<Call>,
+
+	/// Only log QSOs with the call signs listed in this file, one per line
+	#[arg(long, value_name = "FILE", env = "WSPRSPOTS_ONLY_FILE")]
+	pub only_file: Vec<PathBuf>,
+
+	/// Only log QSOs with call signs matching this regular expression (may be repeated)
+	#[arg(long, value_name = "REGEX", env = "WSPRSPOTS_INCLUDE_REGEX")]
+	pub include_regex: Vec<Regex>,
+
+	/// Do not log QSOs with call signs matching this regular expression (may be repeated)
+	#[arg(long, value_name = "REGEX", env = "WSPRSPOTS_EXCLUDE_REGEX")]
+	pub exclude_regex: Vec<Regex>,
+
+	/// Apply the regular expressions to the reporter of every spot as well
+	#[arg(long, env = "WSPRSPOTS_REGEX_REPORTER")]
+	pub regex_reporter: bool,
+
+	/// Only log QSOs with contacts in Maidenhead locators starting with any of these, separated by commas
+	#[arg(long, value_name = "GRID", value_delimiter = ',', env = "WSPRSPOTS_GRID")]
+	pub grid: Vec<Grid>,
+
+	/// Only log QSOs made from Maidenhead locators starting with any of these, separated by commas
+	#[arg(long, value_name = "GRID", value_delimiter = ',', env = "WSPRSPOTS_MY_GRID")]
+	pub my_grid: Vec<Grid>,
+
+	/// Only log QSOs with at least this many spots in each direction
+	#[arg(long, value_name = "N", default_value_t = 1, env = "WSPRSPOTS_MIN_ROUNDS")]
+	pub min_rounds: usize,
+
+	/// Only log QSOs with reciprocal spots from the same or adjacent cycles at least once, as in an
+	/// interleaved exchange rather than between unattended beacons
+	#[arg(long, env = "WSPRSPOTS_SYNCHRONOUS")]
+	pub synchronous: bool,
+
+	/// Only log QSOs where both stations transmitted with at least this power in dBm, if reported
+	#[arg(long, value_name = "DBM", allow_negative_numbers = true, env = "WSPRSPOTS_MIN_POWER_DBM")]
+	pub min_power_dbm: Option<i8>,
+
+	/// Only log QSOs where both stations transmitted with at most this power in dBm, if reported
+	#[arg(long, value_name = "DBM", allow_negative_numbers = true, env = "WSPRSPOTS_MAX_POWER_DBM")]
+	pub max_power_dbm: Option<i8>,
+
+	/// Do not log QSOs already in this ADIF log with the same call sign and band (may be repeated)
+	#[arg(long, value_name = "FILE", env = "WSPRSPOTS_EXISTING_LOG")]
+	pub existing_log: Vec<PathBuf>,
+
+	/// Consider QSOs in existing logs the same if they start at most this many minutes apart
+	#[arg(long, value_name = "MINUTES", default_value_t = 10, env = "WSPRSPOTS_EXISTING_TOLERANCE")]
+	pub existing_tolerance: u64,
+
+	/// Skip repeat QSOs with a contact on a band within this many hours of the last one logged, on
+	/// the same UTC day with per-day, or at all with per-band-slot
+	#[arg(long, value_name = "HOURS|per-day|per-band-slot", env = "WSPRSPOTS_DEDUPE_CONTACT")]
+	pub dedupe_contact: Option<ContactWindow>,
+
+	#[command(flatten)]
+	pub spots: SpotFilterArgs,
+
+	/// Write the log to this file instead of standard output, compressed if named .gz or .zst, to
+	/// this directory when splitting, or to a SQLite database given as sqlite:FILE; may be given
+	/// several times, each prefixed by its format as in jsonl:qsos.jsonl
+	#[arg(short, long, value_name = "PATH", env = "WSPRSPOTS_OUTPUT")]
+	pub output: Vec<Target>,
+
+	/// Write at most this many records to each log file, continuing in files numbered from 1 as in
+	/// qsos-2.adi
+	#[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..), conflicts_with = "append",
+	      env = "WSPRSPOTS_MAX_RECORDS_PER_FILE")]
+	pub max_records_per_file: Option<u64>,
+
+	/// Append to existing log files instead of replacing them
+	#[arg(long, env = "WSPRSPOTS_APPEND")]
+	pub append: bool,
+
+	/// Write separate log files split by the given criterion
+	#[arg(short, long, value_name = "CRITERION", env = "WSPRSPOTS_SPLIT")]
+	pub split: Option<Split>,
+
+	/// Format of the logs
+	#[arg(long, value_name = "FORMAT", default_value = "adif", env = "WSPRSPOTS_FORMAT")]
+	pub format: Format,
+
+	/// Write the QSOs in order of their start time, holding them back until the end of the input
+	#[arg(long, env = "WSPRSPOTS_SORT")]
+	pub sort: bool,
+
+	/// Hold QSOs back for sorting only until QSOs ending this much later in the time of the spots,
+	/// as needed when following or polling
+	#[arg(long, value_name = "MINUTES", requires = "sort", env = "WSPRSPOTS_SORT_WINDOW")]
+	pub sort_window: Option<u64>,
+
+	/// Also send every QSO as an ADIF record over UDP to a logging program at this address as it ends
+	#[arg(long, value_name = "HOST:PORT", conflicts_with = "dry_run", env = "WSPRSPOTS_BROADCAST")]
+	pub broadcast: Option<std::string::String>,
+
+	/// Also announce every QSO as a DX cluster spot to telnet clients connecting to this address as
+	/// it ends
+	#[arg(long, value_name = "HOST:PORT", conflicts_with = "dry_run", env = "WSPRSPOTS_CLUSTER")]
+	pub cluster: Option<std::string::String>,
+
+	/// Also draw the QSOs on a map in KML format for Google Earth, written to this file once done
+	#[arg(long, value_name = "FILE", conflicts_with_all = ["dry_run", "checkpoint"], env = "WSPRSPOTS_KML")]
+	pub kml: Option<PathBuf>,
+
+	/// Also write the QSOs as a GeoJSON feature collection for web maps to this file once done
+	#[arg(long, value_name = "FILE", conflicts_with_all = ["dry_run", "checkpoint"], env = "WSPRSPOTS_GEOJSON")]
+	pub geojson: Option<PathBuf>,
+
+	/// Also write a report of the QSOs with statistics and a map as a single HTML file once done
+	#[arg(long, value_name = "FILE", conflicts_with_all = ["dry_run", "checkpoint"], env = "WSPRSPOTS_REPORT")]
+	pub report: Option<PathBuf>,
+
+	/// Also write a short summary of the QSOs in Markdown format to this file once done
+	#[arg(long, value_name = "FILE", conflicts_with_all = ["dry_run", "checkpoint"], env = "WSPRSPOTS_SUMMARY_MD")]
+	pub summary_md: Option<PathBuf>,
+
+	/// Also write the stations spotted repeatedly in one direction only, but never in the other, as
+	/// CSV to this file once done
+	#[arg(long, value_name = "FILE", conflicts_with = "dry_run", env = "WSPRSPOTS_ONE_WAY")]
+	pub one_way: Option<PathBuf>,
+
+	/// Minimum number of spots for a station to be included in the one‐way report
+	#[arg(long, value_name = "N", default_value = "2", env = "WSPRSPOTS_ONE_WAY_MIN_SPOTS")]
+	pub one_way_min_spots: usize,
+
+	#[command(flatten, next_help_heading = "LoTW upload")]
+	pub lotw: LotwOptions,
+
+	#[cfg(feature = "fetch")]
+	#[command(flatten, next_help_heading = "Club Log upload")]
+	pub clublog: ClublogOptions,
+
+	#[cfg(feature = "fetch")]
+	#[command(flatten, next_help_heading = "HRDLog.net upload")]
+	pub hrdlog: HrdlogOptions,
+
+	/// Keep reading the last input file as it grows, logging QSOs as they end
+	#[arg(short, long, conflicts_with = "until", env = "WSPRSPOTS_FOLLOW")]
+	pub follow: bool,
+
+	/// Match live decodes sent by WSJT-X to this UDP address as well, which requires --follow or --poll
+	#[arg(long, value_name = "ADDR", num_args = 0..=1, require_equals = true, default_missing_value = crate::wsjtx::DEFAULT_ADDR, env = "WSPRSPOTS_WSJTX")]
+	pub wsjtx: Option<std::string::String>,
+
+	/// Do not write any logs, only print how many QSOs would be logged
+	#[arg(short = 'n', long, env = "WSPRSPOTS_DRY_RUN")]
+	pub dry_run: bool,
+
+	/// Save the matching state to this file every minute, so that an interrupted run can be resumed
+	#[arg(long, value_name = "FILE", conflicts_with = "wsjtx", env = "WSPRSPOTS_CHECKPOINT")]
+	pub checkpoint: Option<PathBuf>,
+
+	/// Continue from the checkpoint if there is one, appending to the logs written so far. Rows
+	/// already read are skipped by count, so the inputs must be the same and in the same order
+	#[arg(long, requires = "checkpoint", env = "WSPRSPOTS_RESUME")]
+	pub resume: bool,
+
+	#[command(flatten)]
+	pub input: InputArgs,
+
+	/// Query the spots from wspr.live instead of reading files, which requires --since
+	#[cfg(feature = "fetch")]
+	#[arg(long, conflicts_with_all = ["files", "input", "all_wspr", "wsprd", "follow", "checkpoint"], env = "WSPRSPOTS_WSPR_LIVE")]
+	pub wspr_live: bool,
+
+	/// Keep running and query wspr.live for new spots at this interval
+	#[cfg(feature = "fetch")]
+	#[arg(long, value_name = "MINUTES", requires = "wspr_live", conflicts_with = "until",
+	      value_parser = clap::value_parser!(u64).range(5..), env = "WSPRSPOTS_POLL")]
+	pub poll: Option<u64>,
+
+	/// URL of the wspr.live database interface
+	#[cfg(feature = "fetch")]
+	#[arg(long, value_name = "URL", default_value = crate::wsprlive::DEFAULT_URL, env = "WSPRSPOTS_WSPR_LIVE_URL")]
+	pub wspr_live_url: std::string::String,
+
+	#[command(flatten)]
+	pub record: RecordOptions,
+
+	#[command(flatten, next_help_heading = "Cabrillo logs")]
+	pub cabrillo: CabrilloOptions,
+}
+
+/// Options for spot statistics
+#[derive(Args, Debug)]
+pub struct StatsArgs {
+	/// Only consider spots involving any of these call signs, separated by commas
+	#[arg(short, long, value_name = "CALL", env = "WSPRSPOTS_CALL")]
+	pub call: Option<Calls>,
+
+	#[command(flatten)]
+	pub spots: SpotFilterArgs,
+
+	#[command(flatten)]
+	pub input: InputArgs,
+}
+
+/// Options for spot filtering
+#[derive(Args, Debug)]
+pub struct FilterArgs {
+	/// Only pass spots involving any of these call signs, separated by commas
+	#[arg(short, long, value_name = "CALL", env = "WSPRSPOTS_CALL")]
+	pub call: Option<Calls>,
+
+	#[command(flatten)]
+	pub spots: SpotFilterArgs,
+
+	#[command(flatten)]
+	pub input: InputArgs,
+}
+
+/// Format of converted spots
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum SpotFormat {
+	/// Rows in WSPRnet CSV format, followed by the mode and any extra columns
+	#[default]
+	Csv,
+	/// Columns of spots without extra columns, read back with row groups outside the time window
+	/// skipped
+	#[cfg(feature = "parquet")]
+	Parquet,
+}
+
+/// Options for converting spots
+#[derive(Args, Debug)]
+pub struct ConvertArgs {
+	/// Format to write to standard output
+	#[arg(long, value_name = "FORMAT", default_value = "csv", env = "WSPRSPOTS_TO")]
+	pub to: SpotFormat,
+
+	#[command(flatten)]
+	pub input: InputArgs,
+}
+
+/// Options for downloading archives
+#[cfg(feature = "fetch")]
+#[derive(Args, Debug)]
+pub struct FetchArgs {
+	/// Month as YYYY-MM, or range of months as YYYY-MM..YYYY-MM
+	#[arg(value_name = "MONTHS")]
+	pub months: MonthRange,
+
+	/// Directory in which downloaded archives are kept [default: ~/.cache/wsprspots]
+	#[arg(long, value_name = "DIR", env = "WSPRSPOTS_CACHE_DIR")]
+	pub cache_dir: Option<PathBuf>,
+
+	/// URL of the directory containing the archives
+	#[arg(long, value_name = "URL", default_value = "https://wsprnet.org/archive", env = "WSPRSPOTS_BASE_URL")]
+	pub base_url: std::string::String,
+
+	/// Download archives again even if cached, as for the current month
+	#[arg(long, env = "WSPRSPOTS_REFRESH")]
+	pub refresh: bool,
+
+	/// Only download the archives and print their paths
+	#[arg(long, env = "WSPRSPOTS_DOWNLOAD_ONLY")]
+	pub download_only: bool,
+}
+
+/// Spot‐level criteria
+#[derive(Args, Debug, Default)]
+pub struct SpotFilterArgs {
+	/// Skip spots before this time (Unix timestamp or ISO 8601 date and time, UTC unless given)
+	#[arg(long, value_name = "TIME", value_parser = parse_time, env = "WSPRSPOTS_SINCE")]
+	pub since: Option<u64>,
+
+	/// Skip spots at or after this time (Unix timestamp or ISO 8601 date and time, UTC unless given)
+	#[arg(long, value_name = "TIME", value_parser = parse_time, env = "WSPRSPOTS_UNTIL")]
+	pub until: Option<u64>,
+
+	/// Only consider spots on these bands, such as 20m, 70cm or 13.5kHz, separated by commas
+	#[arg(long, value_name = "BAND", value_delimiter = ',', value_parser = parse_band, env = "WSPRSPOTS_BAND")]
+	pub band: Vec<Band>,
+
+	/// Skip spots with a signal‐to‐noise ratio below this value in dB
+	#[arg(long, value_name = "DB", allow_negative_numbers = true, env = "WSPRSPOTS_MIN_SNR")]
+	pub min_snr: Option<i8>,
+
+	/// Skip spots with a distance below this value in km
+	#[arg(long, value_name = "KM", env = "WSPRSPOTS_MIN_DISTANCE")]
+	pub min_distance: Option<u16>,
+
+	/// Skip spots with a distance above this value in km
+	#[arg(long, value_name = "KM", env = "WSPRSPOTS_MAX_DISTANCE")]
+	pub max_distance: Option<u16>,
+
+	/// Skip spots with an absolute frequency drift above this value in Hz / s
+	#[arg(long, value_name = "HZ", env = "WSPRSPOTS_MAX_DRIFT")]
+	pub max_drift: Option<u8>,
+
+	/// Only read spots in one hour out of every N, given as 1/N, for a quick preview
+	#[arg(long, value_name = "1/N", value_parser = parse_sample, env = "WSPRSPOTS_SAMPLE")]
+	pub sample: Option<u64>,
+}
+
+/// Spot input sources
+#[derive(Args, Debug)]
+pub struct InputArgs {
+	/// Spot database dumps in CSV format, merged by time (`-` or none for standard input)
+	#[arg(value_name = "FILE")]
+	pub files: Vec<PathBuf>,
+
+	/// Also read spots from this source, such as sqlite:FILE, mqtt://BROKER/TOPIC, unix:SOCKET or
+	/// pskreporter:CALL (may be repeated)
+	#[arg(long, value_name = "URI", env = "WSPRSPOTS_INPUT")]
+	pub input: Vec<Input>,
+
+	/// Query for spots in SQLite databases, with columns named as in a header row
+	#[cfg(feature = "sqlite")]
+	#[arg(long, value_name = "SQL", default_value = crate::sqlite::DEFAULT_QUERY, env = "WSPRSPOTS_QUERY")]
+	pub query: std::string::String,
+
+	/// Also read local decodes of WSJT-X from an ALL_WSPR.TXT file, received by the given station
+	/// (may be repeated)
+	#[arg(long, value_name = "CALL:GRID:FILE", env = "WSPRSPOTS_ALL_WSPR")]
+	pub all_wspr: Vec<Decodes>,
+
+	/// Also read local decodes printed by wsprd into a file, received by the given station (may be
+	/// repeated)
+	#[arg(long, value_name = "CALL:GRID:FILE", env = "WSPRSPOTS_WSPRD")]
+	pub wsprd: Vec<Decodes>,
+
+	/// Format of the spot files
+	#[arg(long, value_name = "FORMAT", default_value = "csv", env = "WSPRSPOTS_INPUT_FORMAT")]
+	pub input_format: InputFormat,
+
+	/// Comma‐separated column names of spot files without header, such as
+	/// id,timestamp,reporter,reporter_grid,snr,frequency,call,grid,power,drift,distance
+	#[arg(long, value_name = "NAMES", env = "WSPRSPOTS_COLUMNS")]
+	pub columns: Option<Schema>,
+
+	/// Field delimiter of spot files such as ';' or '\t', detected from the first row if not given
+	#[arg(long, value_name = "CHAR", value_parser = parse_delimiter, env = "WSPRSPOTS_DELIMITER")]
+	pub delimiter: Option<char>,
+
+	/// Mode of spots in rows without a mode column, such as archives of WSPRnet, optionally only on a
+	/// band as in 2200m:WSPR-15 (may be repeated)
+	#[arg(long, value_name = "[BAND:]MODE", env = "WSPRSPOTS_DEFAULT_MODE", value_delimiter = ',')]
+	pub default_mode: Vec<DefaultMode>,
+
+	/// Read the input files one after another instead of merging them by time
+	#[arg(long, env = "WSPRSPOTS_SEQUENTIAL")]
+	pub sequential: bool,
+
+	/// Abort on the first row that fails to parse
+	#[arg(long, env = "WSPRSPOTS_STRICT")]
+	pub strict: bool,
+
+	/// Abort once more than this many rows failed to parse
+	#[arg(long, value_name = "N", conflicts_with = "strict", env = "WSPRSPOTS_MAX_ERRORS")]
+	pub max_errors: Option<usize>,
+
+	/// Do not report progress while reading input files
+	#[arg(long, env = "WSPRSPOTS_NO_PROGRESS")]
+	pub no_progress: bool,
+}
+
+/// Comma‐separated list of call signs
+#[derive(Clone, Debug)]
+pub struct Calls(pub Vec<Call>);
+
+impl FromStr for Calls {
+	type Err = Infallible;
+
+	fn from_str(list: &str) -> Result<Self, Self::Err> {
+		Ok(Calls(list.split(',')
+			.map(str::trim)
+			.filter(|call| !call.is_empty())
+			.map(|call| Call::new(call.into()))
+			.collect()))
+	}
+}
+
+/// Parse a Unix timestamp or an ISO 8601 date with optional time of day
+fn parse_time(time: &str) -> Result<u64, String> {
+	let secs = if let Ok(secs) = time.parse::<i64>() {
+		secs
+	} else if let Ok(datetime) = DateTime::parse_from_rfc3339(time) {
+		datetime.timestamp()
+	} else if let Ok(datetime) = NaiveDateTime::parse_from_str(time, "%Y-%m-%dT%H:%M:%S")
+		.or_else(|_| NaiveDateTime::parse_from_str(time, "%Y-%m-%dT%H:%M"))
+		.or_else(|_| NaiveDateTime::parse_from_str(time, "%Y-%m-%d %H:%M:%S"))
+		.or_else(|_| NaiveDateTime::parse_from_str(time, "%Y-%m-%d %H:%M")) {
+		datetime.and_utc().timestamp()
+	} else if let Ok(date) = NaiveDate::parse_from_str(time, "%Y-%m-%d") {
+		date.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp()
+	} else {
+		return Err("expected Unix timestamp or ISO 8601 date and time".into());
+	};
+
+	u64::try_from(secs).map_err(|_| "time before Unix epoch".into())
+}
+
+/// Parse a sampling rate given as `1/N`
+fn parse_sample(rate: &str) -> Result<u64, String> {
+	rate.strip_prefix("1/")
+		.and_then(|num| num.parse().ok())
+		.filter(|&num| num > 0)
+		.ok_or_else(|| format!("invalid sampling rate {rate}, expected 1/N"))
+}
+
+/// Parse a band name such as `20m`, `2190m` as in ADIF, `70 cm`, or `13.5 kHz` for the segments
+/// and frequency buckets outside the ADIF bands
+pub fn parse_band(band: &str) -> Result<Band, String> {
+	let band = band.replace(' ', "").to_ascii_lowercase();
+	let num = band.trim_end_matches(char::is_alphabetic);
+	let unit = match &band[num.len()..] {
+		"m" => "m",
+		"cm" => "cm",
+		"mm" => "mm",
+		"khz" => "kHz",
+		_ => "",
+	};
+
+	if num.is_empty() || num.parse::<f64>().is_err() || unit.is_empty() {
+		return Err(format!("invalid band {band}, expected a wavelength such as 20m or a frequency such as 13.5kHz"));
+	}
+
+	Ok(match num {
+		// ADIF names 2200 m after the upper end of the band
+		"2190" if unit == "m" => Band::new("2200", "m"),
+		_ => Band(Cow::Owned(num.to_owned()), unit),
+	})
+}
+
+/// Mode of spots without a mode column, optionally only on a band
+#[derive(Clone, Debug)]
+pub struct DefaultMode {
+	/// Band such as 2200 m, or none for all bands
+	band: Option<Band>,
+	mode: Mode,
+}
+
+impl DefaultMode {
+	/// Mode of spots on a frequency given by the first choice that applies, if any
+	pub fn choose(choices: &[DefaultMode], frequency: Frequency) -> Option<Mode> {
+		let band = Band::try_from(frequency).ok();
+		choices.iter()
+			.find(|choice| choice.band.is_none() || choice.band == band)
+			.map(|choice| choice.mode)
+	}
+}
+
+impl FromStr for DefaultMode {
+	type Err = String;
+
+	fn from_str(choice: &str) -> Result<Self, Self::Err> {
+		let (band, mode) = match choice.split_once(':') {
+			Some((band, mode)) => (Some(parse_band(band)?), mode),
+			None => (None, choice),
+		};
+
+		let mode = mode.parse().map_err(|_| format!("unknown mode {mode}, expected one such as WSPR-15 or FST4W-300"))?;
+		Ok(DefaultMode { band, mode })
+	}
+}
+
+/// Locator given for a station
+#[derive(Clone, Debug)]
+pub struct StationGrid {
+	pub call: Call,
+	pub grid: Grid,
+}
+
+impl FromStr for StationGrid {
+	type Err = String;
+
+	fn from_str(station: &str) -> Result<Self, Self::Err> {
+		let Some((call, grid)) = station.split_once(':') else {
+			return Err(format!("invalid station locator {station}, expected CALL:GRID"));
+		};
+
+		if locator::position(grid).is_none() {
+			return Err(format!("invalid locator {grid}"));
+		}
+
+		Ok(StationGrid { call: Call::new(call.into()), grid: Grid::new(grid.into()) })
+	}
+}
+
+/// Window within which repeat QSOs with a contact on a band are skipped
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ContactWindow {
+	/// Within this many hours of the start of the last QSO logged
+	Hours(u64),
+	/// On the same UTC day as the last QSO logged
+	PerDay,
+	/// At all, logging only the first QSO
+	PerBandSlot,
+}
+
+impl FromStr for ContactWindow {
+	type Err = String;
+
+	fn from_str(window: &str) -> Result<Self, Self::Err> {
+		match window {
+			"per-day" => Ok(ContactWindow::PerDay),
+			"per-band-slot" => Ok(ContactWindow::PerBandSlot),
+			hours => match hours.strip_suffix('h').unwrap_or(hours).parse::<u64>() {
+				Ok(hours) if hours > 0 => Ok(ContactWindow::Hours(hours)),
+				_ => Err(format!("invalid window {window}, expected a number of hours, per-day or per-band-slot")),
+			},
+		}
+	}
+}
+
+/// Parse a field delimiter, with `\t` or `tab` standing for a tab
+fn parse_delimiter(delimiter: &str) -> Result<char, String> {
+	match delimiter {
+		"\\t" | "tab" => Ok('\t'),
+		delimiter => match delimiter.chars().collect::<Vec<_>>()[..] {
+			[ch] if ch != '"' && ch != '\n' && ch != '\r' => Ok(ch),
+			_ => Err(format!("invalid delimiter {delimiter}, expected a single character")),
+		},
+	}
+}
+
+/// Parse a width of frequency buckets in kHz as Hz
+fn parse_bucket_width(width: &str) -> Result<u64, String> {
+	let khz = width.parse::<f64>().map_err(|err| err.to_string())?;
+	match (khz * 1e3).round() {
+		hz if hz >= 1.0 && hz.is_finite() => Ok(hz as u64),
+		_ => Err("bucket width must be at least 1 Hz".into()),
+	}
+}
+
+/// Parse a number of two‐minute cycles, or of minutes if suffixed with `m` or `min`
+fn parse_lookback(lookback: &str) -> Result<u64, String> {
+	let cycles = if let Some(minutes) = lookback.strip_suffix("min").or_else(|| lookback.strip_suffix('m')) {
+		minutes.trim().parse::<u64>().map_err(|err| err.to_string())?.div_ceil(2)
+	} else {
+		lookback.parse::<u64>().map_err(|err| err.to_string())?
+	};
+
+	match cycles {
+		1..=720 => Ok(cycles),
+		_ => Err("look back window must be between one cycle and one day".into())
+	}
+}
@@ -0,0 +1,94 @@
+use std::io;
+use std::path::PathBuf;
+
+use clap::Args;
+use log::{debug, warn};
+
+use crate::Call;
+use crate::adif::RecordOptions;
+use crate::online::{Logbook, Outcome, Upload};
+
+/// Endpoint of the realtime API, taking one QSO at a time
+const REALTIME_URL: &str = "https://clublog.org/realtime.php";
+
+/// Uploading the QSOs to Club Log as they end
+#[derive(Args, Clone, Debug)]
+pub struct ClublogOptions {
+	/// Upload every QSO to Club Log as it ends, logging in with this email address
+	#[arg(long, value_name = "EMAIL", requires_all = ["clublog_password", "clublog_api_key"],
+	      conflicts_with = "dry_run", env = "WSPRSPOTS_CLUBLOG_EMAIL")]
+	pub clublog_email: Option<std::string::String>,
+
+	/// Application password of the Club Log account
+	#[arg(long, value_name = "PASSWORD", env = "WSPRSPOTS_CLUBLOG_PASSWORD", hide_env_values = true)]
+	pub clublog_password: Option<std::string::String>,
+
+	/// Club Log API key of this application
+	#[arg(long, value_name = "KEY", env = "WSPRSPOTS_CLUBLOG_API_KEY", hide_env_values = true)]
+	pub clublog_api_key: Option<std::string::String>,
+
+	/// Upload to the log of this call sign instead of the station or operator call sign
+	#[arg(long, value_name = "CALL", env = "WSPRSPOTS_CLUBLOG_CALLSIGN")]
+	pub clublog_callsign: Option<Call>,
+
+	/// ADIF file of QSOs that could not be uploaded and are retried first, by default in the cache
+	/// directory
+	#[arg(long, value_name = "FILE", env = "WSPRSPOTS_CLUBLOG_QUEUE")]
+	pub clublog_queue: Option<PathBuf>,
+}
+
+/// Club Log account to upload to
+struct Clublog {
+	email: std::string::String,
+	password: std::string::String,
+	api_key: std::string::String,
+}
+
+impl Logbook for Clublog {
+	fn name(&self) -> &'static str {
+		"Club Log"
+	}
+
+	fn id(&self) -> &'static str {
+		"clublog"
+	}
+
+	fn post(&self, agent: &ureq::Agent, callsign: &Call, record: &str) -> Outcome {
+		let response = agent.post(REALTIME_URL).send_form(&[
+			("email", &self.email),
+			("password", &self.password),
+			("callsign", callsign.as_str()),
+			("adif", record),
+			("api", &self.api_key),
+		]);
+
+		match response {
+			Ok(response) => {
+				debug!("Club Log: {}", response.into_string().unwrap_or_default().trim());
+				Outcome::Done
+			},
+			Err(ureq::Error::Status(400, response)) => {
+				warn!("Club Log rejected QSO: {}", response.into_string().unwrap_or_default().trim());
+				Outcome::Rejected
+			},
+			Err(ureq::Error::Status(403, _)) => {
+				warn!("Club Log rejected the login of {} for {callsign}", self.email);
+				Outcome::Failed
+			},
+			Err(err) => {
+				warn!("Uploading to Club Log failed: {err}");
+				Outcome::Failed
+			},
+		}
+	}
+}
+
+/// Start uploading to Club Log
+pub fn upload(options: ClublogOptions, record: RecordOptions) -> io::Result<Upload> {
+	let (Some(email), Some(password), Some(api_key)) =
+		(options.clublog_email, options.clublog_password, options.clublog_api_key) else {
+		return Err(io::Error::new(io::ErrorKind::InvalidInput, "Uploading to Club Log requires an email address, password and API key"));
+	};
+
+	Upload::new(Clublog { email, password, api_key }, options.clublog_queue, options.clublog_callsign, record)
+}
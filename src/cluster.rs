@@ -0,0 +1,106 @@
+use std::io;
+use std::io::prelude::*;
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use log::{debug, info, warn};
+
+use crate::Qso;
+
+/// Time after which a client that does not take announcements is dropped
+const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Time a client may take to log in
+const LOGIN_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Clients logged in, with their call signs
+type Clients = Arc<Mutex<Vec<(std::string::String, TcpStream)>>>;
+
+/// Ask a client for its call sign and add it to the clients
+fn login(mut stream: TcpStream, clients: &Clients) -> io::Result<()> {
+	stream.set_read_timeout(Some(LOGIN_TIMEOUT))?;
+	stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+	write!(stream, "Please enter your call: ")?;
+
+	let mut line = Vec::new();
+	io::BufReader::new(stream.try_clone()?).read_until(b'\n', &mut line)?;
+
+	// Telnet clients may prefix their first line with option negotiation
+	let call = line.iter()
+		.filter(|ch| ch.is_ascii_alphanumeric() || **ch == b'/')
+		.map(|&ch| char::from(ch.to_ascii_uppercase()))
+		.collect::<std::string::String>();
+
+	if call.is_empty() {
+		return Err(io::Error::new(io::ErrorKind::InvalidData, "no call sign given"));
+	}
+
+	write!(stream, "Hello {call}, this is {} {}, announcing WSPR QSOs as they end\r\n",
+	       env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"))?;
+	info!("Cluster client {call} logged in from {}", stream.peer_addr()?);
+
+	clients.lock().unwrap().push((call, stream));
+	Ok(())
+}
+
+/// DX cluster spot announcing a QSO, made by the operator with the contact as DX station
+///
+/// The line follows the fixed columns of DX spots, so that cluster clients can parse it.
+fn spot(qso: &Qso) -> std::string::String {
+	let spotter = format!("{}:", qso.call_op);
+	let comment = format!("WSPR QSO {:+}/{:+} dB {}", qso.snr_op, qso.snr_ct, qso.grid_ct);
+
+	format!("DX de {spotter:<10}{:>8.1}  {:<12} {:<30.30} {}Z {}\r\n",
+	        qso.freq_ct.mhz() * 1000.0, qso.call_ct, comment, qso.datetime_off().format("%H%M"), qso.grid_op)
+}
+
+/// Telnet server announcing QSOs in DX cluster spot format as they end
+///
+/// Clients log in with their call sign as with a DX cluster. Clients that cannot take an
+/// announcement are disconnected, so that they do not hold up the run.
+pub struct Cluster {
+	clients: Clients,
+}
+
+impl Cluster {
+	/// Listen for clients at the given address
+	pub fn new(addr: &str) -> io::Result<Self> {
+		let listener = TcpListener::bind(addr)
+			.map_err(|err| io::Error::new(err.kind(), format!("Cannot listen at {addr}: {err}")))?;
+		info!("Announcing QSOs to cluster clients at {}", listener.local_addr()?);
+
+		let clients = Clients::default();
+		let shared = clients.clone();
+		thread::spawn(move || {
+			for stream in listener.incoming() {
+				match stream {
+					Ok(stream) => {
+						let clients = shared.clone();
+						thread::spawn(move || {
+							if let Err(err) = login(stream, &clients) {
+								debug!("Cluster client failed to log in: {err}");
+							}
+						});
+					},
+					Err(err) => warn!("Accepting cluster client failed: {err}"),
+				}
+			}
+		});
+
+		Ok(Cluster { clients })
+	}
+
+	/// Announce a QSO to all clients
+	pub fn announce(&self, qso: &Qso) {
+		let line = spot(qso);
+		self.clients.lock().unwrap().retain_mut(|(call, stream)| match stream.write_all(line.as_bytes()) {
+			Ok(()) => true,
+			Err(err) => {
+				info!("Cluster client {call} disconnected: {err}");
+				false
+			},
+		});
+	}
+}
@@ -0,0 +1,142 @@
+use std::ffi::OsString;
+use std::fs;
+use std::path::PathBuf;
+
+use clap::{Arg, Command};
+use toml::{Table, Value};
+
+/// Default configuration file, used if it exists
+fn default_path() -> Option<PathBuf> {
+	let base = match std::env::var_os("XDG_CONFIG_HOME") {
+		Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+		_ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+	};
+
+	Some(base.join(env!("CARGO_PKG_NAME")).join("config.toml"))
+}
+
+/// Take the configuration file given with `--config` out of the arguments, so that it can be given
+/// before as well as after the subcommand
+fn take_path(args: &mut Vec<OsString>) -> Option<PathBuf> {
+	let end = args.iter().position(|arg| arg == "--").unwrap_or(args.len());
+
+	for index in 1..end {
+		if args[index] == "--config" && index + 1 < end {
+			args.remove(index);
+			return Some(args.remove(index).into());
+		} else if let Some(path) = args[index].to_str().and_then(|arg| arg.strip_prefix("--config=")) {
+			let path = PathBuf::from(path);
+			args.remove(index);
+			return Some(path);
+		}
+	}
+
+	None
+}
+
+/// Find the argument with a long name in a command or any of its subcommands
+fn find<'a>(command: &'a Command, long: &str) -> Option<&'a Arg> {
+	command.get_arguments().find(|arg| arg.get_long() == Some(long))
+		.or_else(|| command.get_subcommands().find_map(|command| find(command, long)))
+}
+
+/// Value of an option in the form its environment variable takes
+fn value(arg: &Arg, key: &str, value: &Value) -> Result<String, String> {
+	match value {
+		Value::String(value) => Ok(value.clone()),
+		Value::Integer(value) => Ok(value.to_string()),
+		Value::Float(value) => Ok(value.to_string()),
+		Value::Boolean(value) => Ok(value.to_string()),
+		Value::Array(values) => {
+			let delimiter = match (arg.get_value_delimiter(), values.len()) {
+				(Some(delimiter), _) => delimiter,
+				(None, 1) => ',',
+				(None, _) => return Err(format!("option {key} takes a single value in a configuration file")),
+			};
+
+			values.iter()
+				.map(|item| match item {
+					Value::Array(_) | Value::Table(_) => Err(format!("invalid value for option {key}")),
+					item => self::value(arg, key, item),
+				})
+				.collect::<Result<Vec<_>, _>>()
+				.map(|values| values.join(&delimiter.to_string()))
+		},
+		_ => Err(format!("invalid value for option {key}")),
+	}
+}
+
+/// Set the environment variables of options given in the configuration file which are not set
+/// already, so that the command line and the environment take precedence over it, and return the
+/// remaining arguments
+pub fn apply(command: &Command, mut args: Vec<OsString>) -> Result<Vec<OsString>, String> {
+	let path = match take_path(&mut args)
+		.or_else(|| std::env::var_os("WSPRSPOTS_CONFIG").filter(|path| !path.is_empty()).map(PathBuf::from)) {
+		Some(path) => path,
+		None => match default_path() {
+			Some(path) if path.is_file() => path,
+			_ => return Ok(args),
+		},
+	};
+
+	let text = fs::read_to_string(&path).map_err(|err| format!("{}: {err}", path.display()))?;
+	let table: Table = text.parse().map_err(|err: toml::de::Error| format!("{}: {}", path.display(), err.message()))?;
+
+	for (key, item) in &table {
+		let long = key.replace('_', "-");
+
+		let arg = find(command, &long)
+			.ok_or_else(|| format!("{}: unknown option {key}", path.display()))?;
+		let env = arg.get_env()
+			.filter(|_| long != "config")
+			.ok_or_else(|| format!("{}: option {key} cannot be set in a configuration file", path.display()))?;
+
+		if std::env::var_os(env).is_none() {
+			let value = value(arg, key, item).map_err(|err| format!("{}: {err}", path.display()))?;
+			std::env::set_var(env, value);
+		}
+	}
+
+	Ok(args)
+}
+
+#[cfg(test)]
+mod tests {
+	use clap::CommandFactory;
+
+	use super::*;
+	use crate::cli::Cli;
+
+	fn args(args: &[&str]) -> Vec<OsString> {
+		args.iter().map(OsString::from).collect()
+	}
+
+	#[test]
+	fn takes_config_from_arguments() {
+		let mut given = args(&["wsprspots", "--config", "a.toml", "match", "DO5EU"]);
+		assert_eq!(take_path(&mut given), Some(PathBuf::from("a.toml")));
+		assert_eq!(given, args(&["wsprspots", "match", "DO5EU"]));
+
+		let mut given = args(&["wsprspots", "match", "--config=b.toml", "DO5EU"]);
+		assert_eq!(take_path(&mut given), Some(PathBuf::from("b.toml")));
+		assert_eq!(given, args(&["wsprspots", "match", "DO5EU"]));
+
+		let mut given = args(&["wsprspots", "DO5EU", "--", "--config", "c.toml"]);
+		assert_eq!(take_path(&mut given), None);
+		assert_eq!(given.len(), 5);
+	}
+
+	#[test]
+	fn converts_values() {
+		let command = Cli::command();
+		let value = |key: &str, item: Value| value(find(&command, key).unwrap(), key, &item);
+
+		assert_eq!(value("lookback", Value::Integer(5)), Ok("5".into()));
+		assert_eq!(value("append", Value::Boolean(true)), Ok("true".into()));
+		assert_eq!(value("band", Value::Array(vec!["20m".into(), "40m".into()])), Ok("20m,40m".into()));
+		assert_eq!(value("output", Value::Array(vec!["qsos.adi".into()])), Ok("qsos.adi".into()));
+		assert!(value("output", Value::Array(vec!["qsos.adi".into(), "qsos.csv".into()])).is_err());
+		assert!(value("lookback", Value::Table(Table::new())).is_err());
+		assert!(find(&command, "no-such-option").is_none());
+	}
+}
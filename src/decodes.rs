@@ -0,0 +1,173 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+
+use crate::{Call, Grid};
+use crate::locator;
+
+/// Bit set in the IDs assigned to local decodes, which have none at WSPRnet
+pub const LOCAL_ID: u64 = 1 << 63;
+
+/// Local decode file together with the station that received the decodes
+#[derive(Clone, Debug)]
+pub struct Decodes {
+	/// Reporter call sign
+	pub call: Call,
+	/// Reporter Maidenhead locator
+	pub grid: Grid,
+	/// Path of the decode file
+	pub path: PathBuf,
+}
+
+impl FromStr for Decodes {
+	type Err = std::string::String;
+
+	/// Parse a specification of the form `CALL:GRID:FILE`
+	fn from_str(spec: &str) -> Result<Self, Self::Err> {
+		let mut parts = spec.splitn(3, ':');
+
+		match (parts.next(), parts.next(), parts.next()) {
+			(Some(call), Some(grid), Some(path)) if !call.is_empty() && !path.is_empty() => {
+				if locator::position(grid).is_none() {
+					return Err(format!("invalid Maidenhead locator {grid}"));
+				}
+
+				Ok(Decodes {
+					call: Call::new(call.into()),
+					grid: Grid::new(grid.into()),
+					path: path.into(),
+				})
+			},
+			_ => Err("expected CALL:GRID:FILE".into()),
+		}
+	}
+}
+
+/// Parse a decoded message of the form `CALL [GRID] POWER`
+///
+/// Hashed call signs in angle brackets and messages without locator are accepted.
+fn message<'a>(fields: &mut impl Iterator<Item = &'a str>) -> Option<(&'a str, &'a str, i8)> {
+	let call = fields.next()?.trim_start_matches('<').trim_end_matches('>');
+	let (grid, power) = match fields.next()? {
+		grid if locator::position(grid).is_some() => (grid, fields.next()?),
+		power => ("", power),
+	};
+
+	Some((call, grid, power.parse().ok()?))
+}
+
+/// Format a local decode by the given reporter call sign and locator as a row in WSPRnet CSV format
+pub fn row(station: (&str, &str), id: u64, timestamp: i64, snr: i8, frequency: f64, message: (&str, &str, i8), drift: i8) -> std::string::String {
+	let (call_rx, grid_rx) = station;
+	let (call, grid, power) = message;
+	let distance = locator::distance(grid_rx, grid).map_or(0, |km| km.round() as u16);
+
+	format!("{},{},{},{},{},{:.6},{},{},{},{},{},0,0,,0",
+		LOCAL_ID | id, timestamp, call_rx, grid_rx, snr, frequency, call, grid, power, drift, distance)
+}
+
+/// Convert a line of an `ALL_WSPR.TXT` file written by WSJT-X 2 into a row in WSPRnet CSV format
+///
+/// The line consists of the date and time of the cycle, SNR, time offset, frequency and the
+/// decoded message, followed by the frequency drift and decoder details. The `wspr_spots.txt` file
+/// written by `wsprd` has the same format.
+pub fn convert(line: &str, decodes: &Decodes, id: u64) -> Option<std::string::String> {
+	let mut fields = line.split_whitespace();
+
+	let date = fields.next()?;
+	let time = fields.next()?;
+	let timestamp = NaiveDateTime::parse_from_str(&format!("{date} {time}"), "%y%m%d %H%M").ok()?
+		.and_utc()
+		.timestamp();
+	let snr: i8 = fields.next()?.parse().ok()?;
+	let _dt = fields.next()?;
+	let frequency: f64 = fields.next()?.parse().ok()?;
+	let message = message(&mut fields)?;
+	let drift: i8 = fields.next()?.parse().ok()?;
+
+	Some(row((&decodes.call, &decodes.grid), id, timestamp, snr, frequency, message, drift))
+}
+
+/// Date of the decodes printed by `wsprd` into a file
+///
+/// This is taken from a `YYMMDD_HHMM` cycle in the file name as used for the recordings, or from
+/// the modification time of the file otherwise.
+pub fn wsprd_date(path: &Path) -> io::Result<NaiveDate> {
+	let name = path.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+
+	let from_name = name.as_bytes().windows(11)
+		.position(|window| window[6] == b'_' && window.iter().enumerate().all(|(idx, ch)| idx == 6 || ch.is_ascii_digit()))
+		.and_then(|start| NaiveDate::parse_from_str(&name[start..start + 6], "%y%m%d").ok());
+
+	match from_name {
+		Some(date) => Ok(date),
+		None => Ok(DateTime::<Utc>::from(fs::metadata(path)?.modified()?).date_naive()),
+	}
+}
+
+/// Convert a line printed by the `wsprd` decoder into a row in WSPRnet CSV format
+///
+/// The line consists of the time of the cycle, SNR, time offset, frequency, frequency drift and
+/// the decoded message. The date is not included and has to be given.
+pub fn convert_wsprd(line: &str, decodes: &Decodes, date: NaiveDate, id: u64) -> Option<std::string::String> {
+	let mut fields = line.split_whitespace();
+
+	let time = NaiveTime::parse_from_str(fields.next()?, "%H%M").ok()?;
+	let timestamp = date.and_time(time).and_utc().timestamp();
+	let snr: i8 = fields.next()?.parse::<f64>().ok()?.round() as i8;
+	let _dt = fields.next()?;
+	let frequency: f64 = fields.next()?.parse().ok()?;
+	let drift: i8 = fields.next()?.parse().ok()?;
+	let message = message(&mut fields)?;
+
+	Some(row((&decodes.call, &decodes.grid), id, timestamp, snr, frequency, message, drift))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn decodes() -> Decodes {
+		"DO5EU:JO62qm:ALL_WSPR.TXT".parse().unwrap()
+	}
+
+	fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+		NaiveDate::from_ymd_opt(year, month, day).unwrap()
+	}
+
+	#[test]
+	fn parses_specifications() {
+		let decodes = decodes();
+		assert_eq!((decodes.call.as_str(), decodes.grid.as_str()), ("DO5EU", "JO62qm"));
+		assert_eq!(decodes.path, Path::new("ALL_WSPR.TXT"));
+
+		assert!("DO5EU:XX99:ALL_WSPR.TXT".parse::<Decodes>().is_err());
+		assert!("DO5EU:JO62qm".parse::<Decodes>().is_err());
+	}
+
+	#[test]
+	fn converts_wsjtx_lines() {
+		let row = convert("210112 2120 -29  0.2   7.040022  DP0GVN IB59ui 27          0  0.16  1  1    0  0  23     1   810", &decodes(), 7);
+		assert_eq!(row.unwrap(), format!("{},1610486400,DO5EU,JO62qm,-29,7.040022,DP0GVN,IB59ui,27,0,13805,0,0,,0", LOCAL_ID | 7));
+
+		let row = convert("210112 2120 -12  0.1  14.097100  <PJ4/K1ABC> 37  -1  0.2  1  1", &decodes(), 8);
+		assert_eq!(row.unwrap(), format!("{},1610486400,DO5EU,JO62qm,-12,14.097100,PJ4/K1ABC,,37,-1,0,0,0,,0", LOCAL_ID | 8));
+
+		assert_eq!(convert("210112 2120 -29 0.2", &decodes(), 9), None);
+	}
+
+	#[test]
+	fn converts_wsprd_lines() {
+		let row = convert_wsprd("2120 -28.6  0.2   7.040022  0  DP0GVN IB59ui 27", &decodes(), date(2021, 1, 12), 7);
+		assert_eq!(row.unwrap(), format!("{},1610486400,DO5EU,JO62qm,-29,7.040022,DP0GVN,IB59ui,27,0,13805,0,0,,0", LOCAL_ID | 7));
+	}
+
+	#[test]
+	fn takes_wsprd_date_from_file_name() {
+		assert_eq!(wsprd_date(Path::new("rec/210112_2120.c2.txt")).unwrap(), date(2021, 1, 12));
+		assert_eq!(wsprd_date(Path::new("spots-201231_0002")).unwrap(), date(2020, 12, 31));
+	}
+}
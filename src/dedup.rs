@@ -0,0 +1,102 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::convert::TryFrom;
+use std::io;
+use std::io::prelude::*;
+
+use smartstring::alias::String;
+
+use crate::{Band, Call, Qso};
+use crate::checkpoint::{Decode, Encode};
+use crate::cli::ContactWindow;
+
+/// Number of recent spot IDs remembered, covering dozens of busy cycles
+const WINDOW: usize = 1 << 18;
+
+/// Recently seen spot IDs, for skipping spots repeated by overlapping inputs
+///
+/// As inputs are merged by time, repeated spots follow each other closely, so that only a bounded
+/// window of IDs needs to be remembered.
+#[derive(Default)]
+pub struct Dedup {
+	ids: HashSet<u64>,
+	/// IDs in order of arrival, to forget the oldest
+	order: VecDeque<u64>,
+}
+
+impl Dedup {
+	/// Note a spot ID and return whether it is new
+	pub fn insert(&mut self, id: u64) -> bool {
+		if !self.ids.insert(id) {
+			return false;
+		}
+
+		if self.order.len() == WINDOW {
+			if let Some(oldest) = self.order.pop_front() {
+				self.ids.remove(&oldest);
+			}
+		}
+
+		self.order.push_back(id);
+		true
+	}
+}
+
+/// Start of the last QSO logged with each contact on each band, for skipping repeats
+#[derive(Default)]
+pub struct Repeats {
+	/// Start time by operator and contact call sign and band
+	last: HashMap<((Call, Call), String), u64>,
+	/// Number of repeat QSOs skipped
+	pub count: usize,
+}
+
+impl Repeats {
+	/// Note a QSO and return whether it repeats one logged within the window
+	pub fn repeat(&mut self, qso: &Qso, window: ContactWindow) -> bool {
+		let band = match Band::try_from(qso.freq_op) {
+			Ok(band) => band.name().into(),
+			Err(_) => qso.freq_op.0.to_string().into(),
+		};
+
+		let key = ((qso.call_op.clone(), qso.call_ct.clone()), band);
+		let repeat = self.last.get(&key).is_some_and(|&last| match window {
+			ContactWindow::Hours(hours) => qso.time_first.abs_diff(last) < hours * 3600,
+			ContactWindow::PerDay => qso.time_first / 86400 == last / 86400,
+			ContactWindow::PerBandSlot => true,
+		});
+
+		if repeat {
+			self.count += 1;
+		} else {
+			self.last.insert(key, qso.time_first);
+		}
+
+		repeat
+	}
+}
+
+impl Encode for Repeats {
+	fn encode(&self, out: &mut dyn Write) -> io::Result<()> {
+		self.last.encode(out)?;
+		self.count.encode(out)
+	}
+}
+
+impl Decode for Repeats {
+	fn decode(input: &mut dyn Read) -> io::Result<Self> {
+		Ok(Repeats { last: Decode::decode(input)?, count: Decode::decode(input)? })
+	}
+}
+
+impl Encode for Dedup {
+	fn encode(&self, out: &mut dyn Write) -> io::Result<()> {
+		self.order.encode(out)
+	}
+}
+
+impl Decode for Dedup {
+	fn decode(input: &mut dyn Read) -> io::Result<Self> {
+		let order = VecDeque::<u64>::decode(input)?;
+		Ok(Dedup { ids: order.iter().copied().collect(), order })
+	}
+}
@@ -0,0 +1,106 @@
+use std::io;
+use std::io::prelude::*;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use chrono::{SecondsFormat, Utc};
+use clap::ValueEnum;
+use log::LevelFilter;
+use log::kv::{self, Key, Value, VisitSource};
+use serde_json::Map;
+
+/// Format of diagnostics on standard error
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+	/// Free‐form text for reading
+	Text,
+	/// One JSON object per line with the time, level, message and details such as the event
+	Json,
+}
+
+/// Whether diagnostics are written as JSON
+static JSON: AtomicBool = AtomicBool::new(false);
+
+/// Members of a JSON diagnostic taken from the key‐value pairs of a record
+struct Members(Map<String, serde_json::Value>);
+
+impl<'kvs> VisitSource<'kvs> for Members {
+	fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), kv::Error> {
+		let value = if let Some(value) = value.to_u64() {
+			value.into()
+		} else if let Some(value) = value.to_i64() {
+			value.into()
+		} else if let Some(value) = value.to_f64() {
+			value.into()
+		} else if let Some(value) = value.to_bool() {
+			value.into()
+		} else {
+			value.to_string().into()
+		};
+
+		self.0.insert(key.to_string(), value);
+		Ok(())
+	}
+}
+
+/// Write a diagnostic as a JSON object on a line of its own, followed by its details
+fn write_json(out: &mut impl Write, level: &str, target: &str, message: &str, details: Map<String, serde_json::Value>) -> io::Result<()> {
+	let mut object = Map::new();
+	object.insert("time".into(), Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true).into());
+	object.insert("level".into(), level.into());
+	object.insert("target".into(), target.into());
+	object.insert("message".into(), message.into());
+	object.extend(details);
+
+	writeln!(out, "{}", serde_json::Value::Object(object))
+}
+
+/// Print diagnostics up to the given level in the given format on standard error
+pub fn init(level: LevelFilter, format: LogFormat) {
+	let mut builder = env_logger::Builder::new();
+	builder.filter_level(level)
+		.format_timestamp(None)
+		.format_target(false)
+		.parse_default_env();
+
+	if format == LogFormat::Json {
+		JSON.store(true, Ordering::Relaxed);
+		builder.format(|buf, record| {
+			let mut details = Members(Map::new());
+			record.key_values().visit(&mut details).map_err(io::Error::other)?;
+			write_json(buf, &record.level().as_str().to_ascii_lowercase(), record.target(), &record.args().to_string(), details.0)
+		});
+	}
+
+	builder.init();
+}
+
+/// Print the final summary of a run, which is shown regardless of the level
+///
+/// As JSON, the summary is an event of its own with the given counts.
+pub fn summary(message: &str, counts: &[(&str, usize)]) {
+	let mut err = io::stderr().lock();
+
+	let _ = if JSON.load(Ordering::Relaxed) {
+		let mut details = Map::new();
+		details.insert("event".into(), "summary".into());
+		details.extend(counts.iter().map(|&(key, count)| (key.to_owned(), count.into())));
+		write_json(&mut err, "info", env!("CARGO_PKG_NAME"), message, details)
+	} else {
+		writeln!(err, "{message}")
+	};
+}
+
+/// Report an error ending the run if diagnostics are written as JSON, returning whether it was
+///
+/// Otherwise the error is left to be printed on return from main.
+pub fn error(err: &io::Error) -> bool {
+	if !JSON.load(Ordering::Relaxed) {
+		return false;
+	}
+
+	let mut details = Map::new();
+	details.insert("event".into(), "failed".into());
+	details.insert("kind".into(), err.kind().to_string().into());
+	let _ = write_json(&mut io::stderr().lock(), "error", env!("CARGO_PKG_NAME"), &err.to_string(), details);
+	true
+}
@@ -0,0 +1,128 @@
+use std::fs::{self, OpenOptions};
+use std::io;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use chrono::{Months, NaiveDate};
+use log::info;
+
+/// Inclusive range of months
+#[derive(Clone, Debug)]
+pub struct MonthRange {
+	first: NaiveDate,
+	last: NaiveDate,
+}
+
+impl MonthRange {
+	/// First day of every month in the range
+	pub fn months(&self) -> impl Iterator<Item = NaiveDate> + '_ {
+		std::iter::successors(Some(self.first), |month| month.checked_add_months(Months::new(1)))
+			.take_while(|month| *month <= self.last)
+	}
+}
+
+impl FromStr for MonthRange {
+	type Err = String;
+
+	/// Parse a month as `YYYY-MM` or a range of months as `YYYY-MM..YYYY-MM`
+	fn from_str(range: &str) -> Result<Self, Self::Err> {
+		let month = |month: &str| NaiveDate::parse_from_str(&format!("{}-01", month.trim()), "%Y-%m-%d")
+			.map_err(|_| format!("invalid month {month}, expected YYYY-MM"));
+
+		let (first, last) = match range.split_once("..") {
+			Some((first, last)) => (month(first)?, month(last)?),
+			None => (month(range)?, month(range)?),
+		};
+
+		if first > last {
+			return Err("first month after last month".into());
+		}
+
+		Ok(MonthRange { first, last })
+	}
+}
+
+/// Default cache directory for downloaded archives
+pub fn cache_dir() -> io::Result<PathBuf> {
+	let base = match std::env::var_os("XDG_CACHE_HOME") {
+		Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+		_ => match std::env::var_os("HOME") {
+			Some(home) => PathBuf::from(home).join(".cache"),
+			None => return Err(io::Error::new(io::ErrorKind::NotFound, "Unable to determine cache directory")),
+		},
+	};
+
+	Ok(base.join(env!("CARGO_PKG_NAME")))
+}
+
+/// Downloads monthly spot archives into a local cache
+pub struct Fetcher {
+	agent: ureq::Agent,
+	/// URL of the directory containing the archives
+	base_url: String,
+	/// Cache directory
+	dir: PathBuf,
+	/// Download archives again even if cached
+	refresh: bool,
+}
+
+impl Fetcher {
+	pub fn new(base_url: &str, dir: PathBuf, refresh: bool) -> io::Result<Self> {
+		fs::create_dir_all(&dir)?;
+
+		Ok(Fetcher {
+			agent: ureq::AgentBuilder::new()
+				.user_agent(concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION")))
+				.build(),
+			base_url: base_url.trim_end_matches('/').to_owned(),
+			dir,
+			refresh,
+		})
+	}
+
+	/// Path of the cached archive for a month, downloading it first if necessary
+	///
+	/// Partial downloads are kept and resumed on the next attempt.
+	pub fn archive(&self, month: NaiveDate) -> io::Result<PathBuf> {
+		let name = format!("wsprspots-{}.csv.gz", month.format("%Y-%m"));
+		let path = self.dir.join(&name);
+
+		if !self.refresh && path.is_file() {
+			return Ok(path);
+		}
+
+		let url = format!("{}/{}", self.base_url, name);
+		let part = self.dir.join(format!("{name}.part"));
+		let offset = fs::metadata(&part).map_or(0, |meta| meta.len());
+
+		let mut request = self.agent.get(&url);
+		if offset > 0 {
+			info!("Resuming download of {url} at {offset} bytes");
+			request = request.set("Range", &format!("bytes={offset}-"));
+		} else {
+			info!("Downloading {url}");
+		}
+
+		match request.call() {
+			Ok(response) => {
+				// Servers ignoring the range send the whole archive
+				let resume = response.status() == 206;
+				let mut file = OpenOptions::new()
+					.create(true)
+					.write(true)
+					.append(resume)
+					.truncate(!resume)
+					.open(&part)?;
+
+				io::copy(&mut response.into_reader(), &mut file)?;
+				file.sync_all()?;
+			},
+			// The partial download is already complete
+			Err(ureq::Error::Status(416, _)) if offset > 0 => (),
+			Err(err) => return Err(io::Error::other(err.to_string())),
+		}
+
+		fs::rename(&part, &path)?;
+		Ok(path)
+	}
+}
@@ -0,0 +1,322 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use regex::{Regex, RegexSet, RegexSetBuilder};
+
+use crate::{Band, Call, Grid, Power, Qso, Spot};
+use crate::adif::Logged;
+use crate::cli::ContactWindow;
+use crate::excluded::EXCLUDED;
+
+/// Criteria deciding which spots and contacts are considered for QSOs
+#[derive(Debug, Default)]
+pub struct Filter {
+	/// Excluded call signs in addition to the built‐in set
+	excluded: HashSet<Call>,
+	/// Exclusively included call signs, if any
+	only: Option<HashSet<Call>>,
+	/// Patterns of call signs to include, if any
+	include_regex: Option<RegexSet>,
+	/// Patterns of call signs to exclude
+	exclude_regex: Option<RegexSet>,
+	/// Apply call sign patterns to the reporter of every spot as well
+	regex_reporter: bool,
+	/// Prefixes of contact locators, if any
+	grids: Vec<Grid>,
+	/// Prefixes of operator locators, if any
+	my_grids: Vec<Grid>,
+	/// Start of time window
+	since: Option<u64>,
+	/// End of time window (exclusive)
+	until: Option<u64>,
+	/// Only one hour out of this many is sampled
+	sample: Option<u64>,
+	/// Bands of the spots, if restricted
+	bands: Vec<Band>,
+	/// Minimum signal‐to‐noise ratio
+	min_snr: Option<i8>,
+	/// Minimum distance
+	min_distance: Option<u16>,
+	/// Maximum distance
+	max_distance: Option<u16>,
+	/// Maximum absolute frequency drift
+	max_drift: Option<u8>,
+	/// Minimum transmission power of both stations
+	min_power: Option<Power>,
+	/// Maximum transmission power of both stations
+	max_power: Option<Power>,
+	/// Minimum number of spots in each direction
+	min_rounds: usize,
+	/// Require reciprocal spots from the same or adjacent cycles
+	synchronous: bool,
+	/// QSOs logged before
+	logged: Logged,
+	/// Window within which repeat QSOs with a contact on a band are skipped, if any
+	contact_window: Option<ContactWindow>,
+}
+
+impl Filter {
+	/// Exclude a call sign
+	pub fn exclude(&mut self, call: Call) {
+		self.excluded.insert(call);
+	}
+
+	/// Exclude the call signs listed in a file
+	pub fn exclude_file(&mut self, path: &Path) -> io::Result<()> {
+		self.excluded.extend(read_calls(path)?);
+		Ok(())
+	}
+
+	/// Only log QSOs with this call sign and others passed to this method
+	pub fn only(&mut self, call: Call) {
+		self.only.get_or_insert_with(HashSet::new).insert(call);
+	}
+
+	/// Only log QSOs with the call signs listed in a file and others passed to this method
+	pub fn only_file(&mut self, path: &Path) -> io::Result<()> {
+		self.only.get_or_insert_with(HashSet::new).extend(read_calls(path)?);
+		Ok(())
+	}
+
+	/// Only log QSOs with call signs matching any of the patterns
+	pub fn include_regex(&mut self, patterns: &[Regex]) -> io::Result<()> {
+		self.include_regex = regex_set(patterns)?;
+		Ok(())
+	}
+
+	/// Do not log QSOs with call signs matching any of the patterns
+	pub fn exclude_regex(&mut self, patterns: &[Regex]) -> io::Result<()> {
+		self.exclude_regex = regex_set(patterns)?;
+		Ok(())
+	}
+
+	/// Apply call sign patterns to the reporter of every spot as well
+	pub fn regex_reporter(&mut self, enable: bool) {
+		self.regex_reporter = enable;
+	}
+
+	/// Only log QSOs with contacts in locators starting with any of the prefixes
+	pub fn grid(&mut self, prefixes: &[Grid]) {
+		self.grids = prefixes.to_vec();
+	}
+
+	/// Only log QSOs made from locators starting with any of the prefixes
+	pub fn my_grid(&mut self, prefixes: &[Grid]) {
+		self.my_grids = prefixes.to_vec();
+	}
+
+	/// Restrict spots to a time window, with the end being exclusive
+	pub fn window(&mut self, since: Option<u64>, until: Option<u64>) {
+		self.since = since;
+		self.until = until;
+	}
+
+	/// Only sample one hour out of the given number, counting from the Unix epoch
+	pub fn sample(&mut self, sample: Option<u64>) {
+		self.sample = sample;
+	}
+
+	/// Only consider spots on the given bands, if any
+	pub fn bands(&mut self, bands: &[Band]) {
+		self.bands = bands.to_vec();
+	}
+
+	/// Skip spots with a signal‐to‐noise ratio below the given value
+	pub fn min_snr(&mut self, snr: Option<i8>) {
+		self.min_snr = snr;
+	}
+
+	/// Skip spots with a distance outside the given range
+	pub fn distance(&mut self, min: Option<u16>, max: Option<u16>) {
+		self.min_distance = min;
+		self.max_distance = max;
+	}
+
+	/// Skip spots with an absolute frequency drift above the given value
+	pub fn max_drift(&mut self, drift: Option<u8>) {
+		self.max_drift = drift;
+	}
+
+	/// Only log QSOs where both stations transmitted with a power in the given range
+	pub fn power(&mut self, min: Option<Power>, max: Option<Power>) {
+		self.min_power = min;
+		self.max_power = max;
+	}
+
+	/// Only log QSOs with at least the given number of spots in each direction
+	pub fn min_rounds(&mut self, rounds: usize) {
+		self.min_rounds = rounds;
+	}
+
+	/// Only log QSOs with reciprocal spots from the same or adjacent cycles, if required
+	pub fn synchronous(&mut self, required: bool) {
+		self.synchronous = required;
+	}
+
+	/// Do not log QSOs contained in the given logs
+	pub fn logged(&mut self, logged: Logged) {
+		self.logged = logged;
+	}
+
+	fn excluded(&self, call: &Call) -> bool {
+		EXCLUDED.contains(call.as_ref()) ||
+		self.excluded.contains(call) ||
+		self.exclude_regex.as_ref().is_some_and(|set| set.is_match(call))
+	}
+
+	fn listed(&self, call: &Call) -> bool {
+		self.only.as_ref().is_none_or(|only| only.contains(call)) &&
+		self.include_regex.as_ref().is_none_or(|set| set.is_match(call))
+	}
+
+	/// Whether a timestamp lies within the time window and a sampled hour
+	pub fn timestamp(&self, timestamp: u64) -> bool {
+		self.since.is_none_or(|since| timestamp >= since) &&
+		self.until.is_none_or(|until| timestamp < until) &&
+		self.sample.is_none_or(|sample| (timestamp / 3600).is_multiple_of(sample))
+	}
+
+	/// Whether a CSV row may contain a relevant spot, judging by its timestamp only
+	///
+	/// This avoids parsing rows outside the time window or the sampled hours. Rows with no valid
+	/// timestamp pass.
+	pub fn row(&self, row: &str) -> bool {
+		if self.since.is_none() && self.until.is_none() && self.sample.is_none() {
+			return true;
+		}
+
+		match row.split(',').nth(1).map(str::parse) {
+			Some(Ok(timestamp)) => self.timestamp(timestamp),
+			_ => true
+		}
+	}
+
+	/// Whether a spot may contribute to QSOs
+	pub fn spot(&self, spot: &Spot) -> bool {
+		if !self.timestamp(spot.timestamp) {
+			return false;
+		}
+
+		if self.min_snr.is_some_and(|snr| spot.snr < snr) {
+			return false;
+		}
+
+		if self.min_distance.is_some_and(|distance| spot.distance < distance) ||
+		   self.max_distance.is_some_and(|distance| spot.distance > distance) {
+			return false;
+		}
+
+		if self.max_drift.is_some_and(|drift| spot.drift.unsigned_abs() > drift) {
+			return false;
+		}
+
+		if !self.bands.is_empty() {
+			let Ok(band) = Band::try_from(spot.frequency) else {
+				return false;
+			};
+
+			if !self.bands.contains(&band) {
+				return false;
+			}
+		}
+
+		if self.regex_reporter {
+			if self.exclude_regex.as_ref().is_some_and(|set| set.is_match(&spot.call_rx)) {
+				return false;
+			}
+
+			if self.include_regex.as_ref().is_some_and(|set| !set.is_match(&spot.call_rx)) {
+				return false;
+			}
+		}
+
+		true
+	}
+
+	/// Whether QSOs with a contact may be logged
+	pub fn contact(&self, call: &Call) -> bool {
+		!self.excluded(call) && self.listed(call)
+	}
+
+	/// Whether QSOs between the given operator and contact locators may be logged
+	pub fn grids(&self, grid_op: &Grid, grid_ct: &Grid) -> bool {
+		fn starts_with(grid: &Grid, prefixes: &[Grid]) -> bool {
+			prefixes.is_empty() || prefixes.iter().any(|prefix| {
+				grid.as_bytes().get(..prefix.len())
+					.is_some_and(|head| head.eq_ignore_ascii_case(prefix.as_bytes()))
+			})
+		}
+
+		starts_with(grid_op, &self.my_grids) && starts_with(grid_ct, &self.grids)
+	}
+
+	/// Whether QSOs between two stations may be logged
+	pub fn pair(&self, call_a: &Call, call_b: &Call) -> bool {
+		!self.excluded(call_a) && !self.excluded(call_b) && (self.listed(call_a) || self.listed(call_b))
+	}
+}
+
+impl Filter {
+	/// Whether a completed QSO may be logged
+	pub fn qso(&self, qso: &Qso) -> bool {
+		if qso.num_rx < self.min_rounds || qso.num_tx < self.min_rounds {
+			return false;
+		}
+
+		if self.synchronous && !qso.synchronous {
+			return false;
+		}
+
+		// Power not reported passes either bound, like other missing fields
+		for power in [qso.power_op, qso.power_ct].into_iter().filter(|power| power.is_known()) {
+			if self.min_power.is_some_and(|min| power < min) ||
+			   self.max_power.is_some_and(|max| power > max) {
+				return false;
+			}
+		}
+
+		true
+	}
+
+	/// Whether a completed QSO was logged before
+	pub fn already_logged(&self, qso: &Qso) -> bool {
+		self.logged.contains(qso)
+	}
+
+	/// Skip repeat QSOs with a contact on a band within the given window
+	pub fn dedupe_contact(&mut self, window: Option<ContactWindow>) {
+		self.contact_window = window;
+	}
+
+	/// Window within which repeat QSOs with a contact on a band are skipped, if any
+	pub fn contact_window(&self) -> Option<ContactWindow> {
+		self.contact_window
+	}
+}
+
+/// Read a list of call signs, one per line, with `#` starting a comment
+pub fn read_calls(path: &Path) -> io::Result<Vec<Call>> {
+	let text = fs::read_to_string(path)
+		.map_err(|err| io::Error::new(err.kind(), format!("{}: {}", path.display(), err)))?;
+
+	Ok(text.lines()
+		.map(|line| line.split('#').next().unwrap_or_default().trim())
+		.filter(|call| !call.is_empty())
+		.map(|call| Call::new(call.into()))
+		.collect())
+}
+
+/// Compile call sign patterns into a case‐insensitive set
+fn regex_set(patterns: &[Regex]) -> io::Result<Option<RegexSet>> {
+	if patterns.is_empty() {
+		return Ok(None);
+	}
+
+	RegexSetBuilder::new(patterns.iter().map(Regex::as_str))
+		.case_insensitive(true)
+		.build()
+		.map(Some)
+		.map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))
+}
@@ -0,0 +1,89 @@
+use std::io;
+use std::path::PathBuf;
+
+use clap::Args;
+use log::{debug, warn};
+
+use crate::Call;
+use crate::adif::RecordOptions;
+use crate::online::{Logbook, Outcome, Upload};
+
+/// Endpoint taking one QSO at a time
+const NEW_ENTRY_URL: &str = "https://robot.hrdlog.net/NewEntry.aspx";
+
+/// Uploading the QSOs to HRDLog.net as they end
+#[derive(Args, Clone, Debug)]
+pub struct HrdlogOptions {
+	/// Upload every QSO to HRDLog.net as it ends
+	#[arg(long, requires = "hrdlog_code", conflicts_with = "dry_run", env = "WSPRSPOTS_HRDLOG")]
+	pub hrdlog: bool,
+
+	/// Upload code of the HRDLog.net account
+	#[arg(long, value_name = "CODE", env = "WSPRSPOTS_HRDLOG_CODE", hide_env_values = true)]
+	pub hrdlog_code: Option<std::string::String>,
+
+	/// Upload to the log of this call sign instead of the station or operator call sign
+	#[arg(long, value_name = "CALL", env = "WSPRSPOTS_HRDLOG_CALLSIGN")]
+	pub hrdlog_callsign: Option<Call>,
+
+	/// ADIF file of QSOs that could not be uploaded and are retried first, by default in the cache
+	/// directory
+	#[arg(long, value_name = "FILE", env = "WSPRSPOTS_HRDLOG_QUEUE")]
+	pub hrdlog_queue: Option<PathBuf>,
+}
+
+/// HRDLog.net account to upload to
+struct Hrdlog {
+	code: std::string::String,
+}
+
+impl Logbook for Hrdlog {
+	fn name(&self) -> &'static str {
+		"HRDLog.net"
+	}
+
+	fn id(&self) -> &'static str {
+		"hrdlog"
+	}
+
+	/// Upload a record, which is answered with an XML document that has an `insert` or `error` element
+	fn post(&self, agent: &ureq::Agent, callsign: &Call, record: &str) -> Outcome {
+		let response = agent.post(NEW_ENTRY_URL).send_form(&[
+			("Callsign", callsign.as_str()),
+			("Code", &self.code),
+			("App", env!("CARGO_PKG_NAME")),
+			("ADIFData", record),
+		]);
+
+		let body = match response.map(ureq::Response::into_string) {
+			Ok(Ok(body)) => body,
+			Ok(Err(err)) => {
+				warn!("Uploading to HRDLog.net failed: {err}");
+				return Outcome::Failed;
+			},
+			Err(err) => {
+				warn!("Uploading to HRDLog.net failed: {err}");
+				return Outcome::Failed;
+			},
+		};
+
+		debug!("HRDLog.net: {}", body.trim());
+
+		match body.split_once("<error>").and_then(|(_, rest)| rest.split_once("</error>")) {
+			Some((error, _)) => {
+				warn!("HRDLog.net rejected QSO for {callsign}: {}", error.trim());
+				Outcome::Rejected
+			},
+			None => Outcome::Done,
+		}
+	}
+}
+
+/// Start uploading to HRDLog.net
+pub fn upload(options: HrdlogOptions, record: RecordOptions) -> io::Result<Upload> {
+	let Some(code) = options.hrdlog_code else {
+		return Err(io::Error::new(io::ErrorKind::InvalidInput, "Uploading to HRDLog.net requires an upload code"));
+	};
+
+	Upload::new(Hrdlog { code }, options.hrdlog_queue, options.hrdlog_callsign, record)
+}
@@ -0,0 +1,766 @@
+use std::cell::Cell;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, VecDeque};
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::str::FromStr;
+
+use chrono::NaiveDate;
+use clap::ValueEnum;
+use flate2::bufread::MultiGzDecoder;
+use log::{log_enabled, warn, Level};
+
+#[cfg(feature = "fetch")]
+use crate::Call;
+use crate::decodes::{self, Decodes};
+use crate::json;
+#[cfg(feature = "mqtt")]
+use crate::mqtt::Subscription;
+use crate::progress::Progress;
+use crate::schema::Schema;
+
+/// UTF‐8 byte order mark, written at the start of files by Windows tools
+const BOM: &[u8] = b"\xef\xbb\xbf";
+
+/// Magic number at the start of gzip streams
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Magic number at the start of bzip2 streams
+#[cfg(feature = "bzip2")]
+const BZIP2_MAGIC: [u8; 3] = *b"BZh";
+
+/// Magic number at the start of Zstandard frames
+#[cfg(feature = "zstd")]
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// Reader keeping count of the bytes read
+struct Counted<R: Read> {
+	inner: R,
+	count: Rc<Cell<u64>>,
+}
+
+impl<R: Read> Read for Counted<R> {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		let len = self.inner.read(buf)?;
+		self.count.set(self.count.get() + len as u64);
+		Ok(len)
+	}
+}
+
+/// Decompress the input if it is compressed
+pub fn decompress<R: BufRead + 'static>(mut reader: R) -> io::Result<Box<dyn BufRead>> {
+	let head = reader.fill_buf()?;
+
+	if head.starts_with(&GZIP_MAGIC) {
+		return Ok(Box::new(io::BufReader::new(MultiGzDecoder::new(reader))));
+	}
+
+	#[cfg(feature = "bzip2")]
+	if head.starts_with(&BZIP2_MAGIC) {
+		return Ok(Box::new(io::BufReader::new(bzip2::bufread::MultiBzDecoder::new(reader))));
+	}
+
+	#[cfg(feature = "zstd")]
+	if head.starts_with(&ZSTD_MAGIC) {
+		return Ok(Box::new(io::BufReader::new(zstd::Decoder::with_buffer(reader)?)));
+	}
+
+	Ok(Box::new(reader))
+}
+
+/// Decompress the input if it is compressed and skip any byte order mark
+fn decode<R: BufRead + 'static>(reader: R) -> io::Result<Box<dyn BufRead>> {
+	let mut reader = decompress(reader)?;
+
+	if reader.fill_buf()?.starts_with(BOM) {
+		reader.consume(BOM.len());
+	}
+
+	Ok(reader)
+}
+
+/// Open an input file for reading, `-` denoting standard input
+///
+/// Compressed input is detected and decompressed transparently.
+pub fn open(path: &Path, count: Option<Rc<Cell<u64>>>) -> io::Result<Box<dyn BufRead>> {
+	if path.as_os_str() == "-" {
+		decode(io::stdin().lock())
+	} else {
+		let file = File::open(path)?;
+		match count {
+			Some(count) => decode(io::BufReader::new(Counted { inner: file, count })),
+			None => decode(io::BufReader::new(file)),
+		}
+	}
+}
+
+/// Format of spot files
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum InputFormat {
+	/// Comma‐separated values in WSPRnet or a detected layout
+	#[default]
+	Csv,
+	/// One JSON object per line
+	Jsonl,
+	/// JSON array of objects
+	Json,
+	/// Apache Parquet file
+	#[cfg(feature = "parquet")]
+	Parquet,
+}
+
+/// Rows read from an input file
+type Rows = Box<dyn Iterator<Item = io::Result<String>>>;
+
+/// Initial capacity of lines, enough for most rows in WSPRnet CSV format
+const LINE_CAPACITY: usize = 128;
+
+/// Lines of a text file, ending in LF or CRLF
+///
+/// Lines that are not valid UTF‐8 are taken to be Latin‐1, as exported by some Windows tools.
+struct Text {
+	reader: Box<dyn BufRead>,
+	/// Incomplete last line
+	pending: Vec<u8>,
+	/// Wait for the last line to be completed, as the file is growing
+	follow: bool,
+	/// Name of the file, to warn about its encoding once
+	name: Option<std::string::String>,
+}
+
+impl Iterator for Text {
+	type Item = io::Result<String>;
+
+	/// Read the next line, if any has been completed yet when following
+	fn next(&mut self) -> Option<Self::Item> {
+		match self.reader.read_until(b'\n', &mut self.pending) {
+			Err(err) => return Some(Err(err)),
+			Ok(_) if self.pending.ends_with(b"\n") => (),
+			Ok(_) if self.follow || self.pending.is_empty() => return None,
+			Ok(_) => (),
+		}
+
+		let mut line = std::mem::replace(&mut self.pending, Vec::with_capacity(LINE_CAPACITY));
+		if line.ends_with(b"\n") {
+			line.pop();
+			if line.ends_with(b"\r") {
+				line.pop();
+			}
+		}
+
+		Some(Ok(String::from_utf8(line).unwrap_or_else(|err| {
+			if let Some(name) = self.name.take() {
+				warn!("{name}: Reading lines that are not valid UTF-8 as Latin-1");
+			}
+
+			err.into_bytes().into_iter().map(char::from).collect()
+		})))
+	}
+}
+
+/// Spot source given by URI
+#[derive(Clone, Debug)]
+pub enum Input {
+	/// SQLite database
+	#[cfg(feature = "sqlite")]
+	Sqlite(PathBuf),
+	/// Subscription to an MQTT topic
+	#[cfg(feature = "mqtt")]
+	Mqtt(Subscription),
+	/// Unix domain socket listened at for spots
+	#[cfg(unix)]
+	Socket(PathBuf),
+	/// PSK Reporter reception reports involving a call sign
+	#[cfg(feature = "fetch")]
+	PskReporter(Call),
+}
+
+impl FromStr for Input {
+	type Err = std::string::String;
+
+	/// Parse a URI such as `sqlite:spots.db`, `mqtt://broker/topic`, `unix:spots.sock` or
+	/// `pskreporter:DO5EU`
+	fn from_str(uri: &str) -> Result<Self, Self::Err> {
+		#[cfg(feature = "fetch")]
+		if let Some(call) = uri.strip_prefix("pskreporter:") {
+			if call.is_empty() || !call.bytes().all(|ch| ch.is_ascii_alphanumeric() || ch == b'/') {
+				return Err(format!("invalid call sign {call}"));
+			}
+
+			return Ok(Input::PskReporter(Call::new(call.into())));
+		}
+
+		#[cfg(feature = "sqlite")]
+		if let Some(path) = uri.strip_prefix("sqlite:") {
+			return Ok(Input::Sqlite(path.into()));
+		}
+
+		#[cfg(feature = "mqtt")]
+		if uri.starts_with("mqtt://") {
+			return uri.parse().map(Input::Mqtt);
+		}
+
+		#[cfg(unix)]
+		if let Some(path) = uri.strip_prefix("unix:") {
+			if path.is_empty() {
+				return Err(format!("missing socket path in {uri}"));
+			}
+
+			return Ok(Input::Socket(path.into()));
+		}
+
+		Err(format!("unsupported input {uri}"))
+	}
+}
+
+/// Input file or other source
+enum Source {
+	/// Spot database dump in WSPRnet CSV format
+	Wsprnet(PathBuf),
+	/// Local decodes of WSJT-X
+	AllWspr(Decodes),
+	/// Local decodes printed by `wsprd`
+	Wsprd(Decodes),
+	/// SQLite database
+	#[cfg(feature = "sqlite")]
+	Sqlite(PathBuf),
+	/// PSK Reporter reception reports
+	#[cfg(feature = "fetch")]
+	PskReporter(Call),
+}
+
+impl Source {
+	/// Path of the file, if any
+	fn path(&self) -> Option<&Path> {
+		match self {
+			Source::Wsprnet(path) => Some(path),
+			#[cfg(feature = "sqlite")]
+			Source::Sqlite(path) => Some(path),
+			Source::AllWspr(decodes) | Source::Wsprd(decodes) => Some(&decodes.path),
+			#[cfg(feature = "fetch")]
+			Source::PskReporter(_) => None,
+		}
+	}
+}
+
+impl fmt::Display for Source {
+	fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			#[cfg(feature = "fetch")]
+			Source::PskReporter(call) => write!(fmtr, "pskreporter:{call}"),
+			source => write!(fmtr, "{}", source.path().unwrap_or(Path::new("-")).display()),
+		}
+	}
+}
+
+/// Format of the file being read
+enum Format {
+	/// Spots in a layout detected from the first row
+	Wsprnet(Option<Box<Schema>>),
+	/// Spots as JSON objects
+	Json,
+	/// Spots converted while reading
+	#[cfg(any(feature = "parquet", feature = "sqlite", feature = "mqtt", feature = "fetch", unix))]
+	Converted,
+	AllWspr(Decodes),
+	/// Decodes printed by `wsprd` on the given date
+	Wsprd(Decodes, NaiveDate),
+}
+
+/// Timestamp of a row in WSPRnet CSV format, or zero if there is none
+fn timestamp(row: &str) -> u64 {
+	row.split(',').nth(1).and_then(|time| time.parse().ok()).unwrap_or(0)
+}
+
+/// Rows of several inputs merged in order of time
+struct Merge {
+	inputs: Vec<Lines>,
+	/// Next row of each input by time, position of the input and line number
+	heads: BinaryHeap<Reverse<(u64, usize, u64, String)>>,
+	/// Inputs to read the next row from
+	pending: Vec<usize>,
+	/// Input and line number of the row taken last
+	last: Option<(usize, u64)>,
+}
+
+impl Merge {
+	fn new(inputs: Vec<Lines>) -> Self {
+		Merge { pending: (0..inputs.len()).collect(), inputs, heads: BinaryHeap::new(), last: None }
+	}
+
+	/// Location of the row taken last
+	fn position(&self) -> Option<std::string::String> {
+		let (idx, line) = self.last?;
+		Some(format!("{}:{line}", self.inputs[idx].name.as_ref()?))
+	}
+}
+
+impl Iterator for Merge {
+	type Item = io::Result<String>;
+
+	/// Take the earliest of the next rows of all inputs
+	///
+	/// Rows of each input are taken in the order read, and inputs given earlier go first at the
+	/// same time.
+	fn next(&mut self) -> Option<Self::Item> {
+		while let Some(idx) = self.pending.pop() {
+			match self.inputs[idx].next() {
+				Some(Ok(row)) => self.heads.push(Reverse((timestamp(&row), idx, self.inputs[idx].line, row))),
+				Some(Err(err)) => return Some(Err(err)),
+				None => (),
+			}
+		}
+
+		let Reverse((_, idx, line, row)) = self.heads.pop()?;
+		self.pending.push(idx);
+		self.last = Some((idx, line));
+		Some(Ok(row))
+	}
+}
+
+/// Lines of a sequence of input files in WSPRnet CSV format, merged in order of time or read one
+/// after another
+///
+/// Lines of other formats are converted.
+pub struct Lines {
+	sources: VecDeque<Source>,
+	/// MQTT topics subscribed to once all sources have been read
+	#[cfg(feature = "mqtt")]
+	subscriptions: Vec<Subscription>,
+	/// Unix domain sockets listened at once all sources have been read
+	#[cfg(unix)]
+	sockets: Vec<PathBuf>,
+	/// Set when a client of the sockets requests to flush
+	#[cfg(unix)]
+	flush: Rc<Cell<bool>>,
+	current: Option<Rows>,
+	/// Name of the current source
+	name: Option<std::string::String>,
+	/// Number of lines read from the current source
+	line: u64,
+	/// Format of the current file
+	format: Format,
+	/// Number of local decodes read, shared between merged inputs
+	num_decodes: Rc<Cell<u64>>,
+	/// Format of spot files
+	input_format: InputFormat,
+	/// Layout of spot files overriding detection
+	columns: Option<Schema>,
+	/// Field delimiter of spot files overriding detection
+	delimiter: Option<char>,
+	/// Time window of interest
+	#[cfg(feature = "parquet")]
+	window: (Option<u64>, Option<u64>),
+	/// Query for spots in SQLite databases
+	#[cfg(feature = "sqlite")]
+	query: std::string::String,
+	/// Keep reading the last file as it grows
+	follow: bool,
+	/// Merge the sources in order of time
+	merge: bool,
+	/// Sources being merged
+	merged: Option<Merge>,
+	progress: Option<Progress>,
+	/// Counter of bytes read for the progress
+	counter: Option<Rc<Cell<u64>>>,
+}
+
+impl Lines {
+	/// Read the given spot dumps, other sources and local decode files, or standard input if there
+	/// are none
+	///
+	/// MQTT topics are subscribed to and Unix domain sockets listened at last, as they never end.
+	pub fn new<I: IntoIterator<Item = PathBuf>>(paths: I, inputs: Vec<Input>, all_wspr: Vec<Decodes>, wsprd: Vec<Decodes>) -> Self {
+		#[cfg(feature = "mqtt")]
+		let mut subscriptions = Vec::new();
+		#[cfg(unix)]
+		let mut sockets = Vec::new();
+
+		let mut sources: VecDeque<_> = paths.into_iter().map(Source::Wsprnet).collect();
+
+		#[cfg_attr(not(any(feature = "sqlite", feature = "mqtt", unix)), allow(clippy::never_loop))]
+		for input in inputs {
+			match input {
+				#[cfg(feature = "sqlite")]
+				Input::Sqlite(path) => sources.push_back(Source::Sqlite(path)),
+				#[cfg(feature = "mqtt")]
+				Input::Mqtt(subscription) => subscriptions.push(subscription),
+				#[cfg(unix)]
+				Input::Socket(path) => sockets.push(path),
+				#[cfg(feature = "fetch")]
+				Input::PskReporter(call) => sources.push_back(Source::PskReporter(call)),
+			}
+		}
+
+		sources.extend(all_wspr.into_iter().map(Source::AllWspr));
+		sources.extend(wsprd.into_iter().map(Source::Wsprd));
+
+		let stdin = sources.is_empty();
+		#[cfg(feature = "mqtt")]
+		let stdin = stdin && subscriptions.is_empty();
+		#[cfg(unix)]
+		let stdin = stdin && sockets.is_empty();
+
+		if stdin {
+			sources.push_back(Source::Wsprnet(PathBuf::from("-")));
+		}
+
+		Lines {
+			sources,
+			#[cfg(feature = "mqtt")]
+			subscriptions,
+			#[cfg(unix)]
+			sockets,
+			#[cfg(unix)]
+			flush: Rc::new(Cell::new(false)),
+			current: None,
+			name: None,
+			line: 0,
+			format: Format::Wsprnet(None),
+			num_decodes: Rc::new(Cell::new(0)),
+			input_format: InputFormat::Csv,
+			columns: None,
+			delimiter: None,
+			#[cfg(feature = "parquet")]
+			window: (None, None),
+			#[cfg(feature = "sqlite")]
+			query: std::string::String::new(),
+			follow: false,
+			merge: false,
+			merged: None,
+			progress: None,
+			counter: None,
+		}
+	}
+
+	/// Read lines from an already open source
+	#[cfg(feature = "fetch")]
+	pub fn reader(reader: Box<dyn BufRead>) -> Self {
+		Lines {
+			sources: VecDeque::new(),
+			#[cfg(feature = "mqtt")]
+			subscriptions: Vec::new(),
+			#[cfg(unix)]
+			sockets: Vec::new(),
+			#[cfg(unix)]
+			flush: Rc::new(Cell::new(false)),
+			current: Some(Box::new(reader.lines())),
+			name: None,
+			line: 0,
+			format: Format::Wsprnet(None),
+			num_decodes: Rc::new(Cell::new(0)),
+			input_format: InputFormat::Csv,
+			columns: None,
+			delimiter: None,
+			#[cfg(feature = "parquet")]
+			window: (None, None),
+			#[cfg(feature = "sqlite")]
+			query: std::string::String::new(),
+			follow: false,
+			merge: false,
+			merged: None,
+			progress: None,
+			counter: None,
+		}
+	}
+
+	/// Read spot files in the given format
+	pub fn input_format(mut self, input_format: InputFormat) -> Self {
+		self.input_format = input_format;
+		self
+	}
+
+	/// Read spot files in the given layout instead of detecting it
+	///
+	/// A header row is skipped nonetheless.
+	pub fn columns(mut self, columns: Option<Schema>) -> Self {
+		self.columns = columns;
+		self
+	}
+
+	/// Split the rows of spot files at the given delimiter instead of detecting it
+	pub fn delimiter(mut self, delimiter: Option<char>) -> Self {
+		self.delimiter = delimiter;
+		self
+	}
+
+	/// Skip parts of Parquet files entirely outside the given time window
+	#[cfg(feature = "parquet")]
+	pub fn window(mut self, since: Option<u64>, until: Option<u64>) -> Self {
+		self.window = (since, until);
+		self
+	}
+
+	/// Query SQLite databases for spots with the given statement
+	#[cfg(feature = "sqlite")]
+	pub fn query(mut self, query: std::string::String) -> Self {
+		self.query = query;
+		self
+	}
+
+	/// Merge the sources by timestamp instead of reading them one after another
+	///
+	/// Sources are never merged while following the last file.
+	pub fn merge(mut self, merge: bool) -> Self {
+		self.merge = merge;
+		self
+	}
+
+	/// Sources read separately for merging, sharing the configuration
+	fn split(&mut self) -> Vec<Lines> {
+		self.sources.drain(..).map(|source| Lines {
+			sources: VecDeque::from([source]),
+			#[cfg(feature = "mqtt")]
+			subscriptions: Vec::new(),
+			#[cfg(unix)]
+			sockets: Vec::new(),
+			#[cfg(unix)]
+			flush: self.flush.clone(),
+			current: None,
+			name: None,
+			line: 0,
+			format: Format::Wsprnet(None),
+			num_decodes: self.num_decodes.clone(),
+			input_format: self.input_format,
+			columns: self.columns.clone(),
+			delimiter: self.delimiter,
+			#[cfg(feature = "parquet")]
+			window: self.window,
+			#[cfg(feature = "sqlite")]
+			query: self.query.clone(),
+			follow: false,
+			merge: false,
+			merged: None,
+			progress: None,
+			counter: self.counter.clone(),
+		}).collect()
+	}
+
+	/// Keep waiting for lines to be appended to the last file, without reporting progress
+	///
+	/// Iteration ends whenever no complete line is available, and may be resumed later.
+	pub fn follow(mut self, follow: bool) -> Self {
+		self.follow = follow;
+		if follow {
+			self.progress = None;
+		}
+
+		self
+	}
+
+	/// Lines of an input file
+	fn rows(&self, reader: Box<dyn BufRead>) -> Rows {
+		Box::new(Text { reader, pending: Vec::new(), follow: self.follow, name: self.name.clone() })
+	}
+
+	/// Report progress on standard error if enabled and the input size is known
+	///
+	/// Progress is never reported when reading from standard input, Parquet files, MQTT topics or
+	/// Unix domain sockets, or in quiet mode.
+	pub fn progress(mut self, enabled: bool) -> Self {
+		if !enabled || !log_enabled!(Level::Warn) {
+			return self;
+		}
+
+		#[cfg(feature = "mqtt")]
+		if !self.subscriptions.is_empty() {
+			return self;
+		}
+
+		#[cfg(unix)]
+		if !self.sockets.is_empty() {
+			return self;
+		}
+
+		#[cfg(feature = "parquet")]
+		if self.input_format == InputFormat::Parquet {
+			return self;
+		}
+
+		let mut total = 0;
+		for source in &self.sources {
+			#[cfg(feature = "sqlite")]
+			if let Source::Sqlite(_) = source {
+				return self;
+			}
+
+			let Some(path) = source.path() else {
+				return self;
+			};
+
+			match path.metadata() {
+				Ok(meta) if path.as_os_str() != "-" && meta.is_file() => total += meta.len(),
+				_ => return self,
+			}
+		}
+
+		self.progress = Progress::new(total);
+		self.counter = self.progress.as_ref().map(Progress::counter);
+		self
+	}
+
+	/// Location of the row returned last as source and line number, such as `spots.csv:42`
+	///
+	/// Converted sources count rows instead of lines.
+	pub fn position(&self) -> Option<std::string::String> {
+		match &self.merged {
+			Some(merged) => merged.position(),
+			None => Some(format!("{}:{}", self.name.as_ref()?, self.line)),
+		}
+	}
+
+	/// Whether a client of the Unix domain sockets requested to flush since the last call
+	///
+	/// Requests are only noticed while following.
+	#[cfg(unix)]
+	pub fn flushed(&self) -> bool {
+		self.flush.replace(false)
+	}
+}
+
+impl Iterator for Lines {
+	type Item = io::Result<String>;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		if self.merge && !self.follow && self.sources.len() > 1 {
+			self.merged = Some(Merge::new(self.split()));
+		}
+
+		loop {
+			if let Some(merged) = &mut self.merged {
+				match merged.next() {
+					Some(row) => {
+						if let Some(progress) = &mut self.progress {
+							progress.advance();
+						}
+
+						return Some(row);
+					},
+					None => self.merged = None,
+				}
+			}
+
+			if let Some(line) = self.current.as_mut().and_then(Iterator::next) {
+				self.line += 1;
+				if let Some(progress) = &mut self.progress {
+					progress.advance();
+				}
+
+				let Ok(line) = line else {
+					return Some(line);
+				};
+
+				// Unconvertible lines are passed on to fail parsing
+				let row = match &mut self.format {
+					Format::Wsprnet(Some(schema)) => schema.convert(&line),
+					Format::Wsprnet(schema) => {
+						let delimiter = self.delimiter.unwrap_or_else(|| crate::schema::delimiter(&line));
+						match (Schema::header(&line, delimiter), &self.columns) {
+							(Some(_), Some(columns)) => {
+								*schema = Some(Box::new(columns.clone().delimiter(delimiter)));
+								continue;
+							},
+							(Some(Ok(header)), None) => {
+								*schema = Some(Box::new(header));
+								continue;
+							},
+							(Some(Err(err)), None) => return Some(Err(io::Error::new(io::ErrorKind::InvalidData,
+								format!("Invalid header “{line}”: {err}")))),
+							(None, columns) => schema.insert(Box::new(columns.clone().map_or_else(|| Schema::detect(&line, delimiter),
+								|columns| columns.delimiter(delimiter)))).convert(&line),
+						}
+					},
+					Format::Json if line.trim().is_empty() => continue,
+					Format::Json => json::convert(&line),
+					#[cfg(any(feature = "parquet", feature = "sqlite", feature = "mqtt", feature = "fetch", unix))]
+					Format::Converted => None,
+					Format::AllWspr(decodes) => {
+						self.num_decodes.set(self.num_decodes.get() + 1);
+						decodes::convert(&line, decodes, self.num_decodes.get())
+					},
+					Format::Wsprd(_, _) if line.starts_with("<DecodeFinished>") => continue,
+					Format::Wsprd(decodes, date) => {
+						self.num_decodes.set(self.num_decodes.get() + 1);
+						decodes::convert_wsprd(&line, decodes, *date, self.num_decodes.get())
+					},
+				};
+
+				return Some(Ok(row.unwrap_or(line)));
+			}
+
+			let Some(source) = self.sources.pop_front() else {
+				#[cfg(feature = "mqtt")]
+				if !self.subscriptions.is_empty() {
+					self.current = Some(crate::mqtt::rows(std::mem::take(&mut self.subscriptions)));
+					self.name = None;
+					self.format = Format::Converted;
+					continue;
+				}
+
+				#[cfg(unix)]
+				if !self.sockets.is_empty() {
+					match crate::socket::rows(&std::mem::take(&mut self.sockets), self.follow, self.flush.clone()) {
+						Ok(rows) => self.current = Some(rows),
+						Err(err) => return Some(Err(err)),
+					}
+
+					self.name = None;
+					self.format = Format::Converted;
+					continue;
+				}
+
+				if let Some(progress) = self.progress.take() {
+					progress.finish();
+				}
+
+				return None;
+			};
+
+			self.name = Some(source.to_string());
+			self.line = 0;
+
+			let counter = self.counter.clone();
+			let rows: io::Result<Rows> = match (&source, self.input_format) {
+				(Source::Wsprnet(path), InputFormat::Json) => open(path, counter)
+					.and_then(json::split)
+					.map(|reader| Box::new(reader.lines()) as Rows),
+				#[cfg(feature = "parquet")]
+				(Source::Wsprnet(path), InputFormat::Parquet) => crate::parquet::rows(path, self.window.0, self.window.1),
+				#[cfg(feature = "sqlite")]
+				(Source::Sqlite(path), _) => Ok(crate::sqlite::rows(path.clone(), self.query.clone())),
+				#[cfg(feature = "fetch")]
+				(Source::PskReporter(call), _) => crate::pskreporter::rows(call),
+				(Source::Wsprnet(path), _) => open(path, counter).map(|reader| self.rows(reader)),
+				(Source::AllWspr(decodes) | Source::Wsprd(decodes), _) => open(&decodes.path, counter).map(|reader| self.rows(reader)),
+			};
+
+			match rows {
+				Ok(rows) => self.current = Some(rows),
+				Err(err) => return Some(Err(io::Error::new(err.kind(), format!("{source}: {err}")))),
+			}
+
+			self.format = match source {
+				Source::Wsprnet(_) => match self.input_format {
+					InputFormat::Csv => Format::Wsprnet(None),
+					InputFormat::Jsonl | InputFormat::Json => Format::Json,
+					#[cfg(feature = "parquet")]
+					InputFormat::Parquet => Format::Converted,
+				},
+				#[cfg(feature = "sqlite")]
+				Source::Sqlite(_) => Format::Converted,
+				#[cfg(feature = "fetch")]
+				Source::PskReporter(_) => Format::Converted,
+				Source::AllWspr(decodes) => Format::AllWspr(decodes),
+				Source::Wsprd(decodes) => match decodes::wsprd_date(&decodes.path) {
+					Ok(date) => Format::Wsprd(decodes, date),
+					Err(err) => return Some(Err(io::Error::new(err.kind(),
+						format!("{}: {}", decodes.path.display(), err)))),
+				},
+			};
+		}
+	}
+}
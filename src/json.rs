@@ -0,0 +1,170 @@
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::io::prelude::*;
+
+use serde_json::{Map, Value, json};
+
+use crate::{Band, Call, Frequency, Power, Qso};
+use crate::adif::RecordOptions;
+use crate::output::Sink;
+use crate::schema;
+
+/// Convert a JSON object describing a spot into a row in WSPRnet CSV format
+///
+/// Fields are looked up by the same names as columns in a header row. Numbers may be given as
+/// strings, as ClickHouse does for 64‐bit integers.
+pub fn convert(line: &str) -> Option<String> {
+	let object: Map<String, Value> = serde_json::from_str(line).ok()?;
+
+	Some(schema::row(object.iter().filter_map(|(name, value)| match value {
+		Value::String(value) => Some((name.as_str(), value.clone())),
+		Value::Number(value) => Some((name.as_str(), value.to_string())),
+		_ => None,
+	})))
+}
+
+/// Split a JSON document into lines holding one spot object each
+///
+/// The document is either an array of spots or an object with a `data` array of spots, as written
+/// by the `JSON` output format of ClickHouse.
+pub fn split(reader: impl Read) -> io::Result<Box<dyn BufRead>> {
+	let spots = match serde_json::from_reader(reader)? {
+		Value::Array(spots) => spots,
+		Value::Object(mut object) => match object.remove("data") {
+			Some(Value::Array(spots)) => spots,
+			_ => return Err(io::Error::new(io::ErrorKind::InvalidData, "Missing data array")),
+		},
+		_ => return Err(io::Error::new(io::ErrorKind::InvalidData, "Expected array of spots")),
+	};
+
+	let mut lines = Vec::new();
+	for spot in spots {
+		serde_json::to_writer(&mut lines, &spot)?;
+		lines.push(b'\n');
+	}
+
+	Ok(Box::new(io::Cursor::new(lines)))
+}
+
+/// Log of QSOs in JSON Lines format, one object per QSO followed by a summary object
+pub struct Objects<W: Write> {
+	out: W,
+	/// Number of QSOs written
+	num_qsos: usize,
+	/// Unique contact call signs
+	contacts: HashSet<Call>,
+	/// Transmit bands of the operators, with a frequency for sorting
+	bands: HashMap<std::string::String, Frequency>,
+}
+
+impl<W: Write> Objects<W> {
+	pub fn new(out: W) -> Self {
+		Objects { out, num_qsos: 0, contacts: HashSet::new(), bands: HashMap::new() }
+	}
+
+	/// Write a QSO object with the same fields as the columns of CSV logs
+	pub fn write(&mut self, qso: &Qso, options: &RecordOptions) -> io::Result<()> {
+		let band = Band::try_from(qso.freq_op).ok().as_ref().map(Band::name);
+		let dbm = |power: Power| power.is_known().then_some(power.0);
+
+		let mut object = json!({
+			"my_call": qso.call_op.as_str(),
+			"my_grid": qso.grid_op.as_str(),
+			"call": qso.call_ct.as_str(),
+			"grid": qso.grid_ct.as_str(),
+			"time_on": qso.datetime_on().format("%Y-%m-%dT%H:%M:%SZ").to_string(),
+			"time_off": qso.datetime_off().format("%Y-%m-%dT%H:%M:%SZ").to_string(),
+			"band": band,
+			"frequency": qso.freq_op.mhz(),
+			"rx_frequency": qso.freq_ct.mhz(),
+			"mode": qso.mode.to_string(),
+			"my_snr": qso.snr_op,
+			"snr": qso.snr_ct,
+			"my_snr_min": qso.snr_op_min,
+			"snr_min": qso.snr_ct_min,
+			"my_snr_mean": (qso.snr_op_mean() * 10.0).round() / 10.0,
+			"snr_mean": (qso.snr_ct_mean() * 10.0).round() / 10.0,
+			"my_power_dbm": dbm(qso.power_op),
+			"power_dbm": dbm(qso.power_ct),
+			"my_drift": qso.drift_op,
+			"drift": qso.drift_ct,
+			"distance": qso.distance,
+			"spots": qso.spots.len(),
+			"rx_spots": qso.num_rx,
+			"tx_spots": qso.num_tx,
+			"spot_ids": qso.spots,
+			"serial": qso.serial,
+			"qso_id": qso.id(),
+		});
+
+		// Extra columns as measured by the operator and the contact, respectively
+		if let Value::Object(fields) = &mut object {
+			for (idx, name) in options.extra_columns.iter().enumerate() {
+				let name = name.to_ascii_lowercase();
+				fields.insert(format!("my_{name}"), qso.extra_op.get(idx).map(|value| value.as_str()).into());
+				fields.insert(name, qso.extra_ct.get(idx).map(|value| value.as_str()).into());
+			}
+		}
+
+		serde_json::to_writer(&mut self.out, &object)?;
+		writeln!(self.out)?;
+
+		if let Some(band) = band {
+			self.bands.entry(band).or_insert(qso.freq_op);
+		}
+
+		self.contacts.insert(qso.call_ct.clone());
+		self.num_qsos += 1;
+		Ok(())
+	}
+
+	/// Write the summary object of the QSOs written so far
+	pub fn finish(mut self) -> io::Result<W> {
+		let mut bands: Vec<_> = self.bands.into_iter().collect();
+		bands.sort_by_key(|&(_, freq)| freq);
+
+		let summary = json!({
+			"summary": {
+				"qsos": self.num_qsos,
+				"contacts": self.contacts.len(),
+				"bands": bands.into_iter().map(|(band, _)| band).collect::<Vec<_>>(),
+			}
+		});
+
+		serde_json::to_writer(&mut self.out, &summary)?;
+		writeln!(self.out)?;
+		Ok(self.out)
+	}
+}
+
+impl Objects<Sink> {
+	/// Make the QSOs written so far available, keeping the counts for the summary
+	pub fn checkpoint(self) -> io::Result<Self> {
+		Ok(Objects { out: self.out.checkpoint()?, ..self })
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn converts_spot_objects() {
+		let row = convert(r#"{"id":"2736249418","time":"2021-01-12 21:00:00","rx_sign":"DO5EU","rx_loc":"JO62qm","snr":-29,
+			"frequency":7040022,"tx_sign":"DP0GVN","tx_loc":"IB59ui","power":27,"drift":0,"distance":13805,"rx_lat":52.5,"band":null}"#);
+		assert_eq!(row.as_deref(), Some("2736249418,1610485200,DO5EU,JO62qm,-29,7.040022,DP0GVN,IB59ui,27,0,13805,,,,,"));
+
+		assert_eq!(convert("[1, 2]"), None);
+		assert_eq!(convert("not json"), None);
+	}
+
+	#[test]
+	fn splits_documents() {
+		let lines = |document: &str| split(document.as_bytes()).map(|reader| reader.lines().collect::<io::Result<Vec<_>>>().unwrap());
+
+		assert_eq!(lines(r#"[{"id":1},{"id":2}]"#).unwrap(), [r#"{"id":1}"#, r#"{"id":2}"#]);
+		assert_eq!(lines(r#"{"meta":[],"data":[{"id":1}],"rows":1}"#).unwrap(), [r#"{"id":1}"#]);
+		assert_eq!(lines(r#"{"meta":[]}"#).unwrap_err().kind(), io::ErrorKind::InvalidData);
+		assert_eq!(lines("1").unwrap_err().kind(), io::ErrorKind::InvalidData);
+	}
+}
@@ -0,0 +1,154 @@
+/// Mean radius of the Earth in km
+const EARTH_RADIUS: f64 = 6371.0;
+
+/// Position of the centre of a Maidenhead locator as latitude and longitude in degrees
+///
+/// Locators of two to twelve characters are accepted. Returns `None` for malformed locators.
+pub fn position(grid: &str) -> Option<(f64, f64)> {
+	let grid = grid.as_bytes();
+	if grid.len() < 2 || grid.len() > 12 || !grid.len().is_multiple_of(2) {
+		return None;
+	}
+
+	// Size of the current square in degrees of longitude and latitude
+	let (mut lon_size, mut lat_size) = (20.0, 10.0);
+	let (mut lon, mut lat) = (-180.0, -90.0);
+
+	for (pair, chunk) in grid.chunks(2).enumerate() {
+		let (x, y, divisions) = match pair {
+			// Fields
+			0 => (chunk[0].to_ascii_uppercase().checked_sub(b'A')?, chunk[1].to_ascii_uppercase().checked_sub(b'A')?, 18),
+			// Subsquares and extended subsquares
+			2 | 4 => (chunk[0].to_ascii_uppercase().checked_sub(b'A')?, chunk[1].to_ascii_uppercase().checked_sub(b'A')?, 24),
+			// Squares and extended squares
+			_ => (chunk[0].checked_sub(b'0')?, chunk[1].checked_sub(b'0')?, 10),
+		};
+
+		if x >= divisions || y >= divisions {
+			return None;
+		}
+
+		if pair > 0 {
+			lon_size /= divisions as f64;
+			lat_size /= divisions as f64;
+		}
+
+		lon += x as f64 * lon_size;
+		lat += y as f64 * lat_size;
+	}
+
+	Some((lat + lat_size / 2.0, lon + lon_size / 2.0))
+}
+
+/// Great circle distance between two locators in km
+pub fn distance(a: &str, b: &str) -> Option<f64> {
+	let (lat_a, lon_a) = position(a)?;
+	let (lat_b, lon_b) = position(b)?;
+	let (lat_a, lon_a, lat_b, lon_b) = (lat_a.to_radians(), lon_a.to_radians(), lat_b.to_radians(), lon_b.to_radians());
+
+	// Haversine formula
+	let h = ((lat_b - lat_a) / 2.0).sin().powi(2) +
+	        lat_a.cos() * lat_b.cos() * ((lon_b - lon_a) / 2.0).sin().powi(2);
+
+	Some(2.0 * EARTH_RADIUS * h.sqrt().asin())
+}
+
+/// Initial bearing of the great circle path from one locator to another in degrees clockwise from
+/// true north
+pub fn bearing(a: &str, b: &str) -> Option<f64> {
+	let (lat_a, lon_a) = position(a)?;
+	let (lat_b, lon_b) = position(b)?;
+	let (lat_a, lat_b, delta) = (lat_a.to_radians(), lat_b.to_radians(), (lon_b - lon_a).to_radians());
+
+	let y = delta.sin() * lat_b.cos();
+	let x = lat_a.cos() * lat_b.sin() - lat_a.sin() * lat_b.cos() * delta.cos();
+
+	Some(y.atan2(x).to_degrees().rem_euclid(360.0))
+}
+
+/// Points along the great circle between two positions given as latitude and longitude in degrees
+///
+/// The path is divided into the given number of segments and includes both ends.
+pub fn great_circle(a: (f64, f64), b: (f64, f64), segments: usize) -> Vec<(f64, f64)> {
+	let vector = |(lat, lon): (f64, f64)| {
+		let (lat, lon) = (f64::to_radians(lat), f64::to_radians(lon));
+		(lat.cos() * lon.cos(), lat.cos() * lon.sin(), lat.sin())
+	};
+
+	let (xa, ya, za) = vector(a);
+	let (xb, yb, zb) = vector(b);
+	let angle = (xa * xb + ya * yb + za * zb).clamp(-1.0, 1.0).acos();
+
+	// Antipodes have no unique great circle, and close points need no intermediate ones
+	if angle < 1e-9 || (std::f64::consts::PI - angle) < 1e-9 || segments < 2 {
+		return vec![a, b];
+	}
+
+	(0..=segments).map(|idx| {
+		let frac = idx as f64 / segments as f64;
+		let wa = ((1.0 - frac) * angle).sin() / angle.sin();
+		let wb = (frac * angle).sin() / angle.sin();
+		let (x, y, z) = (wa * xa + wb * xb, wa * ya + wb * yb, wa * za + wb * zb);
+		(z.atan2(x.hypot(y)).to_degrees(), y.atan2(x).to_degrees())
+	}).collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn assert_near(value: Option<f64>, expected: f64, tolerance: f64) {
+		let value = value.unwrap();
+		assert!((value - expected).abs() <= tolerance, "{value} is not {expected}");
+	}
+
+	#[test]
+	fn finds_centre_of_locators() {
+		assert_eq!(position("JO"), Some((55.0, 10.0)));
+		assert_eq!(position("JO62"), Some((52.5, 13.0)));
+		assert_eq!(position("jo62QM"), position("JO62qm"));
+
+		let (lat, lon) = position("JO62qm").unwrap();
+		assert!((lat - 52.520_833).abs() < 1e-6 && (lon - 13.375).abs() < 1e-6);
+
+		let (lat, lon) = position("JO62qm54ab12").unwrap();
+		assert!((lat - 52.520_833).abs() < 0.01 && (lon - 13.375).abs() < 0.05);
+	}
+
+	#[test]
+	fn rejects_malformed_locators() {
+		for grid in ["", "J", "JO6", "SA", "JO6A", "JO62qy", "JO62qm5", "JO62qm54ab12cd"] {
+			assert_eq!(position(grid), None, "{grid}");
+		}
+	}
+
+	#[test]
+	fn computes_great_circle_distances() {
+		assert_eq!(distance("JO62qm", "JO62qm"), Some(0.0));
+		assert_near(distance("JO62qm", "IB59ui"), 13805.0, 0.5);
+		assert_near(distance("JJ00", "JJ01"), EARTH_RADIUS * 1f64.to_radians(), 1e-6);
+		assert_eq!(distance("JO62qm", "JO6"), None);
+	}
+
+	#[test]
+	fn computes_initial_bearings() {
+		assert_near(bearing("JJ00", "JJ01"), 0.0, 1e-9);
+		assert_near(bearing("JJ01", "JJ00"), 180.0, 1e-9);
+		assert_near(bearing("JJ00", "JJ10"), 90.0, 0.01);
+		assert_near(bearing("JJ10", "JJ00"), 270.0, 0.01);
+		assert_near(bearing("JO62qm", "IB59ui"), 189.0, 0.5);
+		assert_eq!(bearing("JO62qm", "JO6"), None);
+	}
+
+	#[test]
+	fn divides_great_circles() {
+		let path = great_circle((0.0, 0.0), (0.0, 90.0), 3);
+		assert_eq!(path.len(), 4);
+		for ((lat, lon), expected) in path.into_iter().zip([0.0, 30.0, 60.0, 90.0]) {
+			assert!(lat.abs() < 1e-9 && (lon - expected).abs() < 1e-9, "({lat}, {lon})");
+		}
+
+		assert_eq!(great_circle((52.5, 13.4), (52.5, 13.4), 10), [(52.5, 13.4), (52.5, 13.4)]);
+		assert_eq!(great_circle((0.0, 0.0), (0.0, 180.0), 10), [(0.0, 0.0), (0.0, 180.0)]);
+	}
+}
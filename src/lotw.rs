@@ -0,0 +1,142 @@
+use std::io;
+use std::io::prelude::*;
+use std::path::PathBuf;
+use std::process::Command;
+
+use clap::Args;
+use log::{info, warn};
+use tempfile::NamedTempFile;
+
+use crate::Qso;
+use crate::adif::{Adif, Logged, Record, RecordOptions};
+use crate::output::LogFile;
+
+/// Signing and uploading the QSOs to LoTW with TQSL
+#[derive(Args, Clone, Debug)]
+pub struct LotwOptions {
+	/// Sign the QSOs with TQSL using this station location and upload them to LoTW once done
+	#[arg(long, value_name = "LOCATION", requires = "lotw_uploaded", conflicts_with = "dry_run", env = "WSPRSPOTS_LOTW")]
+	pub lotw: Option<std::string::String>,
+
+	/// ADIF log of the QSOs uploaded to LoTW, which are not uploaded again
+	#[arg(long, value_name = "FILE", env = "WSPRSPOTS_LOTW_UPLOADED")]
+	pub lotw_uploaded: Option<PathBuf>,
+
+	/// TQSL executable
+	#[arg(long, value_name = "PATH", default_value = "tqsl", env = "WSPRSPOTS_TQSL")]
+	pub tqsl: PathBuf,
+
+	/// Password of the certificate of the station location, if it has one
+	#[arg(long, value_name = "PASSWORD", env = "WSPRSPOTS_TQSL_PASSWORD", hide_env_values = true)]
+	pub tqsl_password: Option<std::string::String>,
+}
+
+/// Meaning of the exit codes of TQSL
+fn describe(code: i32) -> &'static str {
+	match code {
+		1 => "cancelled by the user",
+		2 => "rejected by LoTW",
+		3 => "unexpected response from LoTW",
+		4 => "TQSL error",
+		5 => "TQSLlib error",
+		6 => "unable to open the input file",
+		7 => "unable to open the output file",
+		10 => "command syntax error",
+		11 => "LoTW connection error",
+		_ => "unknown error",
+	}
+}
+
+/// Upload of the QSOs not uploaded before
+///
+/// The records are collected while logging and handed to TQSL once finished. Only if LoTW accepted
+/// them are they added to the log of uploaded QSOs, so that a failed upload is retried by the next
+/// run.
+pub struct Upload {
+	location: std::string::String,
+	/// Log of the QSOs uploaded before
+	path: PathBuf,
+	options: LotwOptions,
+	record: RecordOptions,
+	/// QSOs uploaded before
+	uploaded: Logged,
+	/// Records of the QSOs to upload
+	records: Vec<std::string::String>,
+	title: std::string::String,
+}
+
+impl Upload {
+	/// Prepare an upload, reading the log of QSOs uploaded before if there is one
+	pub fn new(options: LotwOptions, record: RecordOptions, tolerance: u64, title: &str) -> io::Result<Self> {
+		let (Some(location), Some(path)) = (options.lotw.clone(), options.lotw_uploaded.clone()) else {
+			return Err(io::Error::new(io::ErrorKind::InvalidInput, "Uploading to LoTW requires --lotw and --lotw-uploaded"));
+		};
+
+		let mut uploaded = Logged::new(tolerance);
+		match uploaded.read(&path) {
+			Err(err) if err.kind() == io::ErrorKind::NotFound => info!("No QSOs uploaded to LoTW before"),
+			result => result?,
+		}
+
+		Ok(Upload { location, path, options, record, uploaded, records: Vec::new(), title: title.to_owned() })
+	}
+
+	/// Add a QSO unless it was uploaded before
+	pub fn write(&mut self, qso: &Qso) {
+		if self.uploaded.contains(qso) {
+			info!("Skipped QSO between {} and {} already uploaded to LoTW", qso.call_op, qso.call_ct);
+			return;
+		}
+
+		self.records.push(Record { qso, options: &self.record }.to_string());
+	}
+
+	/// Write the records to an ADIF log, with a header unless continuing an existing log
+	fn write_log(&self, mut out: impl Write, header: bool) -> io::Result<()> {
+		if header {
+			Adif::new(&mut out, &self.title)?;
+		}
+
+		for record in &self.records {
+			writeln!(out, "{record}")?;
+		}
+
+		out.flush()
+	}
+
+	/// Sign and upload the QSOs with TQSL, and add them to the log of uploaded QSOs once accepted
+	pub fn finish(self) -> io::Result<()> {
+		if self.records.is_empty() {
+			info!("No new QSOs to upload to LoTW");
+			return Ok(());
+		}
+
+		let mut log = NamedTempFile::with_suffix(".adi")?;
+		self.write_log(io::BufWriter::new(log.as_file_mut()), true)?;
+
+		info!("Uploading {} QSOs to LoTW from station location {}", self.records.len(), self.location);
+
+		let mut command = Command::new(&self.options.tqsl);
+		command.args(["-x", "-q", "-d", "-u", "-a", "compliant", "-l", &self.location]);
+		if let Some(password) = &self.options.tqsl_password {
+			command.args(["-p", password]);
+		}
+
+		let status = command.arg(log.path()).status()
+			.map_err(|err| io::Error::new(err.kind(), format!("Cannot run {}: {err}", self.options.tqsl.display())))?;
+
+		match status.code() {
+			Some(0) => info!("Uploaded {} QSOs to LoTW", self.records.len()),
+			// QSOs that are duplicates or outside the date range of the location will not be accepted
+			// on another try either
+			Some(8) => warn!("All QSOs were already uploaded to LoTW or outside the date range of the station location"),
+			Some(9) => warn!("Some QSOs were already uploaded to LoTW or outside the date range of the station location"),
+			Some(code) => return Err(io::Error::other(format!("Uploading to LoTW failed: {} (exit code {code})", describe(code)))),
+			None => return Err(io::Error::other("TQSL was terminated by a signal")),
+		}
+
+		let (mut file, existing) = LogFile::create(&self.path, true)?;
+		self.write_log(&mut file, !existing)?;
+		file.persist()
+	}
+}
@@ -1,21 +1,115 @@
-#![feature(exclusive_range_pattern, hash_drain_filter)]
+mod adif;
+use crate::adif::Logged;
+
+mod bandplan;
+
+mod cabrillo;
+
+mod checkpoint;
+use crate::checkpoint::{Decode, Encode};
+
+#[cfg(feature = "fetch")]
+mod clublog;
+
+mod cluster;
+
+mod cli;
+use crate::cli::{Calls, Cli, Command, ConvertArgs, DefaultMode, FilterArgs, InputArgs, MatchOptions, SpotFilterArgs, SpotFormat, StatsArgs};
+
+mod config;
+
+mod decodes;
+
+mod dedup;
+use crate::dedup::{Dedup, Repeats};
+
+mod diagnostics;
 
 mod excluded;
-use crate::excluded::EXCLUDED;
 
+#[cfg(feature = "fetch")]
+mod fetch;
+
+mod filter;
+use crate::filter::Filter;
+
+#[cfg(feature = "fetch")]
+mod hrdlog;
+
+mod input;
+
+mod json;
+
+mod locator;
+
+mod lotw;
+
+mod map;
+
+mod matcher;
+use crate::matcher::{GridPolicy, Matcher, Matching, PairMatcher, Settings};
+
+#[cfg(feature = "mqtt")]
+mod mqtt;
+
+mod oneway;
+use crate::oneway::OneWay;
+
+#[cfg(feature = "fetch")]
+mod online;
+
+mod output;
+use crate::output::{Chunks, LogFile, Output, Sorting};
+
+#[cfg(feature = "parquet")]
+mod parquet;
+
+#[cfg(feature = "fetch")]
+mod pskreporter;
+
+mod progress;
+
+mod report;
+
+mod schema;
+
+#[cfg(unix)]
+mod socket;
+
+#[cfg(feature = "sqlite")]
+mod sqlite;
+
+mod table;
+
+mod template;
+
+mod text;
+
+mod wsjtx;
+
+#[cfg(feature = "fetch")]
+mod wsprlive;
+
+use std::borrow::Cow;
 use std::cmp::{self, Ordering, PartialEq, PartialOrd, Eq, Ord};
 use std::collections::{HashMap, HashSet, BTreeSet, VecDeque};
 use std::convert::TryFrom;
-use std::env;
 use std::error::Error;
 use std::fmt;
 use std::fmt::Display;
+use std::fs;
 use std::io;
 use std::io::prelude::*;
+use std::rc::Rc;
 use std::str::FromStr;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use smartstring::alias::String;
-use chrono::{DateTime, Utc, NaiveDateTime};
+use chrono::{DateTime, Utc};
+use clap::{CommandFactory, Parser};
+use log::{debug, info, warn};
 use unicase::Ascii;
 
 /// Call sign
@@ -59,79 +153,102 @@ impl Display for Frequency {
 	}
 }
 
-/// Frequency band
+/// Frequency band, with the number and unit of its name
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
-struct Band(&'static str, &'static str);
+struct Band(Cow<'static, str>, &'static str);
+
+impl Band {
+	const fn new(num: &'static str, unit: &'static str) -> Self {
+		Band(Cow::Borrowed(num), unit)
+	}
+
+	/// Name without a space, such as `20m` or `13.5kHz`
+	fn name(&self) -> std::string::String {
+		format!("{}{}", self.0, self.1)
+	}
+}
 
 impl TryFrom<Frequency> for Band {
 	type Error = io::Error;
 
 	fn try_from(freq: Frequency) -> Result<Self, Self::Error> {
+		if let Some(band) = bandplan::lookup(freq) {
+			return Ok(band);
+		}
+
+		// Segments outside the ADIF bands are named after their lower edge in kHz
 		match freq.0 {
+			13_500..=13_600
+				=> Ok(Band::new("13.5", "kHz")),
 			135_700..=137_800
-				=> Ok(Band("2200", "m")),
+				=> Ok(Band::new("2200", "m")),
 			160_000..=190_000
-				=> Ok(Band("1750", "m")),
+				=> Ok(Band::new("160", "kHz")),
 			472_000..=479_000
-				=> Ok(Band("630", "m")),
+				=> Ok(Band::new("630", "m")),
+			501_000..=504_000
+				=> Ok(Band::new("560", "m")),
 			1_800_000..=2_000_000
-				=> Ok(Band("160", "m")),
+				=> Ok(Band::new("160", "m")),
 			3_500_000..=4_000_000
-				=> Ok(Band("80", "m")),
-			5_060_000..=5_450_500
-				=> Ok(Band("60", "m")),
+				=> Ok(Band::new("80", "m")),
+			5_060_000..=5_450_000
+				=> Ok(Band::new("60", "m")),
 			7_000_000..=7_300_000
-				=> Ok(Band("40", "m")),
+				=> Ok(Band::new("40", "m")),
 			10_100_000..=10_150_000
-				=> Ok(Band("30", "m")),
+				=> Ok(Band::new("30", "m")),
 			14_000_000..=14_350_000
-				=> Ok(Band("20", "m")),
+				=> Ok(Band::new("20", "m")),
 			18_068_000..=18_168_000
-				=> Ok(Band("17", "m")),
+				=> Ok(Band::new("17", "m")),
 			21_000_000..=21_450_000
-				=> Ok(Band("15", "m")),
+				=> Ok(Band::new("15", "m")),
 			24_890_000..=24_990_000
-				=> Ok(Band("12", "m")),
+				=> Ok(Band::new("12", "m")),
 			28_000_000..=29_700_000
-				=> Ok(Band("10", "m")),
+				=> Ok(Band::new("10", "m")),
 			40_000_000..=45_000_000
-				=> Ok(Band("8", "m")),
+				=> Ok(Band::new("8", "m")),
 			50_000_000..=54_000_000
-				=> Ok(Band("6", "m")),
+				=> Ok(Band::new("6", "m")),
 			54_000_001..=69_900_000
-				=> Ok(Band("5", "m")),
+				=> Ok(Band::new("5", "m")),
 			70_000_000..=71_000_000
-				=> Ok(Band("4", "m")),
+				=> Ok(Band::new("4", "m")),
 			144_000_000..=148_000_000
-				=> Ok(Band("2", "m")),
-			219_000_000..=225_000_000
-				=> Ok(Band("1.25", "m")),
+				=> Ok(Band::new("2", "m")),
+			222_000_000..=225_000_000
+				=> Ok(Band::new("1.25", "m")),
 			420_000_000..=450_000_000
-				=> Ok(Band("70", "cm")),
+				=> Ok(Band::new("70", "cm")),
 			902_000_000..=928_000_000
-				=> Ok(Band("33", "cm")),
+				=> Ok(Band::new("33", "cm")),
 			1_240_000_000..=1_300_000_000
-				=> Ok(Band("23", "cm")),
+				=> Ok(Band::new("23", "cm")),
 			2_300_000_000..=2_450_000_000
-				=> Ok(Band("13", "cm")),
+				=> Ok(Band::new("13", "cm")),
 			3_300_000_000..=3_500_000_000
-				=> Ok(Band("9", "cm")),
-			5_600_000_000..=5_925_000_000
-				=> Ok(Band("6", "cm")),
+				=> Ok(Band::new("9", "cm")),
+			5_650_000_000..=5_925_000_000
+				=> Ok(Band::new("6", "cm")),
 			10_000_000_000..=10_500_000_000
-				=> Ok(Band("1.25", "cm")),
+				=> Ok(Band::new("3", "cm")),
 			24_000_000_000..=24_250_000_000
-				=> Ok(Band("6", "mm")),
+				=> Ok(Band::new("1.25", "cm")),
+			47_000_000_000..=47_200_000_000
+				=> Ok(Band::new("6", "mm")),
 			75_500_000_000..=81_000_000_000
-				=> Ok(Band("4", "mm")),
-			119_980_000_000..=120_020_000_000
-				=> Ok(Band("2.5", "mm")),
-			142_000_000_000..=149_000_000_000
-				=> Ok(Band("2", "mm")),
+				=> Ok(Band::new("4", "mm")),
+			119_980_000_000..=123_000_000_000
+				=> Ok(Band::new("2.5", "mm")),
+			134_000_000_000..=149_000_000_000
+				=> Ok(Band::new("2", "mm")),
 			241_000_000_000..=250_000_000_000
-				=> Ok(Band("1", "mm")),
+				=> Ok(Band::new("1", "mm")),
 			_
-				=> Err(io::Error::new(io::ErrorKind::InvalidData, "Unknown frequency band"))
+				=> bandplan::bucket(freq)
+					.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Unknown frequency band"))
 		}
 	}
 }
@@ -148,6 +265,13 @@ impl fmt::Display for Band {
 struct Power(i8);
 
 impl Power {
+	/// Power not reported, as by PSK Reporter, sorting above any actual power
+	const UNKNOWN: Power = Power(i8::MAX);
+
+	fn is_known(self) -> bool {
+		self != Power::UNKNOWN
+	}
+
 	/// Convert power to Watts
 	fn watts(self) -> f64 {
 		10f64.powf(self.0 as f64 / 10.0 - 3.0)
@@ -164,9 +288,12 @@ impl fmt::Display for Power {
 			(num / mul).round() * mul
 		}
 
+		if !self.is_known() {
+			return write!(fmtr, "unknown power");
+		}
+
 		let watts = self.watts();
 
-		#[allow(illegal_floating_point_literal_pattern)]
 		match watts {
 			..1e-6
 				=> write!(fmtr, "{} nW", watts * 1e9),
@@ -195,6 +322,79 @@ impl fmt::Display for Power {
 	}
 }
 
+/// Transmission mode and cycle length
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+enum Mode {
+	#[default]
+	Wspr2,
+	Wspr15,
+	Fst4w120,
+	Fst4w300,
+	Fst4w900,
+	Fst4w1800,
+}
+
+impl Mode {
+	const NAMES: &'static [(&'static str, Mode)] = &[
+		("WSPR-2", Mode::Wspr2),
+		("WSPR-15", Mode::Wspr15),
+		("FST4W-120", Mode::Fst4w120),
+		("FST4W-300", Mode::Fst4w300),
+		("FST4W-900", Mode::Fst4w900),
+		("FST4W-1800", Mode::Fst4w1800),
+	];
+
+	/// Whether this is one of the FST4W modes
+	fn is_fst4w(self) -> bool {
+		!matches!(self, Mode::Wspr2 | Mode::Wspr15)
+	}
+
+	/// Length of a transmission cycle in seconds
+	fn period(self) -> u64 {
+		match self {
+			Mode::Wspr2 | Mode::Fst4w120 => 120,
+			Mode::Fst4w300 => 300,
+			Mode::Wspr15 | Mode::Fst4w900 => 900,
+			Mode::Fst4w1800 => 1800,
+		}
+	}
+
+	/// Number of the cycle containing a timestamp
+	fn cycle(self, timestamp: u64) -> u64 {
+		timestamp / self.period()
+	}
+}
+
+impl FromStr for Mode {
+	type Err = io::Error;
+
+	/// Parse a mode by name, or by the number uploaded to WSPRnet by WSJT-X
+	fn from_str(mode: &str) -> Result<Self, Self::Err> {
+		match mode {
+			"2" => Ok(Mode::Wspr2),
+			"15" => Ok(Mode::Wspr15),
+			"3" => Ok(Mode::Fst4w120),
+			"5" => Ok(Mode::Fst4w300),
+			"16" => Ok(Mode::Fst4w900),
+			"30" => Ok(Mode::Fst4w1800),
+			_ => Mode::NAMES.iter()
+				.find(|(name, _)| name.eq_ignore_ascii_case(mode))
+				.map(|&(_, mode)| mode)
+				.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Unknown mode")),
+		}
+	}
+}
+
+impl fmt::Display for Mode {
+	fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+		let name = Mode::NAMES.iter()
+			.find(|&&(_, mode)| mode == *self)
+			.map_or("", |(name, _)| name);
+
+		write!(fmtr, "{name}")
+	}
+}
+
 /// WSPR spot
 #[derive(Clone, Debug)]
 struct Spot {
@@ -220,60 +420,165 @@ struct Spot {
 	drift: i8,
 	/// Approximate distance between transmitter and reporter along the great circle path in km
 	distance: u16,
+	/// Transmission mode, WSPR-2 unless given
+	mode: Mode,
+	/// Columns following the standard WSPRnet columns, as added by wsprdaemon
+	extra: Box<[String]>,
 }
 
 impl Spot {
+	/// Number of the cycle of the spot in its mode
 	fn cycle(&self) -> u64 {
-		self.timestamp / 120
+		self.mode.cycle(self.timestamp)
+	}
+}
+
+/// Field of a row in WSPRnet CSV format
+struct Field<'a> {
+	name: &'static str,
+	value: &'a str,
+	/// Position in the row, counting from one
+	column: usize,
+	/// Byte offset in the row
+	offset: usize,
+}
+
+impl Field<'_> {
+	/// Parse the value, naming the field and its position if this fails
+	fn parse<T: FromStr>(&self) -> Result<T, Box<dyn Error>> where T::Err: Into<Box<dyn Error>> {
+		self.value.parse().map_err(|err: T::Err| Box::new(FieldError {
+			name: self.name,
+			column: self.column,
+			offset: self.offset,
+			value: self.value.to_owned(),
+			err: err.into(),
+		}).into())
+	}
+}
+
+/// Fields of a row in WSPRnet CSV format, taken one after another
+struct Fields<'a> {
+	split: std::str::Split<'a, char>,
+	column: usize,
+	offset: usize,
+}
+
+impl<'a> Fields<'a> {
+	fn new(row: &'a str) -> Self {
+		Fields { split: row.split(','), column: 0, offset: 0 }
+	}
+
+	/// Take the next field, if the row has any more
+	fn optional(&mut self, name: &'static str) -> Option<Field<'a>> {
+		let value = self.split.next()?;
+		let field = Field { name, value, column: self.column + 1, offset: self.offset };
+		self.column += 1;
+		self.offset += value.len() + 1;
+		Some(field)
+	}
+
+	/// Take the next field, which must be present
+	fn take(&mut self, name: &'static str) -> Result<Field<'a>, Box<dyn Error>> {
+		self.optional(name).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData,
+			format!("Missing {name} field in column {}", self.column + 1)).into())
+	}
+
+	/// Skip fields that are not used
+	fn skip(&mut self, num: usize) {
+		for _ in 0..num {
+			self.optional("");
+		}
+	}
+
+	/// Values of the remaining fields
+	fn rest(self) -> std::str::Split<'a, char> {
+		self.split
+	}
+}
+
+/// Field of a spot that failed to parse
+#[derive(Debug)]
+struct FieldError {
+	name: &'static str,
+	column: usize,
+	offset: usize,
+	value: std::string::String,
+	err: Box<dyn Error>,
+}
+
+impl fmt::Display for FieldError {
+	fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+		write!(fmtr, "Invalid {} “{}” in column {} at byte {}: {}", self.name, self.value, self.column, self.offset, self.err)
+	}
+}
+
+impl Error for FieldError {
+	fn source(&self) -> Option<&(dyn Error + 'static)> {
+		Some(self.err.as_ref())
 	}
 }
 
 impl FromStr for Spot {
 	type Err = Box<dyn Error>;
 
-	/// Parse Spot from CSV
+	/// Parse Spot from CSV, taking spots without a mode column to be WSPR-2
 	fn from_str(row: &str) -> Result<Self, Box<dyn Error>> {
-		fn invalid(err: &'static str) -> Box<io::Error> {
-			Box::new(io::Error::new(io::ErrorKind::InvalidData, err))
-		}
-
-		let itr = &mut row.split(',');
-
-		Ok(Spot {
-			id: itr.next()
-				.ok_or_else(|| invalid("Missing ID field"))?
-				.parse()?,
-			timestamp: itr.next()
-				.ok_or_else(|| invalid("Missing timestamp field"))?
-				.parse()?,
-			call_rx: itr.next()
-				.ok_or_else(|| invalid("Missing reporter call sign field"))?
-				.parse()?,
-			grid_rx: itr.next()
-				.ok_or_else(|| invalid("Missing reporter grid field"))?
-				.parse()?,
-			snr: itr.next()
-				.ok_or_else(|| invalid("Missing SNR field"))?
-				.parse()?,
-			frequency: Frequency::from_mhz(itr.next()
-				.ok_or_else(|| invalid("Missing frequency field"))?
-				.parse()?),
-			call_tx: itr.next()
-				.ok_or_else(|| invalid("Missing transmitter call sign field"))?
-				.parse()?,
-			grid_tx: itr.next()
-				.ok_or_else(|| invalid("Missing transmitter grid field"))?
-				.parse()?,
-			power: Power::from_dbm(itr.next()
-				.ok_or_else(|| invalid("Missing transmission power field"))?
-				.parse()?),
-			drift: itr.next()
-				.ok_or_else(|| invalid("Missing frequency drift field"))?
-				.parse()?,
-			distance: itr.next()
-				.ok_or_else(|| invalid("Missing distance field"))?
-				.parse()?,
-		})
+		Spot::parse(row, &[])
+	}
+}
+
+impl Spot {
+	/// Parse Spot from CSV, choosing the mode of spots without a mode column by their band
+	///
+	/// The azimuth, band, version and code columns are skipped. They may be followed by the mode
+	/// and further columns, which are kept.
+	fn parse(row: &str, default_mode: &[DefaultMode]) -> Result<Self, Box<dyn Error>> {
+		let mut fields = Fields::new(row);
+		let mut mode_given = false;
+
+		let mut spot = Spot {
+			id: fields.take("ID")?.parse()?,
+			timestamp: fields.take("timestamp")?.parse()?,
+			call_rx: fields.take("reporter call sign")?.parse()?,
+			grid_rx: fields.take("reporter grid")?.parse()?,
+			snr: fields.take("SNR")?.parse()?,
+			frequency: Frequency::from_mhz(fields.take("frequency")?.parse()?),
+			call_tx: fields.take("transmitter call sign")?.parse()?,
+			grid_tx: fields.take("transmitter grid")?.parse()?,
+			power: match fields.take("transmission power")? {
+				field if field.value.is_empty() => Power::UNKNOWN,
+				field => Power::from_dbm(field.parse()?),
+			},
+			drift: fields.take("frequency drift")?.parse()?,
+			distance: fields.take("distance")?.parse()?,
+			mode: {
+				fields.skip(4);
+				match fields.optional("mode") {
+					Some(field) if !field.value.is_empty() => {
+						mode_given = true;
+						field.parse()?
+					},
+					_ => Mode::default(),
+				}
+			},
+			extra: {
+				// Spreadsheets pad rows with empty columns
+				let mut extra: Vec<String> = fields.rest().map(String::from).collect();
+				while extra.last().is_some_and(String::is_empty) {
+					extra.pop();
+				}
+
+				extra.into_boxed_slice()
+			},
+		};
+
+		if !mode_given {
+			if let Some(mode) = DefaultMode::choose(default_mode, spot.frequency) {
+				spot.mode = mode;
+			}
+		}
+
+		Ok(spot)
 	}
 }
 
@@ -285,7 +590,7 @@ impl PartialEq for Spot {
 
 impl PartialOrd for Spot {
 	fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-		Some(self.id.cmp(&other.id))
+		Some(self.cmp(other))
 	}
 }
 
@@ -316,6 +621,14 @@ struct Qso {
 	snr_op: i8,
 	/// Contact’s SNR
 	snr_ct: i8,
+	/// Operator’s lowest SNR
+	snr_op_min: i8,
+	/// Contact’s lowest SNR
+	snr_ct_min: i8,
+	/// Sum of the operator’s SNR over the spots with the operator as transmitter
+	snr_op_sum: i64,
+	/// Sum of the contact’s SNR over the spots with the operator as reporter
+	snr_ct_sum: i64,
 	/// Operator’s transmit power
 	power_op: Power,
 	/// Contact’s transmit power
@@ -328,10 +641,25 @@ struct Qso {
 	drift_op: i8,
 	/// Contact’s frequency drift
 	drift_ct: i8,
-	/// Approximate distance between operator and contact along the great circle path in km
+	/// Distance between operator and contact along the great circle path in km, as reported or
+	/// computed from their locators
 	distance: u16,
 	/// Spot IDs
-	spots: BTreeSet<u64>
+	spots: BTreeSet<u64>,
+	/// Number of spots with the operator as reporter
+	num_rx: usize,
+	/// Number of spots with the operator as transmitter
+	num_tx: usize,
+	/// Contact’s transmission mode
+	mode: Mode,
+	/// Whether any pair of reciprocal spots is from the same or adjacent cycles
+	synchronous: bool,
+	/// Extra columns of the latest spot with the operator as reporter
+	extra_op: Box<[String]>,
+	/// Extra columns of the latest spot with the contact as reporter
+	extra_ct: Box<[String]>,
+	/// Serial number in order of logging, once logged
+	serial: usize,
 }
 
 impl Qso {
@@ -343,12 +671,16 @@ impl Qso {
 		Qso {
 			call_op: op.call_rx.clone(),
 			call_ct: op.call_tx.clone(),
-			grid_op: op.grid_rx.clone(),
-			grid_ct: op.grid_tx.clone(),
+			grid_op: cmp::max_by_key(&ct.grid_tx, &op.grid_rx, |grid| grid.len()).clone(),
+			grid_ct: cmp::max_by_key(&ct.grid_rx, &op.grid_tx, |grid| grid.len()).clone(),
 			time_first: cmp::min(op.timestamp, ct.timestamp),
 			time_last: cmp::max(op.timestamp, ct.timestamp),
 			snr_op: ct.snr,
 			snr_ct: op.snr,
+			snr_op_min: ct.snr,
+			snr_ct_min: op.snr,
+			snr_op_sum: ct.snr.into(),
+			snr_ct_sum: op.snr.into(),
 			power_op: ct.power,
 			power_ct: op.power,
 			freq_op: ct.frequency,
@@ -356,86 +688,106 @@ impl Qso {
 			drift_op: ct.drift,
 			drift_ct: op.drift,
 			distance: op.distance,
-			spots
+			spots,
+			num_rx: 1,
+			num_tx: 1,
+			mode: op.mode,
+			synchronous: op.cycle().abs_diff(ct.cycle()) <= 1,
+			extra_op: op.extra.clone(),
+			extra_ct: ct.extra.clone(),
+			serial: 0,
 		}
 	}
 
 	fn update(&mut self, op: &Spot, ct: &Spot) {
+		// Keep the most precise locators, which may differ in length if compared at lower precision
+		for grid in [&op.grid_rx, &ct.grid_tx] {
+			if grid.len() > self.grid_op.len() {
+				self.grid_op = grid.clone();
+			}
+		}
+
+		for grid in [&op.grid_tx, &ct.grid_rx] {
+			if grid.len() > self.grid_ct.len() {
+				self.grid_ct = grid.clone();
+			}
+		}
+
 		self.time_first = cmp::min(self.time_first, cmp::min(op.timestamp, ct.timestamp));
 		self.time_last = cmp::max(self.time_last, cmp::max(op.timestamp, ct.timestamp));
 		self.snr_op = cmp::max(self.snr_op, ct.snr);
 		self.snr_ct = cmp::max(self.snr_ct, op.snr);
+		self.snr_op_min = cmp::min(self.snr_op_min, ct.snr);
+		self.snr_ct_min = cmp::min(self.snr_ct_min, op.snr);
 		self.drift_op = cmp::max(self.drift_op, ct.drift);
 		self.drift_ct = cmp::max(self.drift_ct, op.drift);
 		self.power_op = cmp::min(self.power_op, ct.power);
 		self.power_ct = cmp::min(self.power_ct, op.power);
-		self.spots.insert(op.id);
-		self.spots.insert(ct.id);
-	}
+		if self.spots.insert(op.id) {
+			self.num_rx += 1;
+			self.snr_ct_sum += i64::from(op.snr);
+		}
 
-	fn cycle_last(&self) -> u64 {
-		self.time_last / 120
-	}
+		if self.spots.insert(ct.id) {
+			self.num_tx += 1;
+			self.snr_op_sum += i64::from(ct.snr);
+		}
 
-	/// Create [DateTime] object from start timestamp
-	fn datetime_on(&self) -> DateTime<Utc> {
-		DateTime::<Utc>::from_utc(NaiveDateTime::from_timestamp(self.time_first as i64, 0), Utc)
+		self.synchronous |= op.cycle().abs_diff(ct.cycle()) <= 1;
+
+		if !op.extra.is_empty() {
+			self.extra_op = op.extra.clone();
+		}
+
+		if !ct.extra.is_empty() {
+			self.extra_ct = ct.extra.clone();
+		}
 	}
 
-	/// Create [DateTime] object from end timestamp
-	fn datetime_off(&self) -> DateTime<Utc> {
-		DateTime::<Utc>::from_utc(NaiveDateTime::from_timestamp((self.time_last + 120) as i64, 0), Utc)
+	/// Compute the distance from the most precise locators of operator and contact, if both are valid
+	fn measure(&mut self) {
+		if let Some(km) = locator::distance(&self.grid_op, &self.grid_ct) {
+			self.distance = km.round() as u16;
+		}
 	}
-}
 
-impl fmt::Display for Qso {
-	fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
-		macro_rules! adif {
-			($name:tt, $($arg:tt)*) => {{
-				let value = format!($($arg)*);
-				write!(fmtr, "<{}:{}>{}", $name, value.len(), value)
-			}}
-		}
-
-		fn fmt_spots(set: &BTreeSet<u64>) -> String {
-			let mut iter = set.iter();
-			let mut st = String::new();
-
-			// Assume that every set contains at least two IDs
-			st.push_str(&iter.next().unwrap().to_string());
-			for id in iter {
-				st.push_str(", ");
-				st.push_str(&id.to_string());
-			}
+	/// Identifier derived from the call signs, bands and first cycle, the same on every run
+	///
+	/// This is the 64‐bit FNV‐1a hash of these, which unlike the hashers of the standard library
+	/// is not going to change.
+	fn id(&self) -> std::string::String {
+		let band = |freq: Frequency| match Band::try_from(freq) {
+			Ok(band) => band.name(),
+			Err(_) => freq.0.to_string(),
+		};
 
-			st
-		}
+		let key = format!("{} {} {} {} {}", self.call_op.to_ascii_uppercase(), self.call_ct.to_ascii_uppercase(),
+		                  band(self.freq_op), band(self.freq_ct), self.mode.cycle(self.time_first));
 
-		adif!("QSO_DATE", "{}", self.datetime_on().format("%Y%m%d"))?;
-		adif!("TIME_ON", "{}", self.datetime_on().format("%H%M"))?;
-		adif!("QSO_DATE_OFF", "{}", self.datetime_off().format("%Y%m%d"))?;
-		adif!("TIME_OFF", "{}", self.datetime_off().format("%H%M"))?;
-		adif!("OPERATOR", "{}", self.call_op)?;
-		adif!("CALL", "{}", self.call_ct)?;
-		adif!("MY_GRIDSQUARE", "{}", self.grid_op)?;
-		adif!("GRIDSQUARE", "{}", self.grid_ct)?;
-		adif!("RST_RCVD", "{:+03}", self.snr_op)?;
-		adif!("RST_SENT", "{:+03}", self.snr_ct)?;
-		adif!("FREQ", "{:.6}", self.freq_op.mhz())?;
-		adif!("RX_FREQ", "{:.6}", self.freq_ct.mhz())?;
+		let hash = key.bytes().fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| {
+			(hash ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01b3)
+		});
 
-		if let Ok(band) = Band::try_from(self.freq_op) {
-			adif!("BAND", "{}{}", band.0, band.1)?;
-		}
+		format!("{hash:016x}")
+	}
 
-		if let Ok(band) = Band::try_from(self.freq_ct) {
-			adif!("BAND_RX", "{}{}", band.0, band.1)?;
-		}
+	/// Operator’s mean SNR
+	fn snr_op_mean(&self) -> f64 {
+		self.snr_op_sum as f64 / self.num_tx as f64
+	}
 
-		adif!("TX_PWR", "{:.4}", self.power_op.watts())?;
-		adif!("RX_PWR", "{:.4}", self.power_ct.watts())?;
-		adif!("DISTANCE", "{}", self.distance)?;
+	/// Contact’s mean SNR
+	fn snr_ct_mean(&self) -> f64 {
+		self.snr_ct_sum as f64 / self.num_rx as f64
+	}
 
+	/// Number of the last cycle of the QSO in its mode
+	fn cycle_last(&self) -> u64 {
+		self.mode.cycle(self.time_last)
+	}
+
+	/// Describe the band, including the receive band if different
+	fn band_description(&self) -> std::string::String {
 		let band_op = match Band::try_from(self.freq_op) {
 			Ok(band) => band.to_string(),
 			Err(_) => self.freq_op.to_string()
@@ -446,145 +798,988 @@ impl fmt::Display for Qso {
 			Err(_) => self.freq_ct.to_string()
 		};
 
-		let band_str = if band_op == band_ct {
+		if band_op == band_ct {
 			band_op
 		} else {
 			format!("{band_op} (RX {band_ct})")
+		}
+	}
+
+	/// Create [DateTime] object from start timestamp
+	fn datetime_on(&self) -> DateTime<Utc> {
+		DateTime::<Utc>::from_timestamp(self.time_first as i64, 0).unwrap_or_default()
+	}
+
+	/// Create [DateTime] object from end timestamp
+	fn datetime_off(&self) -> DateTime<Utc> {
+		DateTime::<Utc>::from_timestamp((self.time_last + self.mode.period()) as i64, 0).unwrap_or_default()
+	}
+}
+
+/// Build spot‐level criteria
+fn spot_filter(args: &SpotFilterArgs) -> Filter {
+	let mut filter = Filter::default();
+	filter.window(args.since, args.until);
+	filter.sample(args.sample);
+	filter.bands(&args.band);
+	filter.min_snr(args.min_snr);
+	filter.distance(args.min_distance, args.max_distance);
+	filter.max_drift(args.max_drift);
+	filter
+}
+
+/// Build spot and contact criteria from the matching options
+fn match_filter(args: &MatchOptions) -> io::Result<Rc<Filter>> {
+	let mut filter = spot_filter(&args.spots);
+
+	for call in &args.exclude {
+		filter.exclude(call.clone());
+	}
+
+	for path in &args.exclude_file {
+		filter.exclude_file(path)?;
+	}
+
+	for call in &args.only {
+		filter.only(call.clone());
+	}
+
+	for path in &args.only_file {
+		filter.only_file(path)?;
+	}
+
+	filter.include_regex(&args.include_regex)?;
+	filter.exclude_regex(&args.exclude_regex)?;
+	filter.regex_reporter(args.regex_reporter);
+	filter.grid(&args.grid);
+	filter.my_grid(&args.my_grid);
+	filter.power(args.min_power_dbm.map(Power::from_dbm), args.max_power_dbm.map(Power::from_dbm));
+	filter.min_rounds(args.min_rounds);
+	filter.synchronous(args.synchronous);
+
+	let mut logged = Logged::new(args.existing_tolerance * 60);
+	for path in &args.existing_log {
+		logged.read(path)?;
+	}
+
+	filter.logged(logged);
+	filter.dedupe_contact(args.dedupe_contact);
+
+	Ok(Rc::new(filter))
+}
+
+/// Read the spots from the given input sources
+fn lines(args: InputArgs, #[cfg_attr(not(feature = "parquet"), allow(unused_variables))] spots: &SpotFilterArgs) -> io::Result<input::Lines> {
+	// Standard input may stand in for any one file, but cannot be read twice
+	let num_stdin = args.files.iter()
+		.chain(args.all_wspr.iter().chain(&args.wsprd).map(|decodes| &decodes.path))
+		.filter(|path| path.as_os_str() == "-")
+		.count();
+
+	if num_stdin > 1 {
+		return Err(io::Error::new(io::ErrorKind::InvalidInput, "Standard input can only be read once"));
+	}
+
+	// Both never end, so that the one read last would never be reached
+	#[cfg(all(feature = "mqtt", unix))]
+	if args.input.iter().any(|input| matches!(input, input::Input::Mqtt(_)))
+		&& args.input.iter().any(|input| matches!(input, input::Input::Socket(_))) {
+		return Err(io::Error::new(io::ErrorKind::InvalidInput, "MQTT topics and Unix domain sockets cannot be read together"));
+	}
+
+	let lines = input::Lines::new(args.files, args.input, args.all_wspr, args.wsprd)
+		.input_format(args.input_format)
+		.columns(args.columns)
+		.delimiter(args.delimiter);
+
+	#[cfg(feature = "sqlite")]
+	let lines = lines.query(args.query);
+
+	// Skip row groups of Parquet files outside the time window
+	#[cfg(feature = "parquet")]
+	let lines = lines.window(spots.since, spots.until);
+
+	Ok(lines.merge(!args.sequential).progress(!args.no_progress))
+}
+
+/// Number of rows failing to parse that are shown without raising the verbosity
+const ERROR_SAMPLES: usize = 3;
+
+/// Rows that failed to parse, up to the number tolerated
+struct ParseErrors {
+	/// Abort on the first row failing to parse
+	strict: bool,
+	/// Abort once more rows failed to parse
+	max: Option<usize>,
+	count: usize,
+}
+
+impl ParseErrors {
+	fn new(args: &InputArgs) -> Self {
+		ParseErrors { strict: args.strict, max: args.max_errors, count: 0 }
+	}
+
+	/// Account for a row that failed to parse at the given location, failing if this exceeds the
+	/// tolerance
+	fn record(&mut self, position: Option<std::string::String>, row: &str, err: &dyn Error) -> io::Result<()> {
+		self.count += 1;
+
+		let message = match position {
+			Some(position) => format!("Failed to parse row {position} “{row}”: {err}"),
+			None => format!("Failed to parse row “{row}”: {err}"),
 		};
 
-		adif!("QSLMSG",
-		      "2-way WSPR spot on {} with {} ({} dBm), SNR {} dB, drift {:+} Hz/s, distance {} km",
-		      band_str, self.power_ct, self.power_ct.0, self.snr_ct, self.drift_ct, self.distance)?;
-		adif!("COMMENT",
-		      "2-way WSPR spot on {} with {} ({} dBm), SNR {} dB, drift {:+} Hz/s, distance {} km",
-		      band_str, self.power_ct, self.power_ct.0, self.snr_ct, self.drift_ct, self.distance)?;
+		if self.strict {
+			return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+		}
+
+		if self.count <= ERROR_SAMPLES {
+			warn!(event = "parse_failed"; "{message}");
+		} else {
+			debug!(event = "parse_failed"; "{message}");
+		}
 
-		adif!("NOTES", "WSPRnet spot IDs {}", fmt_spots(&self.spots))?;
-		adif!("MODE", "WSPR")?;
-		adif!("QSO_RANDOM", "Y")?;
-		write!(fmtr, "<EOR>")
+		if self.max.is_some_and(|max| self.count > max) {
+			return Err(io::Error::new(io::ErrorKind::InvalidData,
+				format!("Giving up after {} rows that failed to parse", self.count)));
+		}
+
+		Ok(())
+	}
+
+	/// Report how many rows were skipped
+	fn finish(&self) {
+		if self.count > 0 {
+			warn!(event = "rows_skipped", rows = self.count; "Skipped {} rows that failed to parse", self.count);
+		}
+	}
+}
+
+/// Allowance for spots being appended late when following input files, in seconds
+const FOLLOW_DELAY: u64 = 300;
+
+/// Interval at which input files are checked for new lines when following them
+const FOLLOW_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Allowance for spots being uploaded late when polling wspr.live, in seconds
+#[cfg_attr(not(feature = "fetch"), allow(dead_code))]
+const POLL_DELAY: u64 = 3600;
+
+/// Interval at which the matching state is saved to the checkpoint
+const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Number of rows after which the time since the last checkpoint is checked
+const CHECKPOINT_ROWS: u64 = 1 << 16;
+
+/// Current time in seconds since the Unix epoch
+fn now() -> u64 {
+	SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs())
+}
+
+/// Queue the decodes received from WSJT-X so far, noting their reporters
+fn receive(decodes: &mpsc::Receiver<io::Result<std::string::String>>, pending: &mut VecDeque<Spot>,
+           reporters: &mut HashSet<Call>, filter: &Filter, default_mode: &[DefaultMode]) -> io::Result<()> {
+	for row in decodes.try_iter() {
+		let row = row?;
+		match Spot::parse(&row, default_mode) {
+			Ok(spot) if filter.spot(&spot) => {
+				reporters.insert(spot.call_rx.clone());
+				pending.push_back(spot);
+			},
+			Ok(_) => (),
+			Err(err) => debug!("Failed to parse decode “{row}”: {err}"),
+		}
 	}
+
+	Ok(())
 }
 
-#[derive(Hash, PartialEq, Eq)]
-struct QsoKey(Call, Grid, Grid, Band, Band);
+/// Summary of the logged QSOs
+#[derive(Default)]
+struct Tally {
+	/// Unique call signs
+	contacts: HashSet<Call>,
+	/// Transmit bands of the operators, with a frequency for sorting
+	bands: HashMap<Band, Frequency>,
+	/// Number of individual QSOs
+	num_qsos: usize,
+	/// Last QSOs with each contact on each band, for skipping repeats
+	repeats: Repeats,
+}
 
-fn main() -> std::io::Result<()> {
-	let call_op = Ascii::new(env::args().nth(1).expect("Missing operator call sign"));
-	let stdin = io::stdin();
+impl Tally {
+	/// Log closed QSOs meeting the criteria and return how many
+	fn log(&mut self, qsos: Vec<Qso>, filter: &Filter, output: &mut Option<Output>) -> io::Result<usize> {
+		let mut num_logged = 0;
 
-	let mut cycle = 0u64;
+		for mut qso in qsos {
+			if !filter.qso(&qso) {
+				debug!(event = "qso_discarded", call_op:% = qso.call_op, call_ct:% = qso.call_ct;
+				       "Discarded QSO between {} and {} not meeting the criteria", qso.call_op, qso.call_ct);
+				continue;
+			}
 
-	// Look back queues
-	let mut rx = VecDeque::<Spot>::new();
-	let mut tx = VecDeque::<Spot>::new();
+			if filter.already_logged(&qso) {
+				info!(event = "qso_skipped", call_op:% = qso.call_op, call_ct:% = qso.call_ct;
+				      "Skipped QSO between {} and {} already logged", qso.call_op, qso.call_ct);
+				continue;
+			}
+
+			if filter.contact_window().is_some_and(|window| self.repeats.repeat(&qso, window)) {
+				debug!(event = "qso_repeated", call_op:% = qso.call_op, call_ct:% = qso.call_ct;
+				       "Skipped QSO between {} and {} repeating one logged before", qso.call_op, qso.call_ct);
+				continue;
+			}
 
-	// Active QSOs
-	let mut qsos = HashMap::<QsoKey, Qso>::new();
+			info!(event = "qso_closed", call_op:% = qso.call_op, call_ct:% = qso.call_ct, spots = qso.spots.len();
+			      "Closed QSO between {} and {} with {} spots", qso.call_op, qso.call_ct, qso.spots.len());
+			qso.serial = self.num_qsos + 1;
+			if let Some(output) = output {
+				output.write(&qso)?;
+			}
+
+			if let Ok(band) = Band::try_from(qso.freq_op) {
+				self.bands.entry(band).or_insert(qso.freq_op);
+			}
+
+			self.contacts.insert(qso.call_ct);
+			self.num_qsos += 1;
+			num_logged += 1;
+		}
+
+		Ok(num_logged)
+	}
+}
+
+/// Identify QSOs from mutual spots and write an ADIF log
+///
+/// Spots are matched for the given operators, or between all pairs of stations if there are none.
+fn run_match(calls: Option<Vec<Call>>, args: MatchOptions) -> io::Result<()> {
+	let filter = match_filter(&args)?;
+	let settings = Settings {
+		lookback: args.lookback,
+		qso_gap: args.qso_gap.map_or(args.lookback, |minutes| minutes.div_ceil(2)),
+		max_duration: args.max_qso_duration.map(|minutes| minutes * 60),
+		same_band: args.same_band_only,
+		grid_precision: args.grid_precision.map(usize::from),
+		rover: args.rover,
+		normalize_calls: args.normalize_calls,
+		reported_distance: args.reported_distance,
+		invalid_grid: args.invalid_grid,
+	};
+
+	if args.invalid_grid == GridPolicy::Substitute && args.station_grid.is_empty() {
+		return Err(io::Error::new(io::ErrorKind::InvalidInput, "Substituting invalid locators requires --station-grid"));
+	}
+
+	let grids: Rc<HashMap<Call, Grid>> = Rc::new(args.station_grid.iter()
+		.map(|station| (station.call.clone(), station.grid.clone()))
+		.collect());
+
+	// Operator call signs and start time for wspr.live queries
+	#[cfg(feature = "fetch")]
+	let live = if args.wspr_live {
+		let (Some(calls), Some(since)) = (&calls, args.spots.since) else {
+			return Err(io::Error::new(io::ErrorKind::InvalidInput, "Querying wspr.live requires operator call signs and --since"));
+		};
+
+		Some((calls.clone(), since))
+	} else {
+		None
+	};
+
+	// Cabrillo and Parquet logs end with a footer, so that they cannot be continued
+	#[cfg(feature = "fetch")]
+	let continued = args.append || args.follow || args.checkpoint.is_some() || args.poll.is_some();
+	#[cfg(not(feature = "fetch"))]
+	let continued = args.append || args.follow || args.checkpoint.is_some();
+
+	#[cfg(feature = "fetch")]
+	let unending = args.follow || args.poll.is_some();
+	#[cfg(not(feature = "fetch"))]
+	let unending = args.follow;
+
+	if args.sort && args.sort_window.is_none() && unending {
+		return Err(io::Error::new(io::ErrorKind::InvalidInput, "Sorting while following or polling requires --sort-window"));
+	}
+
+	let mut formats: Vec<_> = args.output.iter().map(|target| target.format.unwrap_or(args.format)).collect();
+	if formats.is_empty() {
+		formats.push(args.format);
+	}
+
+	if let Some(format) = formats.into_iter().find(|format| !format.appendable()) {
+		if continued {
+			return Err(io::Error::new(io::ErrorKind::InvalidInput,
+				format!("{format:?} logs cannot be written with --append, --follow, --poll or --checkpoint")));
+		}
+	}
+
+	// Live decodes from WSJT-X, held back until spots up to their time have been read
+	let decodes = match &args.wsjtx {
+		#[cfg(feature = "fetch")]
+		Some(_) if !args.follow && args.poll.is_none() => {
+			return Err(io::Error::new(io::ErrorKind::InvalidInput, "Receiving decodes from WSJT-X requires --follow or --poll"));
+		},
+		#[cfg(not(feature = "fetch"))]
+		Some(_) if !args.follow => {
+			return Err(io::Error::new(io::ErrorKind::InvalidInput, "Receiving decodes from WSJT-X requires --follow"));
+		},
+		Some(addr) => Some(wsjtx::listen(addr)?),
+		None => None,
+	};
+
+	let mut pending = VecDeque::new();
+
+	// Stations whose spots are received from WSJT-X, which are not taken from other sources
+	let mut reporters = HashSet::new();
+
+	let mut errors = ParseErrors::new(&args.input);
+	let default_mode = args.input.default_mode.clone();
+
+	// Inputs a checkpoint applies to
+	let files = args.input.files.iter()
+		.map(|path| path.display().to_string())
+		.collect::<Vec<_>>()
+		.join(" ");
+
+	#[cfg(feature = "fetch")]
+	let mut lines = match &live {
+		Some((calls, since)) => input::Lines::reader(wsprlive::spots(&args.wspr_live_url, calls, *since, args.spots.until, None)?),
+		None => lines(args.input, &args.spots)?.follow(args.follow),
+	};
+
+	#[cfg(not(feature = "fetch"))]
+	let mut lines = lines(args.input, &args.spots)?.follow(args.follow);
+
+	// Stations spotted in one direction only
+	let mut one_way = match (&args.one_way, &calls) {
+		(Some(_), Some(calls)) => Some(OneWay::new(calls.clone(), args.one_way_min_spots)),
+		(Some(_), None) => return Err(io::Error::new(io::ErrorKind::InvalidInput, "The one‐way report requires operator call signs")),
+		(None, _) => None,
+	};
+
+	let (mut matching, title) = match calls {
+		Some(calls) => (
+			Matching::Operators(calls.iter()
+				.map(|call| Matcher::new(call.clone(), filter.clone(), settings, grids.clone()))
+				.collect()),
+			calls.iter()
+				.map(|call| call.as_str())
+				.collect::<Vec<_>>()
+				.join(", ")
+		),
+		None => (
+			Matching::AllPairs(Box::new(PairMatcher::new(filter.clone(), settings, grids))),
+			"all stations".to_owned()
+		),
+	};
+
+	let mut tally = Tally::default();
+
+	// Spots repeated by overlapping inputs
+	let mut seen = Dedup::default();
+	let mut num_duplicates = 0usize;
+
+	// Rows read so far, from which a resumed run continues
+	let mut num_rows = 0u64;
+	let mut last_checkpoint = Instant::now();
+	let origin = String::from(format!("{title}: {files}"));
+
+	// Chunks of the logs written and QSOs held back for sorting before the checkpoint
+	let mut chunks: Option<Chunks> = None;
+	let mut sorting: Option<Sorting> = None;
+
+	// Whether a checkpoint was restored, so that the logs written before it are continued
+	let mut resumed = false;
+
+	if let (true, Some(path)) = (args.resume, &args.checkpoint) {
+		match checkpoint::open(path) {
+			Ok(mut state) => {
+				if String::decode(&mut state)? != origin {
+					return Err(io::Error::new(io::ErrorKind::InvalidInput,
+						format!("Checkpoint {} was saved for other operators or inputs", path.display())));
+				}
+
+				num_rows = Decode::decode(&mut state)?;
+				errors.count = Decode::decode(&mut state)?;
+				num_duplicates = Decode::decode(&mut state)?;
+				seen = Decode::decode(&mut state)?;
+				tally = Decode::decode(&mut state)?;
+				matching.restore(&mut state)?;
+
+				chunks = Decode::decode(&mut state)?;
+				output::truncate(&Decode::decode(&mut state)?)?;
+				sorting = Decode::decode(&mut state)?;
+
+				// Spots counted for a one‐way report are only saved if it was requested then
+				if let (true, Some(one_way)) = (bool::decode(&mut state)?, &mut one_way) {
+					one_way.restore(&mut state)?;
+				}
+
+				resumed = true;
+				info!(event = "resumed", rows = num_rows; "Resuming after {num_rows} rows");
+				let skipped = lines.by_ref()
+					.take(num_rows as usize)
+					.try_fold(0u64, |num, line| line.map(|_| num + 1))?;
+
+				if skipped < num_rows {
+					return Err(io::Error::new(io::ErrorKind::InvalidInput,
+						format!("Inputs end before the position of checkpoint {}", path.display())));
+				}
+			},
+			Err(err) if err.kind() == io::ErrorKind::NotFound => {
+				info!("No checkpoint at {}, starting from the beginning", path.display());
+			},
+			Err(err) => return Err(err),
+		}
+	}
+
+	let mut output = if args.dry_run {
+		None
+	} else {
+		let mut output = Output::new(args.split, &args.output, args.append || resumed, &title, args.format, args.record.clone(), args.cabrillo.clone())?;
+		if args.sort {
+			output.sort(args.sort_window.map(|minutes| minutes * 60));
+		}
+
+		if let Some(sorting) = sorting.take() {
+			output.resume_sorting(sorting)?;
+		}
+
+		if let Some(max_records) = args.max_records_per_file {
+			output.chunk(max_records)?;
+			if args.checkpoint.is_some() {
+				output.hold_chunks();
+			}
+
+			if let Some(chunks) = chunks.take() {
+				output.resume_chunks(chunks)?;
+			}
+		}
+
+		if let Some(addr) = &args.broadcast {
+			output.broadcast(addr)?;
+		}
+
+		if let Some(addr) = &args.cluster {
+			output.cluster(addr)?;
+		}
+
+		if let Some(path) = &args.kml {
+			output.kml(path)?;
+		}
+
+		if let Some(path) = &args.geojson {
+			output.geojson(path)?;
+		}
+
+		if let Some(path) = &args.report {
+			output.report(path)?;
+		}
+
+		if let Some(path) = &args.summary_md {
+			output.summary(path)?;
+		}
+
+		if args.lotw.lotw.is_some() {
+			output.lotw(args.lotw.clone(), args.existing_tolerance * 60)?;
+		}
+
+		#[cfg(feature = "fetch")]
+		if args.clublog.clublog_email.is_some() {
+			output.clublog(args.clublog.clone())?;
+		}
+
+		#[cfg(feature = "fetch")]
+		if args.hrdlog.hrdlog {
+			output.hrdlog(args.hrdlog.clone())?;
+		}
+
+		Some(output)
+	};
+
+	// Most recent spot seen, from which polling continues
+	let mut last_id = None;
+	let mut last_time = 0;
+
+	// Only loops when following or polling
+	loop {
+		while let Some(line) = lines.next() {
+			let row = line?;
+
+			// Save the state before this row, once the logs up to it are in place
+			if let Some(path) = &args.checkpoint {
+				if num_rows.is_multiple_of(CHECKPOINT_ROWS) && last_checkpoint.elapsed() >= CHECKPOINT_INTERVAL {
+					if let Some(output) = &mut output {
+						output.sync()?;
+					}
+
+					checkpoint::save(path, |out| {
+						origin.encode(out)?;
+						num_rows.encode(out)?;
+						errors.count.encode(out)?;
+						num_duplicates.encode(out)?;
+						seen.encode(out)?;
+						tally.encode(out)?;
+						matching.save(out)?;
+						output.as_ref().map(Output::chunks).encode(out)?;
+						output.as_ref().map(Output::lengths).transpose()?.unwrap_or_default().encode(out)?;
+						output.as_ref().and_then(Output::sorting).encode(out)?;
+						one_way.encode(out)
+					})?;
+
+					debug!(event = "checkpoint", rows = num_rows; "Saved checkpoint after {num_rows} rows");
+					last_checkpoint = Instant::now();
+				}
+			}
+
+			num_rows += 1;
+
+			if !filter.row(&row) {
+				continue;
+			}
+
+			let last = match Spot::parse(&row, &default_mode) {
+				Ok(spot) => spot,
+				Err(err) => {
+					errors.record(lines.position(), &row, err.as_ref())?;
+					continue;
+				}
+			};
+
+			if !seen.insert(last.id) {
+				num_duplicates += 1;
+				continue;
+			}
+
+			last_id = cmp::max(last_id, Some(last.id));
+			last_time = cmp::max(last_time, last.timestamp);
+
+			if !filter.spot(&last) {
+				continue;
+			}
+
+			if let Some(one_way) = &mut one_way {
+				one_way.add(&last);
+			}
+
+			if let Some(decodes) = &decodes {
+				if reporters.contains(&last.call_rx) {
+					continue;
+				}
+
+				// Match decodes up to the time of this spot first
+				receive(decodes, &mut pending, &mut reporters, &filter, &default_mode)?;
+				while let Some(decode) = pending.pop_front_if(|decode| decode.timestamp <= last.timestamp) {
+					tally.log(matching.push(decode), &filter, &mut output)?;
+				}
+			}
+
+			// Log QSOs with no more spots
+			tally.log(matching.push(last), &filter, &mut output)?;
+		}
+
+		if args.follow {
+			// Clients of Unix domain sockets may request to log all QSOs right away
+			#[cfg(unix)]
+			let until = if lines.flushed() { u64::MAX } else { now().saturating_sub(FOLLOW_DELAY) };
+			#[cfg(not(unix))]
+			let until = now().saturating_sub(FOLLOW_DELAY);
+
+			// Match decodes that no spots will be appended before anymore
+			let mut num_logged = 0;
+			if let Some(decodes) = &decodes {
+				receive(decodes, &mut pending, &mut reporters, &filter, &default_mode)?;
+				while let Some(decode) = pending.pop_front_if(|decode| decode.timestamp < until) {
+					num_logged += tally.log(matching.push(decode), &filter, &mut output)?;
+				}
+			}
+
+			// Log QSOs with no more spots by the time they would have been appended
+			if num_logged + tally.log(matching.expire(until), &filter, &mut output)? > 0 {
+				if let Some(output) = &mut output {
+					output.sync()?;
+				}
+			}
+
+			thread::sleep(FOLLOW_INTERVAL);
+			continue;
+		}
+
+		#[cfg(feature = "fetch")]
+		if let (Some(interval), Some((calls, since))) = (args.poll, &live) {
+			// Make the QSOs logged so far available
+			if let Some(output) = &mut output {
+				output.sync()?;
+			}
+
+			let mut delay = Duration::from_secs(interval * 60);
+			lines = loop {
+				thread::sleep(delay);
+
+				// Allow for spots uploaded late
+				let since = cmp::max(*since, last_time.saturating_sub(POLL_DELAY));
+				match wsprlive::spots(&args.wspr_live_url, calls, since, None, last_id) {
+					Ok(reader) => break input::Lines::reader(reader),
+					Err(err) => {
+						warn!(event = "poll_failed"; "Polling wspr.live failed: {err}");
+						delay = cmp::min(delay * 2, Duration::from_secs(3600));
+					}
+				}
+			};
+
+			// Match decodes too old for any spots uploaded late to be polled before them
+			if let Some(decodes) = &decodes {
+				receive(decodes, &mut pending, &mut reporters, &filter, &default_mode)?;
+				let until = now().saturating_sub(POLL_DELAY);
+				while let Some(decode) = pending.pop_front_if(|decode| decode.timestamp < until) {
+					tally.log(matching.push(decode), &filter, &mut output)?;
+				}
+			}
+
+			continue;
+		}
+
+		break;
+	}
+
+	// Match the decodes left and log the QSOs that later spots would have closed
+	if let Some(decodes) = &decodes {
+		receive(decodes, &mut pending, &mut reporters, &filter, &default_mode)?;
+		while let Some(decode) = pending.pop_front() {
+			tally.log(matching.push(decode), &filter, &mut output)?;
+		}
+	}
+
+	let open = matching.finish();
+	if !open.is_empty() {
+		let num_open = open.len();
+		let num_logged = tally.log(open, &filter, &mut output)?;
+		info!(event = "qsos_flushed", qsos = num_open, logged = num_logged;
+		      "Closed {num_open} QSOs still open at the end of the input, of which {num_logged} were logged");
+	}
+
+	if let Some(output) = output {
+		output.finish()?;
+	}
+
+	if let (Some(one_way), Some(path)) = (one_way, &args.one_way) {
+		let (mut file, _) = LogFile::create(path, false)?;
+		one_way.write(&mut file)?;
+		file.persist()?;
+	}
+
+	// Nothing is left to resume once the run is complete
+	if let Some(path) = &args.checkpoint {
+		match fs::remove_file(path) {
+			Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+			_ => (),
+		}
+	}
+
+	errors.finish();
+
+	if num_duplicates > 0 {
+		warn!(event = "duplicates_skipped", spots = num_duplicates; "Skipped {num_duplicates} duplicate spots");
+	}
+
+	if args.dry_run {
+		let mut bands: Vec<_> = tally.bands.into_iter().collect();
+		bands.sort_by_key(|&(_, freq)| freq);
+
+		diagnostics::summary(&format!("Would log {} QSOs with {} unique call signs on {} bands{}{}", tally.num_qsos, tally.contacts.len(), bands.len(),
+			if bands.is_empty() { "" } else { ": " },
+			bands.iter().map(|(band, _)| band.to_string()).collect::<Vec<_>>().join(", ")),
+			&[("qsos", tally.num_qsos), ("contacts", tally.contacts.len()), ("bands", bands.len())]);
+	} else if args.dedupe_contact.is_some() {
+		diagnostics::summary(&format!("Logged {} QSOs with {} unique call signs, skipping {} repeats", tally.num_qsos, tally.contacts.len(), tally.repeats.count),
+			&[("qsos", tally.num_qsos), ("contacts", tally.contacts.len()), ("repeats", tally.repeats.count)]);
+	} else {
+		diagnostics::summary(&format!("Logged {} QSOs with {} unique call signs", tally.num_qsos, tally.contacts.len()),
+			&[("qsos", tally.num_qsos), ("contacts", tally.contacts.len())]);
+	}
+	Ok(())
+}
+
+/// Print statistics about a spot database dump
+fn run_stats(args: StatsArgs) -> io::Result<()> {
+	let filter = spot_filter(&args.spots);
+	let mut num_rows = 0usize;
+	let mut num_spots = 0usize;
+	let mut errors = ParseErrors::new(&args.input);
+	let mut seen = Dedup::default();
+	let mut num_duplicates = 0usize;
+	let mut time_first = u64::MAX;
+	let mut time_last = u64::MIN;
 
 	// Unique call signs
-	let mut contacts = HashSet::new();
-
-	// Number of individual QSOs
-	let mut num_qsos = 0usize;
-
-	let pkg_name = env!("CARGO_PKG_NAME");
-	let pkg_version = env!("CARGO_PKG_VERSION");
-	println!("Mutual WSPR spots for {}\n\
-	         <ADIF_VER:5>3.1.1\
-	         <CREATED_TIMESTAMP:15>{}\
-	         <PROGRAMID:{}>{}\
-	         <PROGRAMVERSION:{}>{}\
-	         <EOH>",
-	         call_op, Utc::now().format("%Y%m%d %H%M%S"), pkg_name.len(), pkg_name, pkg_version.len(), pkg_version);
-
-	for line in stdin.lock().lines() {
+	let mut calls = HashSet::new();
+
+	// Spots per band
+	let mut bands = HashMap::<Band, usize>::new();
+
+	let default_mode = args.input.default_mode.clone();
+	let mut lines = lines(args.input, &args.spots)?;
+	while let Some(line) = lines.next() {
 		let row = line?;
+		num_rows += 1;
 
-		let last = match row.parse::<Spot>() {
+		if !filter.row(&row) {
+			continue;
+		}
+
+		let spot = match Spot::parse(&row, &default_mode) {
 			Ok(spot) => spot,
 			Err(err) => {
-				eprintln!("Failed to parse row: {err}\n\n{row}");
+				errors.record(lines.position(), &row, err.as_ref())?;
 				continue;
 			}
 		};
 
-		if last.call_rx != call_op && last.call_tx != call_op {
+		if !seen.insert(spot.id) {
+			num_duplicates += 1;
 			continue;
 		}
 
-		// Start new cycle
-		if last.cycle() > cycle {
-			cycle = last.cycle();
+		if !filter.spot(&spot) {
+			continue;
+		}
 
-			// Purge reporter spots
-			rx.retain(|spot| {
-				spot.cycle() >= cycle - 2
-			});
+		if let Some(Calls(calls)) = &args.call {
+			if !calls.contains(&spot.call_rx) && !calls.contains(&spot.call_tx) {
+				continue;
+			}
+		}
+
+		num_spots += 1;
+		time_first = cmp::min(time_first, spot.timestamp);
+		time_last = cmp::max(time_last, spot.timestamp);
+
+		if let Ok(band) = Band::try_from(spot.frequency) {
+			*bands.entry(band).or_default() += 1;
+		}
+
+		calls.insert(spot.call_rx);
+		calls.insert(spot.call_tx);
+	}
+
+	errors.finish();
+
+	if num_duplicates > 0 {
+		warn!(event = "duplicates_skipped", spots = num_duplicates; "Skipped {num_duplicates} duplicate spots");
+	}
+
+	println!("Rows: {num_rows}");
+	println!("Spots: {num_spots}");
+	println!("Unique call signs: {}", calls.len());
+
+	if num_spots > 0 {
+		let fmt_time = |time: u64| {
+			DateTime::<Utc>::from_timestamp(time as i64, 0).unwrap_or_default().format("%Y-%m-%d %H:%M")
+		};
+
+		println!("First spot: {}", fmt_time(time_first));
+		println!("Last spot: {}", fmt_time(time_last));
+	}
+
+	let mut bands: Vec<_> = bands.into_iter().collect();
+	bands.sort_by(|(_, a), (_, b)| b.cmp(a));
+	for (band, count) in bands {
+		println!("Spots on {band}: {count}");
+	}
 
-			// Purge transmitter spots
-			tx.retain(|spot| {
-				spot.cycle() >= cycle - 2
-			});
+	Ok(())
+}
+
+/// Write the spots matching the given criteria in WSPRnet CSV format
+fn run_filter(args: FilterArgs) -> io::Result<()> {
+	let filter = spot_filter(&args.spots);
+	let stdout = io::stdout();
+	let mut out = io::BufWriter::new(stdout.lock());
+	let mut errors = ParseErrors::new(&args.input);
+	let mut seen = Dedup::default();
+	let mut num_duplicates = 0usize;
+
+	let default_mode = args.input.default_mode.clone();
+	let mut lines = lines(args.input, &args.spots)?;
+	while let Some(line) = lines.next() {
+		let row = line?;
+
+		if !filter.row(&row) {
+			continue;
 		}
 
-		let band_last = match Band::try_from(last.frequency) {
-			Ok(band) => band,
+		let spot = match Spot::parse(&row, &default_mode) {
+			Ok(spot) => spot,
 			Err(err) => {
-				eprintln!("Unable to determine band for {}: {}", last.frequency, err);
+				errors.record(lines.position(), &row, err.as_ref())?;
 				continue;
 			}
 		};
 
-		// Spots as reporter
-		if last.call_rx == call_op {
-			if EXCLUDED.contains(last.call_tx.as_ref()) {
+		if !seen.insert(spot.id) {
+			num_duplicates += 1;
+			continue;
+		}
+
+		if !filter.spot(&spot) {
+			continue;
+		}
+
+		if let Some(Calls(calls)) = &args.call {
+			if !calls.contains(&spot.call_rx) && !calls.contains(&spot.call_tx) {
 				continue;
 			}
+		}
 
-			for spot in &tx {
-				if spot.call_rx == last.call_tx &&
-				   spot.grid_rx == last.grid_tx &&
-				   spot.grid_tx == last.grid_rx {
-					let band_spot = Band::try_from(spot.frequency).unwrap();
-					qsos.entry(QsoKey(last.call_tx.clone(), last.grid_rx.clone(), last.grid_tx.clone(), band_last.clone(), band_spot)).or_insert_with(|| {
-						Qso::new(&last, spot)
-					}).update(&last, spot);
-				}
-			}
+		writeln!(out, "{row}")?;
+	}
 
-			rx.push_back(last);
-		// Spots as transmitter
-		} else if last.call_tx == call_op {
-			if EXCLUDED.contains(last.call_rx.as_ref()) {
+	errors.finish();
+
+	if num_duplicates > 0 {
+		warn!(event = "duplicates_skipped", spots = num_duplicates; "Skipped {num_duplicates} duplicate spots");
+	}
+
+	out.flush()
+}
+
+/// Row in WSPRnet CSV format with the mode column filled in if missing or empty
+fn with_mode(row: &str, mode: Mode) -> std::string::String {
+	let mut fields: Vec<&str> = row.split(',').collect();
+	let mode = mode.to_string();
+
+	if fields.len() < 16 {
+		fields.resize(16, "");
+	}
+
+	if fields[15].is_empty() {
+		fields[15] = &mode;
+	}
+
+	fields.join(",")
+}
+
+/// Destination of converted spots
+enum SpotWriter {
+	Csv(io::BufWriter<io::Stdout>),
+	#[cfg(feature = "parquet")]
+	Parquet(Box<parquet::Spots<io::BufWriter<io::Stdout>>>),
+}
+
+/// Write the spots of any input format in the given format to standard output
+fn run_convert(args: ConvertArgs) -> io::Result<()> {
+	let mut errors = ParseErrors::new(&args.input);
+	let mut seen = Dedup::default();
+	let mut num_duplicates = 0usize;
+
+	let mut out = match args.to {
+		SpotFormat::Csv => SpotWriter::Csv(io::BufWriter::new(io::stdout())),
+		#[cfg(feature = "parquet")]
+		SpotFormat::Parquet => SpotWriter::Parquet(Box::new(parquet::Spots::new(io::BufWriter::new(io::stdout()))?)),
+	};
+
+	let default_mode = args.input.default_mode.clone();
+	let mut lines = lines(args.input, &SpotFilterArgs::default())?;
+	while let Some(line) = lines.next() {
+		let row = line?;
+
+		let spot = match Spot::parse(&row, &default_mode) {
+			Ok(spot) => spot,
+			Err(err) => {
+				errors.record(lines.position(), &row, err.as_ref())?;
 				continue;
 			}
+		};
 
-			for spot in &rx {
-				if spot.call_tx == last.call_rx &&
-				   spot.grid_rx == last.grid_tx &&
-				   spot.grid_tx == last.grid_rx {
-					let band_spot = Band::try_from(spot.frequency).unwrap();
-					qsos.entry(QsoKey(last.call_rx.clone(), last.grid_tx.clone(), last.grid_rx.clone(), band_spot, band_last.clone())).or_insert_with(|| {
-						Qso::new(spot, &last)
-					}).update(spot, &last);
-				}
-			}
+		if !seen.insert(spot.id) {
+			num_duplicates += 1;
+			continue;
+		}
+
+		match &mut out {
+			SpotWriter::Csv(out) => match DefaultMode::choose(&default_mode, spot.frequency) {
+				Some(mode) => writeln!(out, "{}", with_mode(&row, mode))?,
+				None => writeln!(out, "{row}")?,
+			},
+			#[cfg(feature = "parquet")]
+			SpotWriter::Parquet(out) => out.write(&spot)?,
+		}
+	}
+
+	errors.finish();
+
+	if num_duplicates > 0 {
+		warn!(event = "duplicates_skipped", spots = num_duplicates; "Skipped {num_duplicates} duplicate spots");
+	}
+
+	match out {
+		SpotWriter::Csv(mut out) => out.flush(),
+		#[cfg(feature = "parquet")]
+		SpotWriter::Parquet(out) => out.finish()?.flush(),
+	}
+}
+
+/// Download monthly archives and write their contents to standard output
+#[cfg(feature = "fetch")]
+fn run_fetch(args: cli::FetchArgs) -> io::Result<()> {
+	let dir = match args.cache_dir {
+		Some(dir) => dir,
+		None => fetch::cache_dir()?,
+	};
 
-			tx.push_back(last);
+	let fetcher = fetch::Fetcher::new(&args.base_url, dir, args.refresh)?;
+	let stdout = io::stdout();
+	let mut out = stdout.lock();
+
+	for month in args.months.months() {
+		let path = fetcher.archive(month)?;
+
+		if args.download_only {
+			writeln!(out, "{}", path.display())?;
+		} else {
+			io::copy(&mut input::open(&path, None)?, &mut out)?;
 		}
+	}
+
+	out.flush()
+}
+
+fn main() -> io::Result<()> {
+	let args = config::apply(&Cli::command(), std::env::args_os().collect())
+		.map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
 
+	let cli = Cli::parse_from(args);
 
-		// Log QSOs with no more spots
-		for (_, qso) in qsos.drain_filter(|_, qso| {
-			qso.cycle_last() < cycle - 2
-		}) {
-			println!("{qso}");
-			contacts.insert(qso.call_ct);
-			num_qsos += 1;
+	diagnostics::init(cli.verbosity.level(), cli.verbosity.log_format);
+
+	if let Some(plan) = cli.band_plan {
+		bandplan::set(plan);
+	}
+
+	if let Some(width) = cli.frequency_buckets {
+		bandplan::set_buckets(width);
+	}
+
+	let result = match cli.command.unwrap_or(Command::Match(cli.matching)) {
+		Command::Match(args) => {
+			let (calls, options) = args.into_parts();
+			run_match(Some(calls), options)
+		},
+		Command::Pairs(args) => run_match(None, args.options),
+		Command::Stats(args) => run_stats(args),
+		Command::Filter(args) => run_filter(args),
+		Command::Convert(args) => run_convert(args),
+		#[cfg(feature = "fetch")]
+		Command::Fetch(args) => run_fetch(args),
+	};
+
+	if let Err(err) = &result {
+		if diagnostics::error(err) {
+			std::process::exit(1);
 		}
 	}
 
-	eprintln!("Logged {} QSOs with {} unique call signs", num_qsos, contacts.len());
-	Ok(())
+	result
 }
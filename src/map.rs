@@ -0,0 +1,249 @@
+use std::collections::HashSet;
+use std::io;
+use std::io::prelude::*;
+
+use serde_json::{Value, json};
+
+use crate::{Band, Call, Grid, Qso};
+use crate::locator;
+
+/// Colours of the bands as RGB, from long to short wavelengths
+const COLORS: &[(&str, &str)] = &[
+	("13.5kHz", "543005"),
+	("2200m", "7f3b08"),
+	("160kHz", "7f3b08"),
+	("630m", "b35806"),
+	("560m", "b35806"),
+	("160m", "e08214"),
+	("80m", "d73027"),
+	("60m", "f46d43"),
+	("40m", "fdae61"),
+	("30m", "fee08b"),
+	("20m", "a6d96a"),
+	("17m", "66bd63"),
+	("15m", "1a9850"),
+	("12m", "35978f"),
+	("10m", "3288bd"),
+	("8m", "5e4fa2"),
+	("6m", "542788"),
+	("5m", "8073ac"),
+	("4m", "b2abd2"),
+	("2m", "de77ae"),
+];
+
+/// Colour of bands not listed
+const OTHER_COLOR: &str = "808080";
+
+/// Approximate length of the segments of paths in km
+const SEGMENT_LENGTH: f64 = 100.0;
+
+/// Largest number of segments of a path
+const MAX_SEGMENTS: usize = 64;
+
+/// Name of the band of a QSO, such as `20m`
+pub fn band(qso: &Qso) -> Option<std::string::String> {
+	Band::try_from(qso.freq_op).ok().as_ref().map(Band::name)
+}
+
+/// Colour of a band as RGB in hexadecimal
+pub fn color(band: Option<&str>) -> &'static str {
+	COLORS.iter()
+		.find(|&&(name, _)| Some(name) == band)
+		.map_or(OTHER_COLOR, |&(_, color)| color)
+}
+
+/// Great circle path between the locators of a QSO as latitude and longitude in degrees
+pub fn path(qso: &Qso) -> Option<Vec<(f64, f64)>> {
+	let a = locator::position(&qso.grid_op)?;
+	let b = locator::position(&qso.grid_ct)?;
+	let distance = locator::distance(&qso.grid_op, &qso.grid_ct)?;
+	let segments = (distance / SEGMENT_LENGTH).ceil() as usize;
+	Some(locator::great_circle(a, b, segments.clamp(1, MAX_SEGMENTS)))
+}
+
+/// Continue the longitudes of a path beyond ±180° where it crosses the antimeridian
+///
+/// This keeps maps that do not wrap around from drawing such paths the long way round.
+pub fn continuous(path: Vec<(f64, f64)>) -> Vec<(f64, f64)> {
+	let mut offset = 0.0;
+	let mut last: Option<f64> = None;
+
+	path.into_iter().map(|(lat, lon)| {
+		match last {
+			Some(last) if lon - last > 180.0 => offset -= 360.0,
+			Some(last) if last - lon > 180.0 => offset += 360.0,
+			_ => (),
+		}
+
+		last = Some(lon);
+		(lat, lon + offset)
+	}).collect()
+}
+
+/// Round degrees to about 10 m, which is plenty for the centres of locators
+fn round(degrees: f64) -> f64 {
+	(degrees * 1e4).round() / 1e4
+}
+
+/// Escape text for XML
+pub fn escape(text: &str) -> std::string::String {
+	text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// Map of QSOs in KML format for Google Earth
+///
+/// Every station gets a placemark at its locator, and every QSO a great circle path from the
+/// operator to the contact, coloured by band.
+pub struct Kml<W: Write> {
+	out: W,
+	/// Stations with a placemark
+	stations: HashSet<(Call, Grid)>,
+}
+
+impl<W: Write> Kml<W> {
+	/// Start a new map, writing the styles of the bands
+	pub fn new(mut out: W, title: &str) -> io::Result<Self> {
+		writeln!(out, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+		writeln!(out, "<kml xmlns=\"http://www.opengis.net/kml/2.2\">")?;
+		writeln!(out, "<Document>")?;
+		writeln!(out, "<name>{}</name>", escape(title))?;
+
+		for &(name, color) in COLORS.iter().chain([&("other", OTHER_COLOR)]) {
+			// KML colours are given as alpha, blue, green and red
+			let abgr = format!("ff{}{}{}", &color[4..6], &color[2..4], &color[0..2]);
+			writeln!(out, "<Style id=\"{name}\"><IconStyle><color>{abgr}</color></IconStyle>\
+			               <LineStyle><color>{abgr}</color><width>2</width></LineStyle></Style>")?;
+		}
+
+		Ok(Kml { out, stations: HashSet::new() })
+	}
+
+	/// Add placemarks for a station unless already added
+	fn station(&mut self, call: &Call, grid: &Grid, style: &str) -> io::Result<()> {
+		let Some((lat, lon)) = locator::position(grid) else {
+			return Ok(());
+		};
+
+		if self.stations.insert((call.clone(), grid.clone())) {
+			writeln!(self.out, "<Placemark><name>{}</name><description>{}</description><styleUrl>#{style}</styleUrl>\
+			                    <Point><coordinates>{lon:.4},{lat:.4}</coordinates></Point></Placemark>",
+			         escape(call), escape(grid))?;
+		}
+
+		Ok(())
+	}
+
+	/// Add the path of a QSO and placemarks for both stations
+	pub fn write(&mut self, qso: &Qso) -> io::Result<()> {
+		let band = band(qso);
+		let style = match band.as_deref() {
+			Some(band) if COLORS.iter().any(|&(name, _)| name == band) => band,
+			_ => "other",
+		}.to_owned();
+
+		self.station(&qso.call_op, &qso.grid_op, &style)?;
+		self.station(&qso.call_ct, &qso.grid_ct, &style)?;
+
+		let Some(path) = path(qso) else {
+			return Ok(());
+		};
+
+		let coordinates = path.iter()
+			.map(|(lat, lon)| format!("{lon:.4},{lat:.4}"))
+			.collect::<Vec<_>>()
+			.join(" ");
+
+		writeln!(self.out, "<Placemark><name>{} – {}</name><description>{}, {} UTC, SNR {} dB, {} km</description>\
+		                    <styleUrl>#{style}</styleUrl><LineString><tessellate>1</tessellate>\
+		                    <coordinates>{coordinates}</coordinates></LineString></Placemark>",
+		         escape(&qso.call_op), escape(&qso.call_ct), qso.band_description(),
+		         qso.datetime_on().format("%Y-%m-%d %H:%M"), qso.snr_ct, qso.distance)
+	}
+
+	/// Write the end of the document
+	pub fn finish(mut self) -> io::Result<W> {
+		writeln!(self.out, "</Document>")?;
+		writeln!(self.out, "</kml>")?;
+		Ok(self.out)
+	}
+}
+
+/// Map of QSOs as a GeoJSON feature collection for web maps
+///
+/// Every station is a point feature at its locator, and every QSO a line string feature along the
+/// great circle path from the operator to the contact. Longitudes of paths are continued beyond
+/// ±180° rather than split where they cross the antimeridian.
+pub struct GeoJson<W: Write> {
+	out: W,
+	/// Stations with a feature
+	stations: HashSet<(Call, Grid)>,
+	/// Whether any features were written
+	started: bool,
+}
+
+impl<W: Write> GeoJson<W> {
+	/// Start a new feature collection
+	pub fn new(mut out: W, title: &str) -> io::Result<Self> {
+		write!(out, "{{\"type\":\"FeatureCollection\",\"name\":{},\"features\":[", json!(title))?;
+		Ok(GeoJson { out, stations: HashSet::new(), started: false })
+	}
+
+	/// Write a feature on a line of its own
+	fn feature(&mut self, geometry: Value, properties: Value) -> io::Result<()> {
+		let feature = json!({
+			"type": "Feature",
+			"geometry": geometry,
+			"properties": properties,
+		});
+
+		write!(self.out, "{}\n{feature}", if self.started { "," } else { "" })?;
+		self.started = true;
+		Ok(())
+	}
+
+	/// Add a point feature for a station unless already added
+	fn station(&mut self, call: &Call, grid: &Grid) -> io::Result<()> {
+		let Some((lat, lon)) = locator::position(grid) else {
+			return Ok(());
+		};
+
+		if self.stations.insert((call.clone(), grid.clone())) {
+			self.feature(json!({ "type": "Point", "coordinates": [lon, lat] }),
+			             json!({ "call": call.as_str(), "grid": grid.as_str() }))?;
+		}
+
+		Ok(())
+	}
+
+	/// Add the path of a QSO and point features for both stations
+	pub fn write(&mut self, qso: &Qso) -> io::Result<()> {
+		self.station(&qso.call_op, &qso.grid_op)?;
+		self.station(&qso.call_ct, &qso.grid_ct)?;
+
+		let Some(path) = path(qso) else {
+			return Ok(());
+		};
+
+		let coordinates: Vec<_> = continuous(path).into_iter()
+			.map(|(lat, lon)| json!([round(lon), round(lat)]))
+			.collect();
+
+		let band = band(qso);
+		self.feature(json!({ "type": "LineString", "coordinates": coordinates }), json!({
+			"my_call": qso.call_op.as_str(),
+			"call": qso.call_ct.as_str(),
+			"time_on": qso.datetime_on().format("%Y-%m-%dT%H:%M:%SZ").to_string(),
+			"band": band,
+			"color": format!("#{}", color(band.as_deref())),
+			"my_snr": qso.snr_op,
+			"snr": qso.snr_ct,
+			"distance": qso.distance,
+		}))
+	}
+
+	/// Write the end of the feature collection
+	pub fn finish(mut self) -> io::Result<W> {
+		writeln!(self.out, "\n]}}")?;
+		Ok(self.out)
+	}
+}
@@ -0,0 +1,757 @@
+use std::cmp;
+use std::collections::{HashMap, VecDeque};
+use std::collections::hash_map::Entry;
+use std::convert::TryFrom;
+use std::hash::Hash;
+use std::io;
+use std::io::prelude::*;
+use std::mem;
+use std::rc::Rc;
+
+use clap::ValueEnum;
+use log::{info, warn};
+
+use crate::{Band, Call, Grid, Qso, Spot};
+use crate::checkpoint::{self, Decode, Encode};
+use crate::filter::Filter;
+use crate::locator;
+
+/// Matching of spots without a valid locator for a station
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum GridPolicy {
+	/// Do not match the spots
+	Skip,
+	/// Match the spots by call sign only
+	Call,
+	/// Take the station to be at the locator given with --station-grid, or skip the spots
+	Substitute,
+}
+
+/// Matching parameters
+#[derive(Clone, Copy, Debug)]
+pub struct Settings {
+	/// Number of cycles to look back for reciprocal spots, in the mode of each spot
+	pub lookback: u64,
+	/// Number of cycles without spots after which a QSO is closed, in the mode of the QSO
+	pub qso_gap: u64,
+	/// Maximum duration of a QSO in seconds before it is split
+	pub max_duration: Option<u64>,
+	/// Only pair spots on the same band
+	pub same_band: bool,
+	/// Number of Maidenhead locator characters to compare, or all if unset
+	pub grid_precision: Option<usize>,
+	/// Close QSOs when a station changes its locator
+	pub rover: bool,
+	/// Match compound call signs by their base call sign
+	pub normalize_calls: bool,
+	/// Keep the distance reported in the spots instead of computing it from the locators
+	pub reported_distance: bool,
+	/// Matching of spots without a valid locator
+	pub invalid_grid: GridPolicy,
+}
+
+/// Base call sign of a compound call sign such as `EA8/DL1ABC` or `DL1ABC/P`
+///
+/// This is taken to be the longest part, which also contains a digit.
+fn base_call(call: &str) -> &str {
+	call.split('/')
+		.filter(|part| part.bytes().any(|ch| ch.is_ascii_digit()))
+		.fold(None, |base: Option<&str>, part| match base {
+			Some(base) if base.len() >= part.len() => Some(base),
+			_ => Some(part),
+		})
+		.unwrap_or(call)
+}
+
+impl Settings {
+	/// Whether two call signs denote the same station
+	fn same_call(&self, a: &Call, b: &Call) -> bool {
+		if self.normalize_calls {
+			base_call(a).eq_ignore_ascii_case(base_call(b))
+		} else {
+			a == b
+		}
+	}
+
+	/// Call sign reduced to the base call sign for use in keys, if enabled
+	fn call_key(&self, call: &Call) -> Call {
+		if self.normalize_calls {
+			Call::new(base_call(call).into())
+		} else {
+			call.clone()
+		}
+	}
+
+	/// Whether two locators denote the same station location
+	///
+	/// Missing locators match any under the call policy and none otherwise.
+	fn same_grid(&self, a: &Grid, b: &Grid) -> bool {
+		if a.is_empty() || b.is_empty() {
+			return self.invalid_grid == GridPolicy::Call;
+		}
+
+		match self.grid_precision {
+			Some(precision) => a.chars().take(precision)
+				.map(|ch| ch.to_ascii_uppercase())
+				.eq(b.chars().take(precision).map(|ch| ch.to_ascii_uppercase())),
+			None => a == b,
+		}
+	}
+
+	/// Earliest cycle of the spots still looked back to in the given cycle
+	///
+	/// Near the start of the epoch, this is the first cycle rather than wrapping around.
+	fn oldest_spot(&self, cycle: u64) -> u64 {
+		cycle.saturating_sub(self.lookback)
+	}
+
+	/// Earliest last cycle of the QSOs still open in the given cycle
+	fn oldest_qso(&self, cycle: u64) -> u64 {
+		cycle.saturating_sub(self.qso_gap)
+	}
+
+	/// Locator reduced to the compared precision for use in keys
+	fn grid_key(&self, grid: &Grid) -> Grid {
+		match self.grid_precision {
+			Some(precision) => Grid::new(grid.chars().take(precision).map(|ch| ch.to_ascii_uppercase()).collect()),
+			None => grid.clone(),
+		}
+	}
+}
+
+/// The first of two locators of a station unless missing
+fn either<'a>(a: &'a Grid, b: &'a Grid) -> &'a Grid {
+	if a.is_empty() { b } else { a }
+}
+
+/// Locators of stations for spots without a valid one
+///
+/// Compound call signs are transmitted in a message without locator, so that many of their spots
+/// lack one. These are taken to be at the locator spotted within the look back window instead.
+struct Locators {
+	/// Locators learned from the spots, with the time they were last spotted at them
+	learned: HashMap<Call, (Grid, u64)>,
+	/// Locators given for stations
+	given: Rc<HashMap<Call, Grid>>,
+}
+
+impl Locators {
+	fn new(given: Rc<HashMap<Call, Grid>>) -> Self {
+		Locators { learned: HashMap::new(), given }
+	}
+
+	/// Learn the valid locators of the reporter and transmitter of a spot
+	fn learn(&mut self, spot: &Spot, settings: &Settings) {
+		for (call, grid) in [(&spot.call_rx, &spot.grid_rx), (&spot.call_tx, &spot.grid_tx)] {
+			if locator::position(grid).is_none() {
+				continue;
+			}
+
+			match self.learned.entry(settings.call_key(call)) {
+				Entry::Occupied(mut entry) if entry.get().1 <= spot.timestamp => {
+					entry.insert((grid.clone(), spot.timestamp));
+				},
+				Entry::Occupied(_) => (),
+				Entry::Vacant(entry) => {
+					entry.insert((grid.clone(), spot.timestamp));
+				},
+			}
+		}
+	}
+
+	/// Fill in the invalid locators of a spot with those learned within the look back window
+	///
+	/// Locators that cannot be filled in are given for the station under the substitute policy and
+	/// left empty as missing otherwise.
+	fn fill(&self, spot: &mut Spot, settings: &Settings) {
+		let (cycle, mode) = (spot.cycle(), spot.mode);
+		for (call, grid) in [(&spot.call_rx, &mut spot.grid_rx), (&spot.call_tx, &mut spot.grid_tx)] {
+			if locator::position(grid).is_some() {
+				continue;
+			}
+
+			*grid = match self.learned.get(&settings.call_key(call)) {
+				Some((known, time)) if cycle.abs_diff(mode.cycle(*time)) <= settings.lookback => known.clone(),
+				_ => match self.given.get(call) {
+					Some(given) if settings.invalid_grid == GridPolicy::Substitute => given.clone(),
+					_ => Grid::new("".into()),
+				},
+			};
+		}
+	}
+}
+
+/// Add a pair of mutual spots to a QSO
+///
+/// If the spots are too far from the QSO in time, it is closed and a new QSO is started instead.
+fn record<K: Hash + Eq>(qsos: &mut HashMap<K, Qso>, key: K, op: &Spot, ct: &Spot, settings: &Settings, closed: &mut Vec<Qso>) {
+	match qsos.entry(key) {
+		Entry::Occupied(mut entry) => {
+			let qso = entry.get_mut();
+			let time = cmp::max(op.timestamp, ct.timestamp);
+
+			if settings.oldest_qso(qso.mode.cycle(time)) > qso.cycle_last() ||
+			   settings.max_duration.is_some_and(|max| time.saturating_sub(qso.time_first) >= max) {
+				closed.push(mem::replace(qso, Qso::new(op, ct)));
+				info!(event = "qso_opened", call_op:% = op.call_rx, call_ct:% = op.call_tx; "Opened QSO between {} and {}", op.call_rx, op.call_tx);
+			}
+
+			qso.update(op, ct);
+			if !settings.reported_distance {
+				qso.measure();
+			}
+		},
+		Entry::Vacant(entry) => {
+			info!(event = "qso_opened", call_op:% = op.call_rx, call_ct:% = op.call_tx; "Opened QSO between {} and {}", op.call_rx, op.call_tx);
+			let qso = entry.insert(Qso::new(op, ct));
+			qso.update(op, ct);
+			if !settings.reported_distance {
+				qso.measure();
+			}
+		}
+	}
+}
+
+/// Close the QSOs of a station that moved to a different locator
+fn relocate<K: Hash + Eq>(qsos: &mut HashMap<K, Qso>, call: &Call, grid: &Grid, settings: &Settings, closed: &mut Vec<Qso>) {
+	closed.extend(qsos.extract_if(|_, qso| {
+		(settings.same_call(&qso.call_op, call) && !settings.same_grid(&qso.grid_op, grid)) ||
+		(settings.same_call(&qso.call_ct, call) && !settings.same_grid(&qso.grid_ct, grid))
+	}).map(|(_, qso)| qso));
+}
+
+/// Close the QSOs without spots since the given time
+fn expire<K: Hash + Eq>(qsos: &mut HashMap<K, Qso>, time: u64, settings: &Settings) -> Vec<Qso> {
+	qsos.extract_if(|_, qso| qso.cycle_last() < settings.oldest_qso(qso.mode.cycle(time)))
+		.map(|(_, qso)| qso)
+		.collect()
+}
+
+#[derive(Hash, PartialEq, Eq)]
+struct QsoKey(Call, Grid, Grid, Band, Band);
+
+/// Correlates mutual spots of a single operator into QSOs
+pub struct Matcher {
+	/// Operator call sign
+	call_op: Call,
+	/// Spot and contact criteria
+	filter: Rc<Filter>,
+	/// Matching parameters
+	settings: Settings,
+	/// Start of the current cycle, in the mode of the spot starting it
+	time: u64,
+	/// Last known operator locator
+	grid_op: Option<Grid>,
+	/// Locators for spots without a valid one
+	locators: Locators,
+	/// Look back queue of spots as reporter
+	rx: VecDeque<Spot>,
+	/// Look back queue of spots as transmitter
+	tx: VecDeque<Spot>,
+	/// Active QSOs
+	qsos: HashMap<QsoKey, Qso>,
+}
+
+impl Matcher {
+	pub fn new(call_op: Call, filter: Rc<Filter>, settings: Settings, grids: Rc<HashMap<Call, Grid>>) -> Self {
+		Matcher {
+			call_op,
+			filter,
+			settings,
+			time: 0,
+			grid_op: None,
+			locators: Locators::new(grids),
+			rx: VecDeque::new(),
+			tx: VecDeque::new(),
+			qsos: HashMap::new(),
+		}
+	}
+
+	/// Whether the operator is reporter or transmitter of a spot
+	pub fn involves(&self, spot: &Spot) -> bool {
+		self.settings.same_call(&spot.call_rx, &self.call_op) || self.settings.same_call(&spot.call_tx, &self.call_op)
+	}
+
+	/// Process a spot involving the operator and return QSOs with no more spots
+	pub fn push(&mut self, mut last: Spot) -> Vec<Qso> {
+		let mut closed = Vec::new();
+
+		// Start new cycle
+		if last.cycle() > last.mode.cycle(self.time) {
+			self.time = last.cycle() * last.mode.period();
+			let now = self.time;
+
+			// Purge reporter spots
+			self.rx.retain(|spot| {
+				spot.cycle() >= self.settings.oldest_spot(spot.mode.cycle(now))
+			});
+
+			// Purge transmitter spots
+			self.tx.retain(|spot| {
+				spot.cycle() >= self.settings.oldest_spot(spot.mode.cycle(now))
+			});
+		}
+
+		self.locators.learn(&last, &self.settings);
+		self.locators.fill(&mut last, &self.settings);
+
+		if self.settings.rover {
+			let grid = if self.settings.same_call(&last.call_rx, &self.call_op) { &last.grid_rx } else { &last.grid_tx };
+			match &self.grid_op {
+				_ if locator::position(grid).is_none() => (),
+				Some(grid_op) if self.settings.same_grid(grid_op, grid) => (),
+				grid_op => {
+					if let Some(grid_op) = grid_op {
+						info!(event = "moved", call:% = self.call_op, grid:% = grid; "{} moved from {} to {}", self.call_op, grid_op, grid);
+					}
+
+					relocate(&mut self.qsos, &self.call_op, grid, &self.settings, &mut closed);
+					self.grid_op = Some(grid.clone());
+				}
+			}
+		}
+
+		let band_last = match Band::try_from(last.frequency) {
+			Ok(band) => band,
+			Err(err) => {
+				warn!(event = "unknown_band", frequency = last.frequency.0; "Unable to determine band for {}: {}", last.frequency, err);
+				return closed;
+			}
+		};
+
+		// Spots as reporter
+		if self.settings.same_call(&last.call_rx, &self.call_op) {
+			if !self.filter.contact(&last.call_tx) || !self.filter.grids(&last.grid_rx, &last.grid_tx) {
+				return closed;
+			}
+
+			for spot in &mut self.tx {
+				self.locators.fill(spot, &self.settings);
+				if self.settings.same_call(&spot.call_rx, &last.call_tx) &&
+				   self.settings.same_grid(&spot.grid_rx, &last.grid_tx) &&
+				   self.settings.same_grid(&spot.grid_tx, &last.grid_rx) {
+					let Ok(band_spot) = Band::try_from(spot.frequency) else {
+						continue;
+					};
+					if self.settings.same_band && band_spot != band_last {
+						continue;
+					}
+
+					let key = QsoKey(self.settings.call_key(&last.call_tx), self.settings.grid_key(either(&last.grid_rx, &spot.grid_tx)), self.settings.grid_key(either(&last.grid_tx, &spot.grid_rx)), band_last.clone(), band_spot);
+					record(&mut self.qsos, key, &last, spot, &self.settings, &mut closed);
+				}
+			}
+
+			self.rx.push_back(last);
+		// Spots as transmitter
+		} else if self.settings.same_call(&last.call_tx, &self.call_op) {
+			if !self.filter.contact(&last.call_rx) || !self.filter.grids(&last.grid_tx, &last.grid_rx) {
+				return closed;
+			}
+
+			for spot in &mut self.rx {
+				self.locators.fill(spot, &self.settings);
+				if self.settings.same_call(&spot.call_tx, &last.call_rx) &&
+				   self.settings.same_grid(&spot.grid_rx, &last.grid_tx) &&
+				   self.settings.same_grid(&spot.grid_tx, &last.grid_rx) {
+					let Ok(band_spot) = Band::try_from(spot.frequency) else {
+						continue;
+					};
+					if self.settings.same_band && band_spot != band_last {
+						continue;
+					}
+
+					let key = QsoKey(self.settings.call_key(&last.call_rx), self.settings.grid_key(either(&last.grid_tx, &spot.grid_rx)), self.settings.grid_key(either(&last.grid_rx, &spot.grid_tx)), band_spot, band_last.clone());
+					record(&mut self.qsos, key, spot, &last, &self.settings, &mut closed);
+				}
+			}
+
+			self.tx.push_back(last);
+		}
+
+		// Close QSOs with no more spots
+		closed.extend(expire(&mut self.qsos, self.time, &self.settings));
+		closed
+	}
+
+	/// Close QSOs without spots since the given time and return them
+	pub fn expire(&mut self, time: u64) -> Vec<Qso> {
+		expire(&mut self.qsos, time, &self.settings)
+	}
+
+	/// Save the look back queues, learned locators and active QSOs
+	fn save(&self, out: &mut dyn Write) -> io::Result<()> {
+		self.time.encode(out)?;
+		self.grid_op.encode(out)?;
+		self.locators.learned.encode(out)?;
+		self.rx.encode(out)?;
+		self.tx.encode(out)?;
+		checkpoint::encode_seq(self.qsos.len(), self.qsos.values(), out)
+	}
+
+	/// Restore the state saved by [Matcher::save]
+	///
+	/// The keys of the QSOs are derived from them as they were from the spots opening them.
+	fn restore(&mut self, input: &mut dyn Read) -> io::Result<()> {
+		self.time = Decode::decode(input)?;
+		self.grid_op = Decode::decode(input)?;
+		self.locators.learned = Decode::decode(input)?;
+		self.rx = Decode::decode(input)?;
+		self.tx = Decode::decode(input)?;
+
+		for qso in checkpoint::decode_seq::<Qso, Vec<_>>(input)? {
+			let key = QsoKey(self.settings.call_key(&qso.call_ct), self.settings.grid_key(&qso.grid_op), self.settings.grid_key(&qso.grid_ct), Band::try_from(qso.freq_ct)?, Band::try_from(qso.freq_op)?);
+			self.qsos.insert(key, qso);
+		}
+
+		Ok(())
+	}
+}
+
+#[derive(Hash, PartialEq, Eq)]
+struct PairKey(Call, Call, Grid, Grid, Band, Band);
+
+/// Correlates mutual spots between all pairs of stations into QSOs
+///
+/// QSOs are logged from the perspective of the station whose call sign sorts first.
+pub struct PairMatcher {
+	/// Spot and contact criteria
+	filter: Rc<Filter>,
+	/// Matching parameters
+	settings: Settings,
+	/// Start of the current cycle, in the mode of the spot starting it
+	time: u64,
+	/// Look back queues by reporter and transmitter call sign
+	spots: HashMap<(Call, Call), VecDeque<Spot>>,
+	/// Last known locators by call sign
+	grids: HashMap<Call, Grid>,
+	/// Locators for spots without a valid one
+	locators: Locators,
+	/// Active QSOs
+	qsos: HashMap<PairKey, Qso>,
+}
+
+impl PairMatcher {
+	pub fn new(filter: Rc<Filter>, settings: Settings, grids: Rc<HashMap<Call, Grid>>) -> Self {
+		PairMatcher {
+			filter,
+			settings,
+			time: 0,
+			spots: HashMap::new(),
+			grids: HashMap::new(),
+			locators: Locators::new(grids),
+			qsos: HashMap::new(),
+		}
+	}
+
+	/// Process a spot and return QSOs with no more spots
+	pub fn push(&mut self, mut last: Spot) -> Vec<Qso> {
+		let mut closed = Vec::new();
+
+		// Start new cycle
+		if last.cycle() > last.mode.cycle(self.time) {
+			self.time = last.cycle() * last.mode.period();
+			let now = self.time;
+
+			// Purge spots and drop empty queues to bound memory usage
+			self.spots.retain(|_, spots| {
+				spots.retain(|spot| spot.cycle() >= self.settings.oldest_spot(spot.mode.cycle(now)));
+				!spots.is_empty()
+			});
+
+			// Close QSOs with no more spots
+			closed.extend(expire(&mut self.qsos, now, &self.settings));
+		}
+
+		if self.settings.same_call(&last.call_rx, &last.call_tx) {
+			return closed;
+		}
+
+		let call_rx = self.settings.call_key(&last.call_rx);
+		let call_tx = self.settings.call_key(&last.call_tx);
+
+		self.locators.learn(&last, &self.settings);
+		self.locators.fill(&mut last, &self.settings);
+
+		if self.settings.rover {
+			for (call, grid) in [(&call_rx, &last.grid_rx), (&call_tx, &last.grid_tx)] {
+				match self.grids.get_mut(call) {
+					_ if locator::position(grid).is_none() => (),
+					Some(known) if self.settings.same_grid(known, grid) => (),
+					Some(known) => {
+						info!(event = "moved", call:% = call, grid:% = grid; "{} moved from {} to {}", call, known, grid);
+						relocate(&mut self.qsos, call, grid, &self.settings, &mut closed);
+						*known = grid.clone();
+					},
+					None => {
+						self.grids.insert(call.clone(), grid.clone());
+					}
+				}
+			}
+		}
+
+		if !self.filter.pair(&last.call_rx, &last.call_tx) ||
+		   !(self.filter.grids(&last.grid_rx, &last.grid_tx) || self.filter.grids(&last.grid_tx, &last.grid_rx)) {
+			return closed;
+		}
+
+		let band_last = match Band::try_from(last.frequency) {
+			Ok(band) => band,
+			Err(err) => {
+				warn!(event = "unknown_band", frequency = last.frequency.0; "Unable to determine band for {}: {}", last.frequency, err);
+				return closed;
+			}
+		};
+
+		// Reciprocal spots with reporter and transmitter swapped
+		let reverse = (call_tx.clone(), call_rx.clone());
+		if let Some(spots) = self.spots.get_mut(&reverse) {
+			for spot in spots {
+				self.locators.fill(spot, &self.settings);
+				if self.settings.same_grid(&spot.grid_rx, &last.grid_tx) &&
+				   self.settings.same_grid(&spot.grid_tx, &last.grid_rx) {
+					let Ok(band_spot) = Band::try_from(spot.frequency) else {
+						continue;
+					};
+					if self.settings.same_band && band_spot != band_last {
+						continue;
+					}
+
+
+					// Spot as reporter and as transmitter of the logging station
+					let (op, ct, band_op, band_ct) = if call_rx < call_tx {
+						(&last, &*spot, band_last.clone(), band_spot)
+					} else {
+						(&*spot, &last, band_spot, band_last.clone())
+					};
+
+					let key = PairKey(self.settings.call_key(&op.call_rx), self.settings.call_key(&op.call_tx), self.settings.grid_key(either(&op.grid_rx, &ct.grid_tx)), self.settings.grid_key(either(&op.grid_tx, &ct.grid_rx)), band_op, band_ct);
+					record(&mut self.qsos, key, op, ct, &self.settings, &mut closed);
+				}
+			}
+		}
+
+		self.spots.entry((call_rx, call_tx))
+			.or_default()
+			.push_back(last);
+
+		closed
+	}
+
+	/// Close QSOs without spots since the given time and return them
+	pub fn expire(&mut self, time: u64) -> Vec<Qso> {
+		expire(&mut self.qsos, time, &self.settings)
+	}
+
+	/// Save the look back queues, known and learned locators and active QSOs
+	fn save(&self, out: &mut dyn Write) -> io::Result<()> {
+		self.time.encode(out)?;
+		checkpoint::encode_seq(self.spots.values().map(VecDeque::len).sum(), self.spots.values().flatten(), out)?;
+		self.grids.encode(out)?;
+		self.locators.learned.encode(out)?;
+		checkpoint::encode_seq(self.qsos.len(), self.qsos.values(), out)
+	}
+
+	/// Restore the state saved by [PairMatcher::save]
+	///
+	/// The keys of the queues and QSOs are derived from their contents as they were when added.
+	fn restore(&mut self, input: &mut dyn Read) -> io::Result<()> {
+		self.time = Decode::decode(input)?;
+
+		for spot in checkpoint::decode_seq::<Spot, Vec<_>>(input)? {
+			self.spots.entry((self.settings.call_key(&spot.call_rx), self.settings.call_key(&spot.call_tx)))
+				.or_default()
+				.push_back(spot);
+		}
+
+		self.grids = Decode::decode(input)?;
+		self.locators.learned = Decode::decode(input)?;
+
+		for qso in checkpoint::decode_seq::<Qso, Vec<_>>(input)? {
+			let key = PairKey(self.settings.call_key(&qso.call_op), self.settings.call_key(&qso.call_ct), self.settings.grid_key(&qso.grid_op), self.settings.grid_key(&qso.grid_ct), Band::try_from(qso.freq_ct)?, Band::try_from(qso.freq_op)?);
+			self.qsos.insert(key, qso);
+		}
+
+		Ok(())
+	}
+}
+
+/// QSO matching mode
+pub enum Matching {
+	/// Mutual spots involving any of the given operators
+	Operators(Vec<Matcher>),
+	/// Mutual spots between all pairs of stations
+	AllPairs(Box<PairMatcher>),
+}
+
+impl Matching {
+	/// Process a spot and return QSOs with no more spots
+	pub fn push(&mut self, spot: Spot) -> Vec<Qso> {
+		match self {
+			Matching::Operators(matchers) => {
+				let mut closed = Vec::new();
+				for matcher in matchers {
+					if matcher.involves(&spot) {
+						closed.extend(matcher.push(spot.clone()));
+					}
+				}
+
+				closed
+			},
+			Matching::AllPairs(matcher) => matcher.push(spot),
+		}
+	}
+
+	/// Close QSOs without spots since the given time and return them
+	pub fn expire(&mut self, time: u64) -> Vec<Qso> {
+		match self {
+			Matching::Operators(matchers) => matchers.iter_mut()
+				.flat_map(|matcher| matcher.expire(time))
+				.collect(),
+			Matching::AllPairs(matcher) => matcher.expire(time),
+		}
+	}
+
+	/// Close all QSOs still open at the end of the input and return them in order of their start
+	pub fn finish(&mut self) -> Vec<Qso> {
+		let mut closed: Vec<_> = match self {
+			Matching::Operators(matchers) => matchers.iter_mut()
+				.flat_map(|matcher| matcher.qsos.drain().map(|(_, qso)| qso))
+				.collect(),
+			Matching::AllPairs(matcher) => matcher.qsos.drain().map(|(_, qso)| qso).collect(),
+		};
+
+		closed.sort_by(|a, b| a.time_first.cmp(&b.time_first)
+			.then_with(|| a.call_op.cmp(&b.call_op))
+			.then_with(|| a.call_ct.cmp(&b.call_ct)));
+		closed
+	}
+
+	/// Save the matching state to a checkpoint
+	pub fn save(&self, out: &mut dyn Write) -> io::Result<()> {
+		match self {
+			Matching::Operators(matchers) => matchers.iter().try_for_each(|matcher| matcher.save(out)),
+			Matching::AllPairs(matcher) => matcher.save(out),
+		}
+	}
+
+	/// Restore the matching state from a checkpoint taken with the same operators
+	pub fn restore(&mut self, input: &mut dyn Read) -> io::Result<()> {
+		match self {
+			Matching::Operators(matchers) => matchers.iter_mut().try_for_each(|matcher| matcher.restore(input)),
+			Matching::AllPairs(matcher) => matcher.restore(input),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::Mode;
+
+	const SETTINGS: Settings = Settings {
+		lookback: 2,
+		qso_gap: 2,
+		max_duration: None,
+		same_band: false,
+		grid_precision: None,
+		rover: false,
+		normalize_calls: false,
+		reported_distance: false,
+		invalid_grid: GridPolicy::Skip,
+	};
+
+	/// Spot on 20 m between the test stations at their locators
+	fn spot(id: u64, timestamp: u64, call_rx: &str, call_tx: &str) -> Spot {
+		let grid = |call| if call == "K1ABC" { "FN42" } else { "JO62" };
+		format!("{id},{timestamp},{call_rx},{},-10,14.097100,{call_tx},{},37,0,6000", grid(call_rx), grid(call_tx))
+			.parse()
+			.unwrap()
+	}
+
+	/// Start of a WSPR-2 cycle
+	fn cycle(number: u64) -> u64 {
+		number * Mode::Wspr2.period()
+	}
+
+	fn operator() -> Matching {
+		Matching::Operators(vec![Matcher::new(Call::new("K1ABC".into()), Rc::default(), SETTINGS, Rc::default())])
+	}
+
+	fn pairs() -> Matching {
+		Matching::AllPairs(Box::new(PairMatcher::new(Rc::default(), SETTINGS, Rc::default())))
+	}
+
+	#[test]
+	fn pairs_spots_in_first_cycle() {
+		for mut matching in [operator(), pairs()] {
+			assert!(matching.push(spot(1, 0, "K1ABC", "DL1ABC")).is_empty());
+			assert!(matching.push(spot(2, 0, "DL1ABC", "K1ABC")).is_empty());
+
+			let qsos = matching.finish();
+			assert_eq!(qsos.len(), 1);
+			assert_eq!(qsos[0].time_first, 0);
+			assert_eq!(qsos[0].spots.iter().copied().collect::<Vec<_>>(), [1, 2]);
+		}
+	}
+
+	#[test]
+	fn looks_back_to_first_cycle() {
+		for mut matching in [operator(), pairs()] {
+			matching.push(spot(1, 0, "K1ABC", "DL1ABC"));
+			matching.push(spot(2, cycle(2), "DL1ABC", "K1ABC"));
+			assert_eq!(matching.finish().len(), 1);
+		}
+	}
+
+	#[test]
+	fn purges_spots_beyond_look_back() {
+		for mut matching in [operator(), pairs()] {
+			matching.push(spot(1, 0, "K1ABC", "DL1ABC"));
+			matching.push(spot(2, cycle(3), "DL1ABC", "K1ABC"));
+			assert!(matching.finish().is_empty());
+		}
+	}
+
+	#[test]
+	fn keeps_qso_open_in_first_cycles() {
+		for mut matching in [operator(), pairs()] {
+			matching.push(spot(1, 0, "K1ABC", "DL1ABC"));
+			matching.push(spot(2, 0, "DL1ABC", "K1ABC"));
+			assert!(matching.push(spot(3, cycle(1), "K1ABC", "DL1ABC")).is_empty());
+			assert!(matching.push(spot(4, cycle(2), "DL1ABC", "K1ABC")).is_empty());
+			assert!(matching.expire(cycle(2)).is_empty());
+
+			let qsos = matching.finish();
+			assert_eq!(qsos.len(), 1);
+			assert_eq!(qsos[0].spots.len(), 4);
+		}
+	}
+
+	#[test]
+	fn closes_qso_after_gap() {
+		for mut matching in [operator(), pairs()] {
+			matching.push(spot(1, 0, "K1ABC", "DL1ABC"));
+			matching.push(spot(2, 0, "DL1ABC", "K1ABC"));
+			assert!(matching.expire(cycle(2)).is_empty());
+
+			let closed = matching.expire(cycle(3));
+			assert_eq!(closed.len(), 1);
+			assert_eq!(closed[0].time_last, 0);
+			assert!(matching.finish().is_empty());
+		}
+	}
+
+	#[test]
+	fn starts_new_qso_after_gap() {
+		for mut matching in [operator(), pairs()] {
+			matching.push(spot(1, 0, "K1ABC", "DL1ABC"));
+			matching.push(spot(2, 0, "DL1ABC", "K1ABC"));
+			let closed = matching.push(spot(3, cycle(10), "K1ABC", "DL1ABC"));
+			assert!(matching.push(spot(4, cycle(10), "DL1ABC", "K1ABC")).is_empty());
+			assert_eq!(closed.len(), 1);
+			assert_eq!(closed[0].spots.iter().copied().collect::<Vec<_>>(), [1, 2]);
+			assert_eq!(matching.finish().len(), 1);
+		}
+	}
+}
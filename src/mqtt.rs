@@ -0,0 +1,164 @@
+use std::cmp;
+use std::fmt;
+use std::io;
+use std::io::prelude::*;
+use std::process;
+use std::str::FromStr;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use log::{debug, info, warn};
+use rumqttc::{Client, Event, MqttOptions, Packet, QoS};
+
+use crate::json;
+use crate::schema;
+
+/// Default port of MQTT brokers
+const DEFAULT_PORT: u16 = 1883;
+
+/// Number of rows received ahead of processing, beyond which messages are left to the broker
+const BUFFER: usize = 4096;
+
+/// Longest wait before reconnecting to a broker
+const MAX_DELAY: Duration = Duration::from_secs(60);
+
+/// Subscription to an MQTT topic given as `mqtt://[user[:password]@]host[:port]/topic`
+#[derive(Clone, Debug)]
+pub struct Subscription {
+	host: String,
+	port: u16,
+	credentials: Option<(String, String)>,
+	topic: String,
+}
+
+impl FromStr for Subscription {
+	type Err = String;
+
+	fn from_str(uri: &str) -> Result<Self, Self::Err> {
+		let rest = uri.strip_prefix("mqtt://").ok_or_else(|| format!("expected mqtt:// in {uri}"))?;
+		let (authority, topic) = rest.split_once('/').ok_or_else(|| format!("missing topic in {uri}"))?;
+
+		if topic.is_empty() {
+			return Err(format!("missing topic in {uri}"));
+		}
+
+		let (credentials, address) = match authority.rsplit_once('@') {
+			Some((userinfo, address)) => {
+				let (user, password) = userinfo.split_once(':').unwrap_or((userinfo, ""));
+				(Some((user.to_owned(), password.to_owned())), address)
+			},
+			None => (None, authority),
+		};
+
+		let (host, port) = match address.rsplit_once(':') {
+			Some((host, port)) => (host, port.parse().map_err(|_| format!("invalid port {port}"))?),
+			None => (address, DEFAULT_PORT),
+		};
+
+		if host.is_empty() {
+			return Err(format!("missing broker in {uri}"));
+		}
+
+		Ok(Subscription { host: host.to_owned(), port, credentials, topic: topic.to_owned() })
+	}
+}
+
+impl fmt::Display for Subscription {
+	/// URI without the credentials
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "mqtt://{}:{}/{}", self.host, self.port, self.topic)
+	}
+}
+
+/// Convert a message payload into rows in WSPRnet CSV format
+///
+/// Payloads are either JSON objects or arrays of spots, or lines of CSV in any layout detected
+/// from files without header.
+fn decode(payload: &[u8]) -> Vec<String> {
+	let text = String::from_utf8_lossy(payload);
+	let text = text.trim();
+
+	let rows: Vec<_> = if text.starts_with('{') {
+		vec![json::convert(text)]
+	} else if text.starts_with('[') {
+		match json::split(text.as_bytes()) {
+			Ok(lines) => lines.lines().map(|line| line.ok().and_then(|line| json::convert(&line))).collect(),
+			Err(_) => vec![None],
+		}
+	} else {
+		text.lines()
+			.map(str::trim)
+			.filter(|line| !line.is_empty())
+			.filter_map(schema::convert_row)
+			.map(Some)
+			.collect()
+	};
+
+	if rows.iter().any(Option::is_none) {
+		debug!("Skipping undecodable spots in message “{text}”");
+	}
+
+	rows.into_iter().flatten().collect()
+}
+
+/// Keep receiving spots of the subscription, sending them as rows until the receiver hangs up
+///
+/// The connection is reestablished after failures at increasing intervals.
+fn subscribe(subscription: &Subscription, idx: usize, tx: &mpsc::SyncSender<io::Result<String>>) {
+	// Stable across reconnections, so that the broker keeps the session
+	let id = format!("wsprspots-{}-{idx}", process::id());
+	let mut options = MqttOptions::new(id, &subscription.host, subscription.port);
+	options.set_keep_alive(Duration::from_secs(60)).set_clean_session(false);
+	if let Some((user, password)) = &subscription.credentials {
+		options.set_credentials(user, password);
+	}
+
+	let (client, mut connection) = Client::new(options, 16);
+	let mut delay = Duration::from_secs(1);
+
+	for event in connection.iter() {
+		match event {
+			Ok(Event::Incoming(Packet::ConnAck(ack))) => {
+				info!("Connected to {subscription}");
+				delay = Duration::from_secs(1);
+
+				if !ack.session_present {
+					if let Err(err) = client.subscribe(&subscription.topic, QoS::AtLeastOnce) {
+						let _ = tx.send(Err(io::Error::other(format!("{subscription}: {err}"))));
+						return;
+					}
+				}
+			},
+			Ok(Event::Incoming(Packet::Publish(publish))) => {
+				for row in decode(&publish.payload) {
+					// Blocks while the buffer is full
+					if tx.send(Ok(row)).is_err() {
+						return;
+					}
+				}
+			},
+			Ok(_) => (),
+			Err(err) => {
+				warn!("Connection to {subscription} failed: {err}");
+				thread::sleep(delay);
+				delay = cmp::min(delay * 2, MAX_DELAY);
+			},
+		}
+	}
+}
+
+/// Receive spots published to the given MQTT topics as rows in WSPRnet CSV format
+///
+/// Each subscription is served by a separate thread, and rows are yielded in order of arrival.
+/// Iteration only ends once all threads have given up.
+pub fn rows(subscriptions: Vec<Subscription>) -> Box<dyn Iterator<Item = io::Result<String>>> {
+	let (tx, rx) = mpsc::sync_channel(BUFFER);
+
+	for (idx, subscription) in subscriptions.into_iter().enumerate() {
+		let tx = tx.clone();
+		thread::spawn(move || subscribe(&subscription, idx, &tx));
+	}
+
+	Box::new(rx.into_iter())
+}
@@ -0,0 +1,173 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::io;
+use std::io::prelude::*;
+
+use chrono::{DateTime, Utc};
+
+use crate::{Band, Call, Frequency, Grid, Spot};
+use crate::checkpoint::{self, Decode, Encode};
+use crate::table::quote;
+
+/// Spots of a station in one direction
+struct Heard {
+	/// Latest locator of the contact
+	grid: Grid,
+	/// Number of spots
+	num_spots: usize,
+	/// Bands of the spots, with a frequency for sorting
+	bands: HashMap<Band, Frequency>,
+	/// Time of the first and last spot
+	first: u64,
+	last: u64,
+	/// Best SNR
+	snr: i8,
+}
+
+impl Heard {
+	fn new(spot: &Spot, grid: &Grid) -> Self {
+		Heard { grid: grid.clone(), num_spots: 0, bands: HashMap::new(), first: spot.timestamp, last: spot.timestamp, snr: spot.snr }
+	}
+
+	fn add(&mut self, spot: &Spot, grid: &Grid) {
+		if spot.timestamp >= self.last {
+			self.grid = grid.clone();
+		}
+
+		self.num_spots += 1;
+		self.first = self.first.min(spot.timestamp);
+		self.last = self.last.max(spot.timestamp);
+		self.snr = self.snr.max(spot.snr);
+
+		if let Ok(band) = Band::try_from(spot.frequency) {
+			self.bands.entry(band).or_insert(spot.frequency);
+		}
+	}
+}
+
+impl Encode for Heard {
+	/// Bands are saved by their frequency, from which they are determined again
+	fn encode(&self, out: &mut dyn Write) -> io::Result<()> {
+		self.grid.encode(out)?;
+		self.num_spots.encode(out)?;
+		checkpoint::encode_seq(self.bands.len(), self.bands.values(), out)?;
+		self.first.encode(out)?;
+		self.last.encode(out)?;
+		self.snr.encode(out)
+	}
+}
+
+impl Decode for Heard {
+	fn decode(input: &mut dyn Read) -> io::Result<Self> {
+		let grid = Decode::decode(input)?;
+		let num_spots = Decode::decode(input)?;
+		let bands = checkpoint::decode_seq::<Frequency, Vec<_>>(input)?
+			.into_iter()
+			.map(|freq| Ok((Band::try_from(freq)?, freq)))
+			.collect::<io::Result<_>>()?;
+
+		Ok(Heard { grid, num_spots, bands, first: Decode::decode(input)?, last: Decode::decode(input)?, snr: Decode::decode(input)? })
+	}
+}
+
+/// Spots between an operator and a contact in either direction
+#[derive(Default)]
+struct Pair {
+	/// Spots with the operator as reporter
+	rx: Option<Heard>,
+	/// Spots with the operator as transmitter
+	tx: Option<Heard>,
+}
+
+impl Encode for Pair {
+	fn encode(&self, out: &mut dyn Write) -> io::Result<()> {
+		self.rx.encode(out)?;
+		self.tx.encode(out)
+	}
+}
+
+impl Decode for Pair {
+	fn decode(input: &mut dyn Read) -> io::Result<Self> {
+		Ok(Pair { rx: Decode::decode(input)?, tx: Decode::decode(input)? })
+	}
+}
+
+/// Report of stations spotted in one direction only, which are worth completing a QSO with
+///
+/// A contact is listed if spotted repeatedly in one direction while the reciprocal direction never
+/// appeared in the input, whether or not within the look back window.
+pub struct OneWay {
+	/// Operator call signs
+	calls: Vec<Call>,
+	/// Minimum number of spots for a contact to be listed
+	min_spots: usize,
+	/// Spots by operator and contact call sign
+	pairs: HashMap<(Call, Call), Pair>,
+}
+
+impl OneWay {
+	pub fn new(calls: Vec<Call>, min_spots: usize) -> Self {
+		OneWay { calls, min_spots, pairs: HashMap::new() }
+	}
+
+	/// Count a spot if it involves one of the operators
+	pub fn add(&mut self, spot: &Spot) {
+		if self.calls.contains(&spot.call_rx) {
+			let pair = self.pairs.entry((spot.call_rx.clone(), spot.call_tx.clone())).or_default();
+			pair.rx.get_or_insert_with(|| Heard::new(spot, &spot.grid_tx)).add(spot, &spot.grid_tx);
+		}
+
+		if self.calls.contains(&spot.call_tx) {
+			let pair = self.pairs.entry((spot.call_tx.clone(), spot.call_rx.clone())).or_default();
+			pair.tx.get_or_insert_with(|| Heard::new(spot, &spot.grid_rx)).add(spot, &spot.grid_rx);
+		}
+	}
+
+	/// Restore the spots counted before a checkpoint was saved
+	pub fn restore(&mut self, input: &mut dyn Read) -> io::Result<()> {
+		self.pairs = Decode::decode(input)?;
+		Ok(())
+	}
+
+	/// Write the contacts spotted in one direction only as CSV with a header row, most spotted first
+	pub fn write(self, mut out: impl Write) -> io::Result<()> {
+		let mut rows: Vec<_> = self.pairs.into_iter()
+			.filter_map(|((call_op, call_ct), pair)| match pair {
+				Pair { rx: Some(heard), tx: None } => Some((call_op, call_ct, "rx", heard)),
+				Pair { rx: None, tx: Some(heard) } => Some((call_op, call_ct, "tx", heard)),
+				_ => None,
+			})
+			.filter(|(_, _, _, heard)| heard.num_spots >= self.min_spots)
+			.collect();
+
+		rows.sort_by(|a, b| b.3.num_spots.cmp(&a.3.num_spots)
+			.then_with(|| a.0.cmp(&b.0))
+			.then_with(|| a.1.cmp(&b.1)));
+
+		let time = |timestamp: u64| DateTime::<Utc>::from_timestamp(timestamp as i64, 0)
+			.unwrap_or_default()
+			.format("%Y-%m-%dT%H:%M:%SZ");
+
+		writeln!(out, "my_call,call,grid,direction,spots,bands,first,last,best_snr")?;
+		for (call_op, call_ct, direction, heard) in rows {
+			let mut bands: Vec<_> = heard.bands.into_iter().collect();
+			bands.sort_by_key(|&(_, freq)| freq);
+			let bands = bands.iter()
+				.map(|(band, _)| band.name())
+				.collect::<Vec<_>>()
+				.join(" ");
+
+			writeln!(out, "{},{},{},{direction},{},{bands},{},{},{}", quote(&call_op), quote(&call_ct), quote(&heard.grid),
+			         heard.num_spots, time(heard.first), time(heard.last), heard.snr)?;
+		}
+
+		out.flush()
+	}
+}
+
+impl Encode for OneWay {
+	/// Only the spots counted are saved, the operators and minimum being given again on resuming
+	fn encode(&self, out: &mut dyn Write) -> io::Result<()> {
+		self.pairs.encode(out)
+	}
+}
@@ -0,0 +1,210 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::io::prelude::*;
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use log::info;
+use smartstring::alias::String;
+
+use crate::{Call, Qso};
+use crate::adif::{self, Adif, Record, RecordOptions};
+use crate::output::LogFile;
+
+/// Number of attempts to upload a QSO before queuing it
+const ATTEMPTS: u32 = 4;
+
+/// Wait before the second attempt, doubled for every further one
+const BACKOFF: Duration = Duration::from_secs(2);
+
+/// Outcome of uploading a QSO
+pub enum Outcome {
+	/// Accepted or known as a duplicate
+	Done,
+	/// Rejected as invalid, so that retrying is pointless
+	Rejected,
+	/// Failed in a way that may pass later
+	Failed,
+}
+
+/// Online logbook taking one QSO at a time
+pub trait Logbook: Send + 'static {
+	/// Name of the logbook for messages
+	fn name(&self) -> &'static str;
+
+	/// Short name of the logbook for file names
+	fn id(&self) -> &'static str;
+
+	/// Upload the ADIF record of a QSO to the log of the given call sign once
+	fn post(&self, agent: &ureq::Agent, callsign: &Call, record: &str) -> Outcome;
+}
+
+/// Encode the fields of a record read back from an ADIF file, in order of their names
+fn encode(fields: &HashMap<String, String>) -> std::string::String {
+	let mut fields: Vec<_> = fields.iter().collect();
+	fields.sort();
+
+	let mut record: std::string::String = fields.into_iter()
+		.map(|(name, value)| format!("<{name}:{}>{value}", value.len()))
+		.collect();
+
+	record.push_str("<EOR>");
+	record
+}
+
+/// Upload of QSOs to an online logbook running in the background
+///
+/// QSOs that still fail after several attempts are added to the queue, which is retried first on the
+/// next run. The queue is an ADIF file, so that it can be imported by hand as well.
+pub struct Upload {
+	tx: mpsc::Sender<(Call, std::string::String)>,
+	thread: thread::JoinHandle<io::Result<()>>,
+	/// Call sign of the log to upload to, instead of the station or operator call sign
+	callsign: Option<Call>,
+	record: RecordOptions,
+}
+
+impl Upload {
+	/// Start uploading, beginning with the QSOs queued before
+	///
+	/// Without a queue file, the queue is kept in the cache directory, named after the logbook.
+	pub fn new(logbook: impl Logbook, queue: Option<PathBuf>, callsign: Option<Call>, record: RecordOptions) -> io::Result<Self> {
+		let queue = match queue {
+			Some(path) => path,
+			None => {
+				let dir = crate::fetch::cache_dir()?;
+				fs::create_dir_all(&dir)?;
+				dir.join(format!("{}-queue.adi", logbook.id()))
+			},
+		};
+
+		let mut uploader = Uploader {
+			agent: ureq::AgentBuilder::new()
+				.user_agent(concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION")))
+				.timeout(Duration::from_secs(30))
+				.build(),
+			logbook,
+			queue,
+			queued: Vec::new(),
+		};
+
+		uploader.queued = uploader.read_queue()?;
+		let (tx, rx) = mpsc::channel();
+		let thread = thread::spawn(move || uploader.run(rx));
+
+		Ok(Upload { tx, thread, callsign, record })
+	}
+
+	/// Upload a QSO in the background
+	pub fn send(&self, qso: &Qso) {
+		let callsign = self.callsign.as_ref()
+			.or(self.record.station.station_callsign.as_ref())
+			.unwrap_or(&qso.call_op)
+			.clone();
+
+		// The uploader only stops once all senders are gone
+		let _ = self.tx.send((callsign, Record { qso, options: &self.record }.to_string()));
+	}
+
+	/// Wait for all QSOs to be uploaded or queued
+	pub fn finish(self) -> io::Result<()> {
+		drop(self.tx);
+		self.thread.join().map_err(|_| io::Error::other("Uploader panicked"))?
+	}
+}
+
+/// State of the background uploader
+struct Uploader<L: Logbook> {
+	agent: ureq::Agent,
+	logbook: L,
+	/// Queue file
+	queue: PathBuf,
+	/// Records in the queue, with their call signs
+	queued: Vec<(Call, std::string::String)>,
+}
+
+impl<L: Logbook> Uploader<L> {
+	/// Read the records queued by previous runs
+	fn read_queue(&self) -> io::Result<Vec<(Call, std::string::String)>> {
+		let text = match fs::read(&self.queue) {
+			Ok(text) => text,
+			Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+			Err(err) => return Err(io::Error::new(err.kind(), format!("{}: {err}", self.queue.display()))),
+		};
+
+		let records = adif::records(&text)
+			.map_err(|err| io::Error::new(err.kind(), format!("{}: {err}", self.queue.display())))?;
+
+		Ok(records.iter().filter_map(|fields| {
+			let call = fields.get("STATION_CALLSIGN").or(fields.get("OPERATOR"))?;
+			Some((Call::new(call.clone()), encode(fields)))
+		}).collect())
+	}
+
+	/// Replace the queue file with the records still queued, removing it if there are none
+	fn write_queue(&self) -> io::Result<()> {
+		if self.queued.is_empty() {
+			return match fs::remove_file(&self.queue) {
+				Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+				_ => Ok(()),
+			};
+		}
+
+		let (mut file, _) = LogFile::create(&self.queue, false)?;
+		Adif::new(&mut file, &format!("QSOs waiting for upload to {}", self.logbook.name()))?;
+		for (_, record) in &self.queued {
+			writeln!(file, "{record}")?;
+		}
+
+		file.persist()
+	}
+
+	/// Upload a record, backing off between attempts, and return whether it is done with
+	fn upload(&self, callsign: &Call, record: &str) -> bool {
+		let mut delay = BACKOFF;
+
+		for attempt in 1..=ATTEMPTS {
+			match self.logbook.post(&self.agent, callsign, record) {
+				Outcome::Done | Outcome::Rejected => return true,
+				Outcome::Failed if attempt < ATTEMPTS => {
+					thread::sleep(delay);
+					delay *= 2;
+				},
+				Outcome::Failed => (),
+			}
+		}
+
+		false
+	}
+
+	/// Upload the queued and then the new records until there are no more
+	fn run(mut self, rx: mpsc::Receiver<(Call, std::string::String)>) -> io::Result<()> {
+		// The queue file is left as it is until all queued records were tried, as logbooks
+		// recognise those uploaded again as duplicates
+		let pending = std::mem::take(&mut self.queued);
+		if !pending.is_empty() {
+			info!("Uploading {} queued QSOs to {}", pending.len(), self.logbook.name());
+		}
+
+		for (callsign, record) in pending {
+			if !self.upload(&callsign, &record) {
+				self.queued.push((callsign, record));
+			}
+		}
+
+		self.write_queue()?;
+
+		for (callsign, record) in rx {
+			if !self.upload(&callsign, &record) {
+				info!("Queued QSO for upload to {} by the next run", self.logbook.name());
+				self.queued.push((callsign, record));
+				self.write_queue()?;
+			}
+		}
+
+		Ok(())
+	}
+}
@@ -0,0 +1,1088 @@
+use std::cmp;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::io;
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use clap::ValueEnum;
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use smartstring::alias::String;
+use tempfile::NamedTempFile;
+
+use crate::{Band, Qso};
+use crate::adif::{Adif, Broadcast, RecordOptions};
+use crate::cabrillo::{Cabrillo, CabrilloOptions};
+use crate::checkpoint::{self, Decode, Encode};
+use crate::cluster::Cluster;
+#[cfg(feature = "fetch")]
+use crate::clublog::{self, ClublogOptions};
+#[cfg(feature = "fetch")]
+use crate::hrdlog::{self, HrdlogOptions};
+use crate::json::Objects;
+use crate::lotw::{self, LotwOptions};
+use crate::map::{GeoJson, Kml};
+#[cfg(feature = "fetch")]
+use crate::online::Upload;
+#[cfg(feature = "parquet")]
+use crate::parquet::Qsos;
+use crate::report::{Report, Summary};
+#[cfg(feature = "sqlite")]
+use crate::sqlite::Database;
+use crate::table::Table;
+use crate::template::Rendered;
+use crate::text::Text;
+
+/// File a log is written to
+enum Backing {
+	/// Temporary file next to the destination, moved into place once complete
+	Temporary(NamedTempFile),
+	/// Destination moved into place before, appended to directly
+	Direct(File),
+}
+
+impl Backing {
+	/// Write the contents to disk and move them into place, returning the destination for further
+	/// records
+	fn persist(self, path: &Path) -> io::Result<File> {
+		match self {
+			Backing::Temporary(tmp) => {
+				tmp.as_file().sync_all()?;
+				tmp.persist(path).map_err(|err| err.error)
+			},
+			Backing::Direct(file) => {
+				file.sync_all()?;
+				Ok(file)
+			},
+		}
+	}
+}
+
+impl Write for Backing {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		match self {
+			Backing::Temporary(tmp) => tmp.write(buf),
+			Backing::Direct(file) => file.write(buf),
+		}
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		match self {
+			Backing::Temporary(tmp) => tmp.flush(),
+			Backing::Direct(file) => file.flush(),
+		}
+	}
+}
+
+/// Writer of an output file, compressing its contents if the file name asks for it
+enum Writer {
+	Plain(io::BufWriter<Backing>),
+	Gzip(GzEncoder<io::BufWriter<Backing>>),
+	#[cfg(feature = "zstd")]
+	Zstd(zstd::Encoder<'static, io::BufWriter<Backing>>),
+}
+
+impl Writer {
+	/// Write to a file, compressed by the extension of the destination
+	///
+	/// Appending adds a new gzip member or zstd frame, which decompress as one stream.
+	fn new(file: Backing, path: &Path) -> io::Result<Self> {
+		let file = io::BufWriter::new(file);
+
+		match path.extension().and_then(|ext| ext.to_str()) {
+			Some("gz") => Ok(Writer::Gzip(GzEncoder::new(file, Compression::default()))),
+			#[cfg(feature = "zstd")]
+			Some("zst") => Ok(Writer::Zstd(zstd::Encoder::new(file, 0)?)),
+			#[cfg(not(feature = "zstd"))]
+			Some("zst") => Err(io::Error::new(io::ErrorKind::InvalidInput, "zstd compression requires the zstd feature")),
+			_ => Ok(Writer::Plain(file)),
+		}
+	}
+
+	fn finish(self) -> io::Result<Backing> {
+		let file = match self {
+			Writer::Plain(file) => file,
+			Writer::Gzip(encoder) => encoder.finish()?,
+			#[cfg(feature = "zstd")]
+			Writer::Zstd(encoder) => encoder.finish()?,
+		};
+
+		file.into_inner().map_err(io::IntoInnerError::into_error)
+	}
+}
+
+impl Write for Writer {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		match self {
+			Writer::Plain(file) => file.write(buf),
+			Writer::Gzip(encoder) => encoder.write(buf),
+			#[cfg(feature = "zstd")]
+			Writer::Zstd(encoder) => encoder.write(buf),
+		}
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		match self {
+			Writer::Plain(file) => file.flush(),
+			Writer::Gzip(encoder) => encoder.flush(),
+			#[cfg(feature = "zstd")]
+			Writer::Zstd(encoder) => encoder.flush(),
+		}
+	}
+}
+
+/// Output file that replaces its destination atomically once finished
+///
+/// Files named with a `.gz` or `.zst` extension are compressed. Once moved into place at a
+/// checkpoint, further output is appended to the destination directly.
+pub struct LogFile {
+	file: Writer,
+	path: PathBuf,
+}
+
+impl LogFile {
+	/// Create a temporary file next to the destination
+	///
+	/// When appending, the current contents of the destination are copied first, so that it is left
+	/// as it was unless finished. Returns whether there were any.
+	pub fn create(path: &Path, append: bool) -> io::Result<(Self, bool)> {
+		let dir = match path.parent() {
+			Some(dir) if !dir.as_os_str().is_empty() => dir,
+			_ => Path::new("."),
+		};
+
+		let mut tmp = NamedTempFile::new_in(dir)?;
+		let mut existing = false;
+
+		if append {
+			match File::open(path) {
+				Ok(mut file) => existing = io::copy(&mut file, &mut tmp)? > 0,
+				Err(err) if err.kind() == io::ErrorKind::NotFound => (),
+				Err(err) => return Err(err),
+			}
+		}
+
+		Ok((LogFile {
+			file: Writer::new(Backing::Temporary(tmp), path)?,
+			path: path.to_owned(),
+		}, existing))
+	}
+
+	/// Write the output so far to disk and move it into place, continuing to append to it
+	pub fn checkpoint(self) -> io::Result<Self> {
+		let file = self.file.finish()?.persist(&self.path)?;
+		Ok(LogFile { file: Writer::new(Backing::Direct(file), &self.path)?, path: self.path })
+	}
+
+	/// Move the file into place
+	pub fn persist(self) -> io::Result<()> {
+		self.file.finish()?.persist(&self.path)?;
+		Ok(())
+	}
+}
+
+impl Write for LogFile {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		self.file.write(buf)
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		self.file.flush()
+	}
+}
+
+/// Destination of a single log
+pub enum Sink {
+	Stdout(io::StdoutLock<'static>),
+	File(LogFile),
+}
+
+impl Sink {
+	/// Make the output written so far available, keeping the sink open
+	pub fn checkpoint(self) -> io::Result<Self> {
+		match self {
+			Sink::Stdout(mut out) => {
+				out.flush()?;
+				Ok(Sink::Stdout(out))
+			},
+			Sink::File(file) => Ok(Sink::File(file.checkpoint()?)),
+		}
+	}
+
+	/// Flush the output and move files into place
+	pub fn finish(self) -> io::Result<()> {
+		match self {
+			Sink::Stdout(mut out) => out.flush(),
+			Sink::File(file) => file.persist(),
+		}
+	}
+}
+
+impl Write for Sink {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		match self {
+			Sink::Stdout(out) => out.write(buf),
+			Sink::File(file) => file.write(buf),
+		}
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		match self {
+			Sink::Stdout(out) => out.flush(),
+			Sink::File(file) => file.flush(),
+		}
+	}
+}
+
+/// Format of the logs
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+	/// ADIF records
+	#[default]
+	Adif,
+	/// Cabrillo 3.0 QSO lines for activity events
+	Cabrillo,
+	/// One row of CSV per QSO with all details, for analysis
+	Csv,
+	/// One JSON object per QSO with the same details, followed by a summary object
+	Jsonl,
+	/// Columns of QSOs with the same details, for long‐term analysis
+	#[cfg(feature = "parquet")]
+	Parquet,
+	/// Each QSO rendered through the template given with `--template`
+	Template,
+	/// One aligned line per QSO, for reading
+	Text,
+}
+
+impl Format {
+	/// Whether logs can be continued after they were moved into place
+	pub fn appendable(self) -> bool {
+		match self {
+			Format::Adif | Format::Csv | Format::Jsonl | Format::Template | Format::Text => true,
+			Format::Cabrillo => false,
+			#[cfg(feature = "parquet")]
+			Format::Parquet => false,
+		}
+	}
+
+	/// File name extension of logs
+	fn extension(self) -> &'static str {
+		match self {
+			Format::Adif => "adi",
+			Format::Cabrillo => "cbr",
+			Format::Csv => "csv",
+			Format::Jsonl => "jsonl",
+			#[cfg(feature = "parquet")]
+			Format::Parquet => "parquet",
+			Format::Template | Format::Text => "txt",
+		}
+	}
+}
+
+/// Log being written in one of the formats
+enum Log {
+	Adif(Adif<Sink>),
+	Cabrillo(Cabrillo<Sink>),
+	Csv(Table<Sink>),
+	Jsonl(Objects<Sink>),
+	#[cfg(feature = "parquet")]
+	Parquet(Qsos<LogFile>),
+	Template(Rendered<Sink>),
+	Text(Text<Sink>),
+	/// Table of a SQLite database, regardless of the format
+	#[cfg(feature = "sqlite")]
+	Sqlite(Database),
+}
+
+impl Log {
+	/// Write a QSO
+	fn write(&mut self, qso: &Qso, record: &RecordOptions) -> io::Result<()> {
+		match self {
+			Log::Adif(log) => log.write(qso, record),
+			Log::Cabrillo(log) => log.write(qso),
+			Log::Csv(log) => log.write(qso, record),
+			Log::Jsonl(log) => log.write(qso, record),
+			#[cfg(feature = "parquet")]
+			Log::Parquet(log) => log.write(qso),
+			Log::Template(log) => log.write(qso),
+			Log::Text(log) => log.write(qso),
+			#[cfg(feature = "sqlite")]
+			Log::Sqlite(db) => db.write(qso),
+		}
+	}
+
+	/// Make the records written so far available
+	///
+	/// Cabrillo and Parquet logs are only complete once finished, so they are left as they are.
+	/// JSON Lines logs only get their summary once finished.
+	fn checkpoint(self) -> io::Result<Self> {
+		match self {
+			Log::Adif(log) => Ok(Log::Adif(Adif::resume(log.into_inner().checkpoint()?))),
+			log @ Log::Cabrillo(_) => Ok(log),
+			#[cfg(feature = "parquet")]
+			log @ Log::Parquet(_) => Ok(log),
+			Log::Csv(log) => Ok(Log::Csv(Table::resume(log.into_inner().checkpoint()?))),
+			Log::Jsonl(log) => Ok(Log::Jsonl(log.checkpoint()?)),
+			Log::Template(log) => Ok(Log::Template(log.checkpoint()?)),
+			Log::Text(log) => Ok(Log::Text(Text::resume(log.into_inner().checkpoint()?))),
+			#[cfg(feature = "sqlite")]
+			Log::Sqlite(db) => Ok(Log::Sqlite(db.checkpoint()?)),
+		}
+	}
+
+	/// Complete the log and move files into place
+	fn finish(self) -> io::Result<()> {
+		match self {
+			Log::Adif(log) => log.into_inner().finish(),
+			Log::Cabrillo(log) => log.finish()?.finish(),
+			Log::Csv(log) => log.into_inner().finish(),
+			Log::Jsonl(log) => log.finish()?.finish(),
+			#[cfg(feature = "parquet")]
+			Log::Parquet(log) => log.finish()?.persist(),
+			Log::Template(log) => log.into_inner().finish(),
+			Log::Text(log) => log.into_inner().finish(),
+			#[cfg(feature = "sqlite")]
+			Log::Sqlite(db) => db.finish(),
+		}
+	}
+}
+
+/// Map, report, broadcast or upload of all QSOs, besides the logs
+enum Extra {
+	Broadcast(Broadcast),
+	Cluster(Cluster),
+	GeoJson(GeoJson<LogFile>),
+	Kml(Kml<LogFile>),
+	Lotw(Box<lotw::Upload>),
+	/// Upload to an online logbook
+	#[cfg(feature = "fetch")]
+	Online(Upload),
+	Report(Box<Report<LogFile>>),
+	Summary(Summary<LogFile>),
+}
+
+impl Extra {
+	/// Add a QSO
+	fn write(&mut self, qso: &Qso) -> io::Result<()> {
+		match self {
+			Extra::Broadcast(broadcast) => {
+				broadcast.send(qso);
+				Ok(())
+			},
+			Extra::Cluster(cluster) => {
+				cluster.announce(qso);
+				Ok(())
+			},
+			Extra::GeoJson(map) => map.write(qso),
+			Extra::Kml(map) => map.write(qso),
+			Extra::Lotw(upload) => {
+				upload.write(qso);
+				Ok(())
+			},
+			#[cfg(feature = "fetch")]
+			Extra::Online(upload) => {
+				upload.send(qso);
+				Ok(())
+			},
+			Extra::Report(report) => report.write(qso),
+			Extra::Summary(summary) => summary.write(qso),
+		}
+	}
+
+	/// Complete the file and move it into place
+	fn finish(self) -> io::Result<()> {
+		match self {
+			Extra::Broadcast(_) | Extra::Cluster(_) => Ok(()),
+			Extra::GeoJson(map) => map.finish()?.persist(),
+			Extra::Kml(map) => map.finish()?.persist(),
+			Extra::Lotw(upload) => upload.finish(),
+			#[cfg(feature = "fetch")]
+			Extra::Online(upload) => upload.finish(),
+			Extra::Report(report) => report.finish()?.persist(),
+			Extra::Summary(summary) => summary.finish()?.persist(),
+		}
+	}
+}
+
+/// Criterion by which QSOs are split into separate logs
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Split {
+	/// One log per operator call sign
+	Operator,
+	/// One log per transmit band of the operator
+	Band,
+	/// One log per month in which the QSO started
+	Month,
+}
+
+impl Split {
+	/// Name of the log a QSO belongs to
+	fn key(self, qso: &Qso) -> String {
+		match self {
+			Split::Operator => qso.call_op.replace('/', "_").into(),
+			Split::Band => match Band::try_from(qso.freq_op) {
+				Ok(band) => band.name().into(),
+				Err(_) => "unknown".into(),
+			},
+			Split::Month => qso.datetime_on().format("%Y-%m").to_string().into(),
+		}
+	}
+}
+
+/// Log title naming the operator call signs
+fn title(calls: &str) -> std::string::String {
+	format!("Mutual WSPR spots for {calls}")
+}
+
+/// QSOs held back to be logged in order of their start time
+///
+/// QSOs end in order of their last spot, so that a long QSO may end after shorter ones that
+/// started later. With a window, QSOs are held back only until QSOs ending that much later can no
+/// longer start before them.
+pub struct Sorting {
+	/// Window in seconds, or none to hold back all QSOs until finished
+	window: Option<u64>,
+	/// QSOs by start time and order of arrival
+	held: BTreeMap<(u64, u64), Qso>,
+	/// Number of QSOs held back so far
+	num_held: u64,
+	/// Latest end of the QSOs so far
+	time_last: u64,
+}
+
+impl Sorting {
+	/// Hold back a QSO and return those that can be logged now, in order
+	fn push(&mut self, qso: &Qso) -> Vec<Qso> {
+		self.time_last = cmp::max(self.time_last, qso.time_last);
+		self.held.insert((qso.time_first, self.num_held), qso.clone());
+		self.num_held += 1;
+
+		let Some(window) = self.window else {
+			return Vec::new();
+		};
+
+		let until = self.time_last.saturating_sub(window);
+		let later = self.held.split_off(&(until, 0));
+		std::mem::replace(&mut self.held, later).into_values().collect()
+	}
+
+	/// Return all QSOs held back, in order
+	fn take(&mut self) -> Vec<Qso> {
+		std::mem::take(&mut self.held).into_values().collect()
+	}
+}
+
+/// Saves the QSOs held back and the latest end so far, but not the window given by the options
+impl Encode for Sorting {
+	fn encode(&self, out: &mut dyn Write) -> io::Result<()> {
+		checkpoint::encode_seq(self.held.len(), self.held.values(), out)?;
+		self.time_last.encode(out)
+	}
+}
+
+impl Decode for Sorting {
+	fn decode(input: &mut dyn Read) -> io::Result<Self> {
+		let held: Vec<Qso> = checkpoint::decode_seq(input)?;
+		let num_held = held.len() as u64;
+		let held = held.into_iter()
+			.zip(0..)
+			.map(|(qso, idx)| ((qso.time_first, idx), qso))
+			.collect();
+
+		Ok(Sorting { window: None, held, num_held, time_last: u64::decode(input)? })
+	}
+}
+
+/// Cut log files back to their lengths at a checkpoint, dropping the records written after it
+pub fn truncate(lengths: &HashMap<PathBuf, u64>) -> io::Result<()> {
+	for (path, &len) in lengths {
+		match fs::OpenOptions::new().write(true).open(path) {
+			Ok(file) if file.metadata()?.len() > len => file.set_len(len)?,
+			Ok(_) => (),
+			Err(err) if err.kind() == io::ErrorKind::NotFound => (),
+			Err(err) => return Err(err),
+		}
+	}
+
+	Ok(())
+}
+
+/// Path of a numbered file, with the number before the extension, as in qsos-2.adi.gz
+fn numbered(path: &Path, number: usize) -> PathBuf {
+	let name = path.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+	let base = name.strip_suffix(".gz").or_else(|| name.strip_suffix(".zst")).unwrap_or(name);
+	let (stem, ext) = name.split_at(base.rfind('.').filter(|&idx| idx > 0).unwrap_or(base.len()));
+	path.with_file_name(format!("{stem}-{number}{ext}"))
+}
+
+/// Destination of logs given with `--output`, optionally prefixed by their format
+#[derive(Clone, Debug)]
+pub struct Target {
+	/// Format of the logs, unless the one given with `--format`
+	pub format: Option<Format>,
+	/// Output file, directory for split logs, `-` for standard output, or SQLite database given as
+	/// `sqlite:FILE`
+	pub path: PathBuf,
+}
+
+impl FromStr for Target {
+	type Err = std::string::String;
+
+	fn from_str(target: &str) -> Result<Self, Self::Err> {
+		#[cfg(not(feature = "sqlite"))]
+		if target.starts_with("sqlite:") {
+			return Err("built without SQLite support, as enabled with --features sqlite".into());
+		}
+
+		if let Some((prefix, path)) = target.split_once(':') {
+			if let Ok(format) = Format::from_str(prefix, true) {
+				return Ok(Target { format: Some(format), path: path.into() });
+			}
+		}
+
+		Ok(Target { format: None, path: target.into() })
+	}
+}
+
+/// Logs written to one destination
+struct Destination {
+	/// Output file, or directory for split logs, or none for standard output
+	path: Option<PathBuf>,
+	/// Format of the logs
+	format: Format,
+	/// Open logs by name
+	logs: HashMap<String, Log>,
+	/// Paths of the open log files by log name
+	files: HashMap<String, PathBuf>,
+	/// Number of the current file and records written to it by log name, when chunked
+	chunks: HashMap<String, (usize, u64)>,
+}
+
+/// Number of the current file and records written to it by log name for every destination, saved
+/// to continue chunks after resuming
+pub struct Chunks(Vec<HashMap<String, (usize, u64)>>);
+
+impl Encode for Chunks {
+	fn encode(&self, out: &mut dyn Write) -> io::Result<()> {
+		checkpoint::encode_seq(self.0.len(), self.0.iter(), out)
+	}
+}
+
+impl Decode for Chunks {
+	fn decode(input: &mut dyn Read) -> io::Result<Self> {
+		checkpoint::decode_seq(input).map(Chunks)
+	}
+}
+
+/// Destination of the generated logs
+pub struct Output {
+	/// Criterion by which QSOs are split into separate logs
+	split: Option<Split>,
+	/// Append to existing log files
+	append: bool,
+	/// Operator call signs
+	calls: std::string::String,
+	/// Record contents beyond the QSO itself
+	record: RecordOptions,
+	/// Contest details of Cabrillo logs
+	cabrillo: CabrilloOptions,
+	/// Logs of every destination
+	destinations: Vec<Destination>,
+	/// Maps, reports, broadcasts and uploads of all QSOs
+	extras: Vec<Extra>,
+	/// QSOs held back to be logged in order
+	sorting: Option<Sorting>,
+	/// Maximum number of records per file
+	max_records: Option<u64>,
+	/// Completed chunks held back until the next checkpoint, if checkpoints are saved
+	completed: Option<Vec<Log>>,
+}
+
+impl Output {
+	/// Create output for logs of the given operators
+	///
+	/// Without targets, a single log is written to standard output in the given format. Split logs
+	/// are written to the current directory in that case. Every target is written in its own
+	/// format, or the given one if it has none.
+	pub fn new(split: Option<Split>, targets: &[Target], append: bool, calls: &str, format: Format, record: RecordOptions, cabrillo: CabrilloOptions) -> io::Result<Self> {
+		let mut output = Output { split, append, calls: calls.to_owned(), record, cabrillo, destinations: Vec::new(), extras: Vec::new(), sorting: None, max_records: None, completed: None };
+
+		let stdout = [Target { format: None, path: PathBuf::from("-") }];
+		let targets = if targets.is_empty() { &stdout[..] } else { targets };
+		if targets.iter().filter(|target| target.path.as_os_str() == "-").count() > 1 {
+			return Err(io::Error::new(io::ErrorKind::InvalidInput, "Only one log can be written to standard output"));
+		}
+
+		if output.record.template.is_none() && targets.iter().any(|target| target.format.unwrap_or(format) == Format::Template) {
+			return Err(io::Error::new(io::ErrorKind::InvalidInput, "Template logs require --template"));
+		}
+
+		for target in targets {
+			let destination = output.destination(target, format)?;
+			output.destinations.push(destination);
+		}
+
+		Ok(output)
+	}
+
+	/// Prepare the logs of a target
+	///
+	/// A path such as `sqlite:qsos.db` names a database to add the QSOs to instead.
+	fn destination(&self, target: &Target, format: Format) -> io::Result<Destination> {
+		let path = (target.path.as_os_str() != "-").then(|| target.path.clone());
+		let mut destination = Destination { path, format: target.format.unwrap_or(format), logs: HashMap::new(), files: HashMap::new(), chunks: HashMap::new() };
+
+		#[cfg(feature = "sqlite")]
+		if let Some(db) = destination.path.as_deref().and_then(Path::to_str).and_then(|path| path.strip_prefix("sqlite:")) {
+			if self.split.is_some() {
+				return Err(io::Error::new(io::ErrorKind::InvalidInput, "QSOs written to a database cannot be split"));
+			}
+
+			let db = Database::open(Path::new(db))?;
+			destination.logs.insert(String::new(), Log::Sqlite(db));
+			return Ok(destination);
+		}
+
+		if self.split.is_none() {
+			let log = match &destination.path {
+				Some(path) => {
+					destination.files.insert(String::new(), path.clone());
+					self.open(destination.format, path, &title(&self.calls))?
+				},
+				None => self.start(destination.format, Sink::Stdout(io::stdout().lock()), &title(&self.calls))?,
+			};
+
+			destination.logs.insert(String::new(), log);
+		} else if let Some(dir) = &destination.path {
+			fs::create_dir_all(dir)?;
+		}
+
+		Ok(destination)
+	}
+
+	/// Also send every QSO record over UDP as it is written
+	pub fn broadcast(&mut self, addr: &str) -> io::Result<()> {
+		self.extras.push(Extra::Broadcast(Broadcast::new(addr, self.record.clone())?));
+		Ok(())
+	}
+
+	/// Also announce every QSO to DX cluster clients connecting to the given address as it is written
+	pub fn cluster(&mut self, addr: &str) -> io::Result<()> {
+		self.extras.push(Extra::Cluster(Cluster::new(addr)?));
+		Ok(())
+	}
+
+	/// Also upload every QSO to Club Log as it is written
+	#[cfg(feature = "fetch")]
+	pub fn clublog(&mut self, options: ClublogOptions) -> io::Result<()> {
+		self.extras.push(Extra::Online(clublog::upload(options, self.record.clone())?));
+		Ok(())
+	}
+
+	/// Also upload every QSO to HRDLog.net as it is written
+	#[cfg(feature = "fetch")]
+	pub fn hrdlog(&mut self, options: HrdlogOptions) -> io::Result<()> {
+		self.extras.push(Extra::Online(hrdlog::upload(options, self.record.clone())?));
+		Ok(())
+	}
+
+	/// Also draw all QSOs on a map in KML format
+	///
+	/// Maps and reports are complete only once finished, so they are left as they are by
+	/// checkpoints.
+	pub fn kml(&mut self, path: &Path) -> io::Result<()> {
+		let (file, _) = LogFile::create(path, false)?;
+		self.extras.push(Extra::Kml(Kml::new(file, &title(&self.calls))?));
+		Ok(())
+	}
+
+	/// Also draw all QSOs on a map in GeoJSON format
+	pub fn geojson(&mut self, path: &Path) -> io::Result<()> {
+		let (file, _) = LogFile::create(path, false)?;
+		self.extras.push(Extra::GeoJson(GeoJson::new(file, &title(&self.calls))?));
+		Ok(())
+	}
+
+	/// Also write a report of all QSOs in HTML format
+	pub fn report(&mut self, path: &Path) -> io::Result<()> {
+		let (file, _) = LogFile::create(path, false)?;
+		self.extras.push(Extra::Report(Box::new(Report::new(file, &title(&self.calls)))));
+		Ok(())
+	}
+
+	/// Also upload all QSOs not uploaded before to LoTW once finished
+	///
+	/// QSOs starting at most the given number of seconds apart are considered the same.
+	pub fn lotw(&mut self, options: LotwOptions, tolerance: u64) -> io::Result<()> {
+		let upload = lotw::Upload::new(options, self.record.clone(), tolerance, &title(&self.calls))?;
+		self.extras.push(Extra::Lotw(Box::new(upload)));
+		Ok(())
+	}
+
+	/// Also write a summary of all QSOs in Markdown format
+	pub fn summary(&mut self, path: &Path) -> io::Result<()> {
+		let (file, _) = LogFile::create(path, false)?;
+		self.extras.push(Extra::Summary(Summary::new(file, &title(&self.calls))));
+		Ok(())
+	}
+
+	/// Log QSOs in order of their start time, holding them back for at most the given number of
+	/// seconds in the time of the spots, or until finished
+	///
+	/// Broadcasts, uploads, maps and reports still take QSOs as they end.
+	pub fn sort(&mut self, window: Option<u64>) {
+		self.sorting = Some(Sorting { window, held: BTreeMap::new(), num_held: 0, time_last: 0 });
+	}
+
+	/// Write at most the given number of records to each file, continuing in files numbered from 1
+	pub fn chunk(&mut self, max_records: u64) -> io::Result<()> {
+		self.max_records = Some(max_records);
+
+		for idx in 0..self.destinations.len() {
+			let destination = &self.destinations[idx];
+			let Some(path) = &destination.path else {
+				return Err(io::Error::new(io::ErrorKind::InvalidInput, "Logs split into chunks require --output"));
+			};
+
+			if path.to_str().is_some_and(|path| path.starts_with("sqlite:")) {
+				return Err(io::Error::new(io::ErrorKind::InvalidInput, "QSOs written to a database cannot be split into chunks"));
+			}
+
+			// The single log opened right away is replaced by the first chunk
+			if self.split.is_none() {
+				let path = numbered(path, 1);
+				let log = self.open(destination.format, &path, &title(&self.calls))?;
+				self.destinations[idx].logs.insert(String::new(), log);
+				self.destinations[idx].files.insert(String::new(), path);
+			}
+		}
+
+		Ok(())
+	}
+
+	/// Start a new log, writing the header of ADIF, CSV and text logs right away
+	fn start(&self, format: Format, sink: Sink, title: &str) -> io::Result<Log> {
+		match format {
+			Format::Adif => Ok(Log::Adif(Adif::new(sink, title)?)),
+			Format::Cabrillo => Ok(Log::Cabrillo(Cabrillo::new(sink, self.cabrillo.clone(),
+				self.record.station.station_callsign.clone(), self.record.station.my_name.clone()))),
+			Format::Csv => Ok(Log::Csv(Table::new(sink, &self.record)?)),
+			Format::Jsonl => Ok(Log::Jsonl(Objects::new(sink))),
+			#[cfg(feature = "parquet")]
+			Format::Parquet => match sink {
+				Sink::File(file) => Ok(Log::Parquet(Qsos::new(file)?)),
+				Sink::Stdout(_) => Err(io::Error::new(io::ErrorKind::InvalidInput, "Parquet logs require --output")),
+			},
+			Format::Template => match &self.record.template {
+				Some(template) => Ok(Log::Template(Rendered::new(sink, template.clone()))),
+				None => Err(io::Error::new(io::ErrorKind::InvalidInput, "Template logs require --template")),
+			},
+			Format::Text => Ok(Log::Text(Text::new(sink)?)),
+		}
+	}
+
+	/// Open a log file, continuing an existing ADIF, CSV or text log when appending
+	fn open(&self, format: Format, path: &Path, title: &str) -> io::Result<Log> {
+		let (file, existing) = LogFile::create(path, self.append)?;
+
+		match format {
+			Format::Adif if existing => Ok(Log::Adif(Adif::resume(Sink::File(file)))),
+			Format::Csv if existing => Ok(Log::Csv(Table::resume(Sink::File(file)))),
+			Format::Text if existing => Ok(Log::Text(Text::resume(Sink::File(file)))),
+			_ => self.start(format, Sink::File(file), title),
+		}
+	}
+
+	/// Hold completed chunks back until the next checkpoint, so that a resumed run does not find
+	/// records written after it
+	pub fn hold_chunks(&mut self) {
+		self.completed = Some(Vec::new());
+	}
+
+	/// Current chunks of the logs, to be continued after resuming
+	pub fn chunks(&self) -> Chunks {
+		Chunks(self.destinations.iter().map(|destination| destination.chunks.clone()).collect())
+	}
+
+	/// Continue the chunks written before the checkpoint of a resumed run
+	pub fn resume_chunks(&mut self, chunks: Chunks) -> io::Result<()> {
+		if chunks.0.len() != self.destinations.len() {
+			return Err(io::Error::new(io::ErrorKind::InvalidInput, "Checkpoint was saved for other outputs"));
+		}
+
+		for (destination, chunks) in self.destinations.iter_mut().zip(chunks.0) {
+			// Logs opened in the first chunk are opened again in the current one with the next record
+			for (key, &(number, _)) in &chunks {
+				if number > 1 {
+					if let Some(log) = destination.logs.remove(key) {
+						log.finish()?;
+					}
+				}
+			}
+
+			destination.chunks = chunks;
+		}
+
+		Ok(())
+	}
+
+	/// Write a QSO record to the log it belongs to
+	pub fn write(&mut self, qso: &Qso) -> io::Result<()> {
+		for extra in &mut self.extras {
+			extra.write(qso)?;
+		}
+
+		match &mut self.sorting {
+			Some(sorting) => {
+				for qso in sorting.push(qso) {
+					self.log(&qso)?;
+				}
+
+				Ok(())
+			},
+			None => self.log(qso),
+		}
+	}
+
+	/// QSOs held back for sorting, to be held back again after resuming
+	pub fn sorting(&self) -> Option<&Sorting> {
+		self.sorting.as_ref()
+	}
+
+	/// Hold back the QSOs held back before the checkpoint of a resumed run, or log them right away
+	/// if not sorting anymore
+	pub fn resume_sorting(&mut self, resumed: Sorting) -> io::Result<()> {
+		match &mut self.sorting {
+			Some(sorting) => {
+				for qso in resumed.held.into_values() {
+					sorting.held.insert((qso.time_first, sorting.num_held), qso);
+					sorting.num_held += 1;
+				}
+
+				sorting.time_last = cmp::max(sorting.time_last, resumed.time_last);
+				Ok(())
+			},
+			None => resumed.held.values().try_for_each(|qso| self.log(qso)),
+		}
+	}
+
+	/// Write a QSO record to the logs it belongs to right away
+	fn log(&mut self, qso: &Qso) -> io::Result<()> {
+		let key = match self.split {
+			Some(split) => split.key(qso),
+			None => String::new(),
+		};
+
+		for idx in 0..self.destinations.len() {
+			if !self.destinations[idx].logs.contains_key(&key) {
+				let destination = &self.destinations[idx];
+				let path = match (self.split, &destination.path) {
+					(None, Some(path)) => path.clone(),
+					(_, dir) => {
+						let name = format!("wspr-{key}.{}", destination.format.extension());
+						dir.as_ref().map_or_else(|| PathBuf::from(&name), |dir| dir.join(&name))
+					},
+				};
+
+				let path = match self.max_records {
+					Some(_) => numbered(&path, destination.chunks.get(&key).map_or(1, |&(number, _)| number)),
+					None => path,
+				};
+
+				let calls = match self.split {
+					Some(Split::Operator) => qso.call_op.as_str(),
+					_ => &self.calls,
+				};
+
+				let log = self.open(destination.format, &path, &title(calls))?;
+				self.destinations[idx].logs.insert(key.clone(), log);
+				self.destinations[idx].files.insert(key.clone(), path);
+			}
+
+			let destination = &mut self.destinations[idx];
+			if let Some(log) = destination.logs.get_mut(&key) {
+				log.write(qso, &self.record)?;
+			}
+
+			// A full chunk is completed, and the next one started with the next record
+			if let Some(max_records) = self.max_records {
+				let chunk = destination.chunks.entry(key.clone()).or_insert((1, 0));
+				chunk.1 += 1;
+				if chunk.1 >= max_records {
+					*chunk = (chunk.0 + 1, 0);
+					match (destination.logs.remove(&key), &mut self.completed) {
+						(Some(log), Some(completed)) => completed.push(log),
+						(Some(log), None) => log.finish()?,
+						(None, _) => (),
+					}
+				}
+			}
+		}
+
+		Ok(())
+	}
+
+	/// Lengths of the open log files, to which they are cut back when resuming after a checkpoint
+	/// saved right after syncing
+	pub fn lengths(&self) -> io::Result<HashMap<PathBuf, u64>> {
+		let mut lengths = HashMap::new();
+		for destination in &self.destinations {
+			for key in destination.logs.keys() {
+				if let Some(path) = destination.files.get(key) {
+					lengths.insert(path.clone(), fs::metadata(path)?.len());
+				}
+			}
+		}
+
+		Ok(lengths)
+	}
+
+	/// Flush all logs and move files into place, keeping them open for further records
+	pub fn sync(&mut self) -> io::Result<()> {
+		for log in self.completed.iter_mut().flat_map(std::mem::take) {
+			log.finish()?;
+		}
+
+		for destination in &mut self.destinations {
+			for (key, log) in std::mem::take(&mut destination.logs) {
+				destination.logs.insert(key, log.checkpoint()?);
+			}
+		}
+
+		Ok(())
+	}
+
+	/// Flush all logs and move files into place
+	pub fn finish(mut self) -> io::Result<()> {
+		if let Some(mut sorting) = self.sorting.take() {
+			for qso in sorting.take() {
+				self.log(&qso)?;
+			}
+		}
+
+		for log in self.completed.into_iter().flatten() {
+			log.finish()?;
+		}
+
+		for destination in self.destinations {
+			for log in destination.logs.into_values() {
+				log.finish()?;
+			}
+		}
+
+		for extra in self.extras {
+			extra.finish()?;
+		}
+
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use clap::{Args, FromArgMatches};
+
+	use super::*;
+	use crate::Spot;
+
+	/// QSO of K1ABC with the given contact lasting from the first to the last time
+	fn qso(call_ct: &str, time_first: u64, time_last: u64) -> Qso {
+		let spot = |call_rx: &str, call_tx: &str, timestamp: u64| -> Spot {
+			format!("1,{timestamp},{call_rx},FN42,-10,14.097100,{call_tx},JO62,37,0,6000").parse().unwrap()
+		};
+
+		Qso::new(&spot("K1ABC", call_ct, time_first), &spot(call_ct, "K1ABC", time_last))
+	}
+
+	/// Options as without any arguments
+	fn options<T: Args + FromArgMatches>() -> T {
+		let matches = T::augment_args(clap::Command::new("wsprspots")).get_matches_from(["wsprspots"]);
+		T::from_arg_matches(&matches).unwrap()
+	}
+
+	fn sorting(window: Option<u64>) -> Sorting {
+		Sorting { window, held: BTreeMap::new(), num_held: 0, time_last: 0 }
+	}
+
+	fn calls(qsos: &[Qso]) -> Vec<&str> {
+		qsos.iter().map(|qso| qso.call_ct.as_str()).collect()
+	}
+
+	#[test]
+	fn sorts_qsos_within_window() {
+		let mut sorting = sorting(Some(600));
+
+		assert!(sorting.push(&qso("DL1ABC", 120, 240)).is_empty());
+		assert!(sorting.push(&qso("DL2ABC", 0, 360)).is_empty());
+		assert!(sorting.push(&qso("DL3ABC", 240, 480)).is_empty());
+		assert_eq!(calls(&sorting.push(&qso("DL4ABC", 720, 840))), ["DL2ABC", "DL1ABC"]);
+		assert_eq!(calls(&sorting.take()), ["DL3ABC", "DL4ABC"]);
+	}
+
+	#[test]
+	fn holds_back_all_qsos_without_window() {
+		let mut sorting = sorting(None);
+
+		assert!(sorting.push(&qso("DL1ABC", 7200, 7320)).is_empty());
+		assert!(sorting.push(&qso("DL2ABC", 0, 86400)).is_empty());
+		assert!(sorting.push(&qso("DL3ABC", 7200, 7440)).is_empty());
+		assert_eq!(calls(&sorting.take()), ["DL2ABC", "DL1ABC", "DL3ABC"]);
+	}
+
+	#[test]
+	fn saves_qsos_held_back() {
+		let mut sorting = sorting(Some(600));
+		sorting.push(&qso("DL1ABC", 120, 240));
+		sorting.push(&qso("DL2ABC", 0, 360));
+		sorting.push(&qso("DL3ABC", 0, 120));
+
+		let mut buf = Vec::new();
+		sorting.encode(&mut buf).unwrap();
+		let mut resumed = Sorting::decode(&mut buf.as_slice()).unwrap();
+
+		assert_eq!(resumed.window, None);
+		assert_eq!(resumed.time_last, 360);
+		assert_eq!(calls(&resumed.take()), ["DL2ABC", "DL3ABC", "DL1ABC"]);
+	}
+
+	#[test]
+	fn numbers_files() {
+		assert_eq!(numbered(Path::new("qsos.adi"), 2), Path::new("qsos-2.adi"));
+		assert_eq!(numbered(Path::new("logs/qsos.csv.gz"), 1), Path::new("logs/qsos-1.csv.gz"));
+		assert_eq!(numbered(Path::new("qsos.jsonl.zst"), 3), Path::new("qsos-3.jsonl.zst"));
+		assert_eq!(numbered(Path::new("logs.d/qsos"), 4), Path::new("logs.d/qsos-4"));
+		assert_eq!(numbered(Path::new(".qsos"), 5), Path::new(".qsos-5"));
+	}
+
+	#[test]
+	fn writes_chunks() {
+		let dir = tempfile::tempdir().unwrap();
+		let target = Target { format: None, path: dir.path().join("qsos.adi") };
+
+		let mut output = Output::new(None, &[target], false, "K1ABC", Format::Adif, options(), options()).unwrap();
+		output.chunk(2).unwrap();
+		for (idx, call) in ["DL1ABC", "DL2ABC", "DL3ABC", "DL4ABC", "DL5ABC"].into_iter().enumerate() {
+			output.write(&qso(call, idx as u64 * 120, idx as u64 * 120)).unwrap();
+		}
+		output.finish().unwrap();
+
+		let records = |name: &str| fs::read_to_string(dir.path().join(name)).unwrap().matches("<EOR>").count();
+		assert_eq!([records("qsos-1.adi"), records("qsos-2.adi"), records("qsos-3.adi")], [2, 2, 1]);
+		assert!(!dir.path().join("qsos.adi").exists());
+		assert!(!dir.path().join("qsos-4.adi").exists());
+
+		// Resumed from a checkpoint taken after the fifth record
+		let target = Target { format: None, path: dir.path().join("qsos.adi") };
+		let mut output = Output::new(None, &[target], true, "K1ABC", Format::Adif, options(), options()).unwrap();
+		output.chunk(2).unwrap();
+		output.resume_chunks(Chunks(vec![HashMap::from([(String::new(), (3, 1))])])).unwrap();
+		output.write(&qso("DL6ABC", 600, 600)).unwrap();
+		output.write(&qso("DL7ABC", 720, 720)).unwrap();
+		output.finish().unwrap();
+
+		assert_eq!([records("qsos-1.adi"), records("qsos-3.adi"), records("qsos-4.adi")], [2, 2, 1]);
+	}
+}
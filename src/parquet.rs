@@ -0,0 +1,415 @@
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::path::Path;
+use std::sync::Arc;
+
+use log::debug;
+use parquet::basic::{Compression, ConvertedType};
+use parquet::data_type::{ByteArray, ByteArrayType, DoubleType, Int32Type, Int64Type};
+use parquet::file::metadata::RowGroupMetaData;
+use parquet::file::properties::WriterProperties;
+use parquet::file::serialized_reader::{ReadOptionsBuilder, SerializedFileReader};
+use parquet::file::statistics::Statistics;
+use parquet::file::writer::{SerializedColumnWriter, SerializedFileWriter};
+use parquet::record::{Field, Row};
+use parquet::record::reader::RowIter;
+use parquet::schema::parser::parse_message_type;
+
+use crate::{Band, Power, Qso, Spot};
+use crate::schema;
+
+/// Whether a row group may hold spots within the time window, judging by its statistics
+fn in_window(group: &RowGroupMetaData, since: Option<u64>, until: Option<u64>) -> bool {
+	let Some(column) = group.columns().iter()
+		.find(|column| schema::position(column.column_descr().name()) == Some(1)) else {
+		return true;
+	};
+
+	let scale = match column.column_descr().converted_type() {
+		ConvertedType::TIMESTAMP_MILLIS => 1_000,
+		ConvertedType::TIMESTAMP_MICROS => 1_000_000,
+		_ => 1,
+	};
+
+	let (min, max) = match column.statistics() {
+		Some(Statistics::Int64(stats)) => (stats.min_opt().copied(), stats.max_opt().copied()),
+		Some(Statistics::Int32(stats)) => (stats.min_opt().map(|&min| min.into()), stats.max_opt().map(|&max| max.into())),
+		_ => return true,
+	};
+
+	let (Some(min), Some(max)) = (min, max) else {
+		return true;
+	};
+
+	!(since.is_some_and(|since| max / scale < since as i64) || until.is_some_and(|until| min / scale >= until as i64))
+}
+
+/// Value of a field as in CSV
+fn value(field: &Field) -> String {
+	match field {
+		Field::Null => String::new(),
+		Field::Str(value) => value.clone(),
+		Field::TimestampMillis(millis) => (millis / 1_000).to_string(),
+		Field::TimestampMicros(micros) => (micros / 1_000_000).to_string(),
+		field => field.to_string(),
+	}
+}
+
+/// Convert a Parquet row into a row in WSPRnet CSV format
+fn convert(row: &Row) -> String {
+	schema::row(row.get_column_iter().map(|(name, field)| (name.as_str(), value(field))))
+}
+
+/// Read the spots of a Parquet file as rows in WSPRnet CSV format
+///
+/// Columns are looked up by the same names as in a header row. Row groups entirely outside the time
+/// window are skipped without decoding them.
+pub fn rows(path: &Path, since: Option<u64>, until: Option<u64>) -> io::Result<Box<dyn Iterator<Item = io::Result<String>>>> {
+	if path.as_os_str() == "-" {
+		return Err(io::Error::new(io::ErrorKind::InvalidInput, "Parquet files cannot be read from standard input"));
+	}
+
+	let options = ReadOptionsBuilder::new()
+		.with_predicate(Box::new(move |group, idx| {
+			let keep = in_window(group, since, until);
+			if !keep {
+				debug!("Skipping row group {idx} outside the time window");
+			}
+
+			keep
+		}))
+		.build();
+
+	let reader = SerializedFileReader::new_with_options(File::open(path)?, options)
+		.map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+
+	Ok(Box::new(RowIter::from_file_into(Box::new(reader))
+		.map(|row| row.map(|row| convert(&row)).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err)))))
+}
+
+/// Schema of QSO logs, kept stable so that queries keep working
+const QSO_SCHEMA: &str = "
+	message qso {
+		REQUIRED BYTE_ARRAY my_call (UTF8);
+		REQUIRED BYTE_ARRAY my_grid (UTF8);
+		REQUIRED BYTE_ARRAY call (UTF8);
+		REQUIRED BYTE_ARRAY grid (UTF8);
+		REQUIRED INT64 time_on (TIMESTAMP(MILLIS, true));
+		REQUIRED INT64 time_off (TIMESTAMP(MILLIS, true));
+		OPTIONAL BYTE_ARRAY band (UTF8);
+		REQUIRED DOUBLE frequency;
+		REQUIRED DOUBLE rx_frequency;
+		REQUIRED BYTE_ARRAY mode (UTF8);
+		REQUIRED INT32 my_snr;
+		REQUIRED INT32 snr;
+		OPTIONAL INT32 my_power_dbm;
+		OPTIONAL INT32 power_dbm;
+		REQUIRED INT32 my_drift;
+		REQUIRED INT32 drift;
+		REQUIRED INT32 distance;
+		REQUIRED INT32 spots;
+		REQUIRED INT32 rx_spots;
+		REQUIRED INT32 tx_spots;
+		REPEATED INT64 spot_ids;
+	}
+";
+
+/// Number of rows per row group
+const ROW_GROUP_SIZE: usize = 1 << 16;
+
+/// Values of a column in a row group
+enum Values {
+	Bytes(Vec<ByteArray>),
+	Int32(Vec<i32>),
+	Int64(Vec<i64>),
+	Double(Vec<f64>),
+}
+
+/// Column of QSOs buffered for the next row group
+struct Column {
+	values: Values,
+	/// Definition levels of optional and repeated columns
+	def: Option<Vec<i16>>,
+	/// Repetition levels of repeated columns
+	rep: Option<Vec<i16>>,
+}
+
+impl Column {
+	fn new(values: Values, optional: bool, repeated: bool) -> Self {
+		Column { values, def: (optional || repeated).then(Vec::new), rep: repeated.then(Vec::new) }
+	}
+
+	/// Mark whether a value of an optional column is present
+	fn defined(&mut self, present: bool) {
+		if let Some(def) = &mut self.def {
+			def.push(present.into());
+		}
+	}
+
+	fn text(&mut self, value: Option<&str>) {
+		self.defined(value.is_some());
+		if let (Values::Bytes(values), Some(value)) = (&mut self.values, value) {
+			values.push(value.into());
+		}
+	}
+
+	fn int32(&mut self, value: Option<i32>) {
+		self.defined(value.is_some());
+		if let (Values::Int32(values), Some(value)) = (&mut self.values, value) {
+			values.push(value);
+		}
+	}
+
+	fn int64(&mut self, value: i64) {
+		self.defined(true);
+		if let Values::Int64(values) = &mut self.values {
+			values.push(value);
+		}
+	}
+
+	fn double(&mut self, value: f64) {
+		self.defined(true);
+		if let Values::Double(values) = &mut self.values {
+			values.push(value);
+		}
+	}
+
+	/// Add the values of a repeated column for one row
+	fn list(&mut self, list: impl IntoIterator<Item = i64>) {
+		let mut empty = true;
+		for (idx, value) in list.into_iter().enumerate() {
+			self.int64(value);
+			if let Some(rep) = &mut self.rep {
+				rep.push(i16::from(idx > 0));
+			}
+
+			empty = false;
+		}
+
+		if empty {
+			self.defined(false);
+			if let Some(rep) = &mut self.rep {
+				rep.push(0);
+			}
+		}
+	}
+
+	/// Write the buffered values to a column chunk
+	fn write(&mut self, writer: &mut SerializedColumnWriter) -> parquet::errors::Result<()> {
+		let def = self.def.as_deref();
+		let rep = self.rep.as_deref();
+
+		match &self.values {
+			Values::Bytes(values) => writer.typed::<ByteArrayType>().write_batch(values, def, rep)?,
+			Values::Int32(values) => writer.typed::<Int32Type>().write_batch(values, def, rep)?,
+			Values::Int64(values) => writer.typed::<Int64Type>().write_batch(values, def, rep)?,
+			Values::Double(values) => writer.typed::<DoubleType>().write_batch(values, def, rep)?,
+		};
+
+		match &mut self.values {
+			Values::Bytes(values) => values.clear(),
+			Values::Int32(values) => values.clear(),
+			Values::Int64(values) => values.clear(),
+			Values::Double(values) => values.clear(),
+		}
+
+		self.def.iter_mut().chain(self.rep.iter_mut()).for_each(Vec::clear);
+		Ok(())
+	}
+}
+
+/// Rows of a Parquet file buffered in columns until a row group is complete
+struct Table<W: Write + Send> {
+	writer: SerializedFileWriter<W>,
+	/// Columns in the order of the schema
+	columns: Vec<Column>,
+	/// Number of rows buffered
+	num_rows: usize,
+}
+
+impl<W: Write + Send> Table<W> {
+	fn new(out: W, schema: &str, columns: Vec<Column>) -> io::Result<Self> {
+		let schema = parse_message_type(schema).map_err(io::Error::other)?;
+		let properties = WriterProperties::builder()
+			.set_compression(Compression::ZSTD(Default::default()))
+			.build();
+		let writer = SerializedFileWriter::new(out, Arc::new(schema), Arc::new(properties))
+			.map_err(io::Error::other)?;
+
+		Ok(Table { writer, columns, num_rows: 0 })
+	}
+
+	/// Account for a row added to the columns, writing a row group once enough are buffered
+	fn added(&mut self) -> io::Result<()> {
+		self.num_rows += 1;
+		if self.num_rows >= ROW_GROUP_SIZE {
+			self.flush()?;
+		}
+
+		Ok(())
+	}
+
+	/// Write the buffered rows as a row group
+	fn flush(&mut self) -> io::Result<()> {
+		if self.num_rows == 0 {
+			return Ok(());
+		}
+
+		let mut group = self.writer.next_row_group().map_err(io::Error::other)?;
+		for column in &mut self.columns {
+			let Some(mut writer) = group.next_column().map_err(io::Error::other)? else {
+				break;
+			};
+
+			column.write(&mut writer).map_err(io::Error::other)?;
+			writer.close().map_err(io::Error::other)?;
+		}
+
+		group.close().map_err(io::Error::other)?;
+		self.num_rows = 0;
+		Ok(())
+	}
+
+	/// Write the remaining rows and the footer
+	fn finish(mut self) -> io::Result<W> {
+		self.flush()?;
+		self.writer.into_inner().map_err(io::Error::other)
+	}
+}
+
+/// Log of QSOs in a Parquet file with a fixed schema
+pub struct Qsos<W: Write + Send>(Table<W>);
+
+impl<W: Write + Send> Qsos<W> {
+	/// Start a new log
+	pub fn new(out: W) -> io::Result<Self> {
+		let text = || Column::new(Values::Bytes(Vec::new()), false, false);
+		let int32 = || Column::new(Values::Int32(Vec::new()), false, false);
+		let columns = vec![
+			text(), text(), text(), text(),
+			Column::new(Values::Int64(Vec::new()), false, false),
+			Column::new(Values::Int64(Vec::new()), false, false),
+			Column::new(Values::Bytes(Vec::new()), true, false),
+			Column::new(Values::Double(Vec::new()), false, false),
+			Column::new(Values::Double(Vec::new()), false, false),
+			text(),
+			int32(), int32(),
+			Column::new(Values::Int32(Vec::new()), true, false),
+			Column::new(Values::Int32(Vec::new()), true, false),
+			int32(), int32(), int32(), int32(), int32(), int32(),
+			Column::new(Values::Int64(Vec::new()), false, true),
+		];
+
+		Ok(Qsos(Table::new(out, QSO_SCHEMA, columns)?))
+	}
+
+	/// Add a QSO, writing a row group once enough are buffered
+	pub fn write(&mut self, qso: &Qso) -> io::Result<()> {
+		let band = Band::try_from(qso.freq_op).ok().as_ref().map(Band::name);
+		let dbm = |power: Power| power.is_known().then_some(power.0.into());
+		let [my_call, my_grid, call, grid, time_on, time_off, band_column, frequency, rx_frequency, mode,
+		     my_snr, snr, my_power_dbm, power_dbm, my_drift, drift, distance, spots, rx_spots, tx_spots,
+		     spot_ids] = &mut self.0.columns[..] else {
+			unreachable!("columns do not match the schema");
+		};
+
+		my_call.text(Some(&qso.call_op));
+		my_grid.text(Some(&qso.grid_op));
+		call.text(Some(&qso.call_ct));
+		grid.text(Some(&qso.grid_ct));
+		time_on.int64(qso.datetime_on().timestamp_millis());
+		time_off.int64(qso.datetime_off().timestamp_millis());
+		band_column.text(band.as_deref());
+		frequency.double(qso.freq_op.mhz());
+		rx_frequency.double(qso.freq_ct.mhz());
+		mode.text(Some(&qso.mode.to_string()));
+		my_snr.int32(Some(qso.snr_op.into()));
+		snr.int32(Some(qso.snr_ct.into()));
+		my_power_dbm.int32(dbm(qso.power_op));
+		power_dbm.int32(dbm(qso.power_ct));
+		my_drift.int32(Some(qso.drift_op.into()));
+		drift.int32(Some(qso.drift_ct.into()));
+		distance.int32(Some(qso.distance.into()));
+		spots.int32(Some(qso.spots.len() as i32));
+		rx_spots.int32(Some(qso.num_rx as i32));
+		tx_spots.int32(Some(qso.num_tx as i32));
+		spot_ids.list(qso.spots.iter().map(|&id| id as i64));
+
+		self.0.added()
+	}
+
+	/// Write the remaining QSOs and the footer
+	pub fn finish(self) -> io::Result<W> {
+		self.0.finish()
+	}
+}
+
+/// Schema of spots, named like the columns of a header row so that the files can be read back
+const SPOT_SCHEMA: &str = "
+	message spot {
+		REQUIRED INT64 id;
+		REQUIRED INT64 timestamp (TIMESTAMP(MILLIS, true));
+		REQUIRED BYTE_ARRAY reporter (UTF8);
+		REQUIRED BYTE_ARRAY reporter_grid (UTF8);
+		REQUIRED INT32 snr;
+		REQUIRED DOUBLE frequency;
+		REQUIRED BYTE_ARRAY call (UTF8);
+		REQUIRED BYTE_ARRAY grid (UTF8);
+		OPTIONAL INT32 power;
+		REQUIRED INT32 drift;
+		REQUIRED INT32 distance;
+		REQUIRED BYTE_ARRAY mode (UTF8);
+	}
+";
+
+/// Spots in a Parquet file, without the columns following the mode
+pub struct Spots<W: Write + Send>(Table<W>);
+
+impl<W: Write + Send> Spots<W> {
+	pub fn new(out: W) -> io::Result<Self> {
+		let text = || Column::new(Values::Bytes(Vec::new()), false, false);
+		let int32 = || Column::new(Values::Int32(Vec::new()), false, false);
+		let columns = vec![
+			Column::new(Values::Int64(Vec::new()), false, false),
+			Column::new(Values::Int64(Vec::new()), false, false),
+			text(), text(),
+			int32(),
+			Column::new(Values::Double(Vec::new()), false, false),
+			text(), text(),
+			Column::new(Values::Int32(Vec::new()), true, false),
+			int32(), int32(),
+			text(),
+		];
+
+		Ok(Spots(Table::new(out, SPOT_SCHEMA, columns)?))
+	}
+
+	/// Add a spot, writing a row group once enough are buffered
+	pub fn write(&mut self, spot: &Spot) -> io::Result<()> {
+		let [id, timestamp, reporter, reporter_grid, snr, frequency, call, grid, power, drift, distance,
+		     mode] = &mut self.0.columns[..] else {
+			unreachable!("columns do not match the schema");
+		};
+
+		id.int64(spot.id as i64);
+		timestamp.int64(spot.timestamp as i64 * 1_000);
+		reporter.text(Some(&spot.call_rx));
+		reporter_grid.text(Some(&spot.grid_rx));
+		snr.int32(Some(spot.snr.into()));
+		frequency.double(spot.frequency.mhz());
+		call.text(Some(&spot.call_tx));
+		grid.text(Some(&spot.grid_tx));
+		power.int32(spot.power.is_known().then_some(spot.power.0.into()));
+		drift.int32(Some(spot.drift.into()));
+		distance.int32(Some(spot.distance.into()));
+		mode.text(Some(&spot.mode.to_string()));
+
+		self.0.added()
+	}
+
+	/// Write the remaining spots and the footer
+	pub fn finish(self) -> io::Result<W> {
+		self.0.finish()
+	}
+}
@@ -0,0 +1,67 @@
+use std::cell::Cell;
+use std::io::{self, IsTerminal};
+use std::io::prelude::*;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+/// Minimum interval between updates of the progress line
+const INTERVAL: Duration = Duration::from_millis(250);
+
+/// Progress line on standard error for input of known size
+pub struct Progress {
+	/// Total input size in bytes
+	total: u64,
+	/// Bytes read so far
+	done: Rc<Cell<u64>>,
+	/// Rows read so far
+	rows: u64,
+	/// Time reading started
+	start: Instant,
+	/// Time of the last update
+	drawn: Instant,
+}
+
+impl Progress {
+	/// Start reporting progress, unless standard error is not a terminal
+	pub fn new(total: u64) -> Option<Self> {
+		if !io::stderr().is_terminal() {
+			return None;
+		}
+
+		let now = Instant::now();
+		Some(Progress { total, done: Rc::new(Cell::new(0)), rows: 0, start: now, drawn: now })
+	}
+
+	/// Counter to be updated with the number of bytes read
+	pub fn counter(&self) -> Rc<Cell<u64>> {
+		self.done.clone()
+	}
+
+	/// Account for a row and redraw if due
+	pub fn advance(&mut self) {
+		self.rows += 1;
+
+		// Checking the time is comparatively expensive
+		if self.rows.is_multiple_of(4096) && self.drawn.elapsed() >= INTERVAL {
+			self.drawn = Instant::now();
+			self.draw();
+		}
+	}
+
+	fn draw(&self) {
+		let elapsed = self.start.elapsed().as_secs_f64();
+		let fraction = (self.done.get() as f64 / self.total as f64).min(1.0);
+		let rate = self.rows as f64 / elapsed;
+		let eta = if fraction > 0.0 { elapsed * (1.0 - fraction) / fraction } else { 0.0 } as u64;
+
+		let _ = write!(io::stderr().lock(), "\r{:5.1} %  {} rows  {:.0} rows/s  ETA {}:{:02}:{:02}\x1b[K",
+			fraction * 100.0, self.rows, rate, eta / 3600, eta / 60 % 60, eta % 60);
+	}
+
+	/// Clear the progress line
+	pub fn finish(&self) {
+		if self.drawn > self.start {
+			let _ = write!(io::stderr().lock(), "\r\x1b[K");
+		}
+	}
+}
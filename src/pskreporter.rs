@@ -0,0 +1,85 @@
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::io;
+use std::io::prelude::*;
+
+use log::info;
+use regex::Regex;
+
+use crate::Call;
+use crate::locator;
+
+/// URL of the PSK Reporter retrieval interface
+const URL: &str = "https://retrieve.pskreporter.info/query";
+
+/// Period covered by the retrieval interface in seconds
+const PERIOD: u64 = 24 * 3600;
+
+/// Bit set in the IDs assigned to PSK Reporter reception reports
+///
+/// As the reports carry no IDs, these are derived from their contents, so that repeated reports
+/// are recognised as such.
+pub const PSK_REPORTER_ID: u64 = 1 << 61;
+
+/// Convert the attributes of a reception report into a row in WSPRnet CSV format
+///
+/// Reports lack the transmission power, which is left empty, and the frequency drift, which is
+/// taken to be zero.
+fn convert(report: &HashMap<&str, &str>) -> Option<String> {
+	let call_rx = report.get("receiverCallsign")?;
+	let grid_rx = report.get("receiverLocator").copied().unwrap_or_default();
+	let call_tx = report.get("senderCallsign")?;
+	let grid_tx = report.get("senderLocator").copied().unwrap_or_default();
+	let frequency: u64 = report.get("frequency")?.parse().ok()?;
+	let snr: i8 = report.get("sNR")?.parse().ok()?;
+
+	// Reports are timed when received, while spots are timed by the start of the cycle
+	let timestamp: u64 = report.get("flowStartSeconds")?.parse().ok()?;
+	let timestamp = timestamp - timestamp % 120;
+
+	let distance = locator::distance(grid_rx, grid_tx).map_or(0, |km| km.round() as u16);
+
+	let mut hasher = DefaultHasher::new();
+	(timestamp, call_rx, call_tx, frequency).hash(&mut hasher);
+	let id = PSK_REPORTER_ID | hasher.finish() & (PSK_REPORTER_ID - 1);
+
+	Some(format!("{},{},{},{},{},{:.6},{},{},,0,{}",
+		id, timestamp, call_rx, grid_rx, snr, frequency as f64 / 1e6, call_tx, grid_tx, distance))
+}
+
+/// Retrieve the WSPR reception reports of the last day involving the given call sign from PSK
+/// Reporter, as rows in WSPRnet CSV format in order of time
+pub fn rows(call: &Call) -> io::Result<Box<dyn Iterator<Item = io::Result<String>>>> {
+	info!("Querying PSK Reporter for {call}");
+
+	let mut body = String::new();
+	ureq::get(URL)
+		.set("User-Agent", concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION")))
+		.query("callsign", call.as_str())
+		.query("mode", "WSPR")
+		.query("flowStartSeconds", &format!("-{PERIOD}"))
+		.query("rronly", "1")
+		.query("noactive", "1")
+		.call()
+		.map_err(|err| io::Error::other(err.to_string()))?
+		.into_reader()
+		.read_to_string(&mut body)?;
+
+	let element = Regex::new(r"<receptionReport\s([^>]*)>").unwrap();
+	let attribute = Regex::new(r#"(\w+)="([^"]*)""#).unwrap();
+
+	let mut rows: Vec<(u64, String)> = element.captures_iter(&body)
+		.filter_map(|report| {
+			let report: HashMap<&str, &str> = attribute.captures_iter(report.get(1)?.as_str())
+				.filter_map(|attr| Some((attr.get(1)?.as_str(), attr.get(2)?.as_str())))
+				.collect();
+
+			Some((report.get("flowStartSeconds")?.parse().ok()?, convert(&report)?))
+		})
+		.collect();
+
+	rows.sort_by_key(|&(time, _)| time);
+	info!("Retrieved {} reception reports", rows.len());
+
+	Ok(Box::new(rows.into_iter().map(|(_, row)| Ok(row))))
+}
@@ -0,0 +1,382 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write as _;
+use std::io;
+use std::io::prelude::*;
+
+use chrono::{DateTime, Timelike, Utc};
+
+use crate::{Band, Call, Frequency, Qso};
+use crate::map::{self, escape};
+
+/// Size of a pixel of the map in degrees
+const SCALE: f64 = 0.25;
+
+/// Longest QSO on a band
+pub struct Record {
+	pub call_op: Call,
+	pub call_ct: Call,
+	pub grid_ct: String,
+	pub time: DateTime<Utc>,
+	pub distance: u16,
+}
+
+/// Longest QSO in time
+pub struct Session {
+	pub call_op: Call,
+	pub call_ct: Call,
+	pub band: std::string::String,
+	pub start: DateTime<Utc>,
+	pub end: DateTime<Utc>,
+}
+
+/// Statistics of a band
+pub struct BandStats {
+	/// Frequency for sorting
+	pub freq: Frequency,
+	/// Number of QSOs
+	pub num_qsos: usize,
+	/// Unique contact call signs
+	pub contacts: HashSet<Call>,
+	/// Longest QSO
+	pub record: Option<Record>,
+	/// Number of QSOs by the hour they started
+	pub hours: [usize; 24],
+}
+
+/// Statistics of all QSOs written
+#[derive(Default)]
+pub struct Statistics {
+	/// Statistics by transmit band of the operators
+	pub bands: HashMap<Option<Band>, BandStats>,
+	/// Unique contact call signs
+	pub contacts: HashSet<Call>,
+	/// Number of QSOs
+	pub num_qsos: usize,
+	/// Start of the first and end of the last QSO
+	pub period: Option<(DateTime<Utc>, DateTime<Utc>)>,
+	/// Longest QSO in time
+	pub session: Option<Session>,
+}
+
+impl Statistics {
+	/// Count a QSO
+	pub fn add(&mut self, qso: &Qso) {
+		let stats = self.bands.entry(Band::try_from(qso.freq_op).ok()).or_insert_with(|| BandStats {
+			freq: qso.freq_op,
+			num_qsos: 0,
+			contacts: HashSet::new(),
+			record: None,
+			hours: [0; 24],
+		});
+
+		stats.num_qsos += 1;
+		stats.contacts.insert(qso.call_ct.clone());
+		stats.hours[qso.datetime_on().hour() as usize] += 1;
+
+		if stats.record.as_ref().is_none_or(|record| qso.distance > record.distance) {
+			stats.record = Some(Record {
+				call_op: qso.call_op.clone(),
+				call_ct: qso.call_ct.clone(),
+				grid_ct: qso.grid_ct.to_string(),
+				time: qso.datetime_on(),
+				distance: qso.distance,
+			});
+		}
+
+		self.contacts.insert(qso.call_ct.clone());
+		self.num_qsos += 1;
+
+		let (on, off) = (qso.datetime_on(), qso.datetime_off());
+		self.period = Some(match self.period {
+			Some((first, last)) => (first.min(on), last.max(off)),
+			None => (on, off),
+		});
+
+		if self.session.as_ref().is_none_or(|session| off - on > session.end - session.start) {
+			self.session = Some(Session {
+				call_op: qso.call_op.clone(),
+				call_ct: qso.call_ct.clone(),
+				band: qso.band_description(),
+				start: on,
+				end: off,
+			});
+		}
+	}
+
+	/// Statistics by band from long to short wavelengths
+	pub fn bands(&self) -> Vec<(Option<&Band>, &BandStats)> {
+		let mut bands: Vec<_> = self.bands.iter().map(|(band, stats)| (band.as_ref(), stats)).collect();
+		bands.sort_by_key(|(_, stats)| stats.freq);
+		bands
+	}
+
+	/// Longest QSO on any band, along with the band
+	pub fn record(&self) -> Option<(Option<&Band>, &Record)> {
+		self.bands.iter()
+			.filter_map(|(band, stats)| Some((band.as_ref(), stats.record.as_ref()?)))
+			.max_by_key(|(_, record)| record.distance)
+	}
+}
+
+/// Name of a band for display
+pub fn band_name(band: Option<&Band>) -> std::string::String {
+	match band {
+		Some(band) => band.to_string(),
+		None => "other".to_owned(),
+	}
+}
+
+/// Single HTML file reporting the QSOs written, for sharing
+///
+/// The report holds statistics by band and hour, the longest QSOs, a map drawn as SVG and a table
+/// of all QSOs. It needs no resources beyond the file itself.
+pub struct Report<W: Write> {
+	out: W,
+	title: std::string::String,
+	stats: Statistics,
+	/// Rows of the QSO table
+	rows: std::string::String,
+	/// Paths and stations drawn on the map
+	paths: std::string::String,
+	/// Stations and paths between them by band, drawn only once each
+	stations: HashSet<(Call, String)>,
+	drawn: HashSet<(Call, String, Call, String, Option<std::string::String>)>,
+}
+
+impl<W: Write> Report<W> {
+	/// Start a new report, which is written once finished
+	pub fn new(out: W, title: &str) -> Self {
+		Report {
+			out,
+			title: title.to_owned(),
+			stats: Statistics::default(),
+			rows: std::string::String::new(),
+			paths: std::string::String::new(),
+			stations: HashSet::new(),
+			drawn: HashSet::new(),
+		}
+	}
+
+	/// Draw a station on the map unless already drawn
+	fn station(&mut self, call: &Call, grid: &str) {
+		let Some((lat, lon)) = crate::locator::position(grid) else {
+			return;
+		};
+
+		if self.stations.insert((call.clone(), grid.to_owned())) {
+			// Writing to a string cannot fail
+			let _ = writeln!(self.paths, "<circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"3\"><title>{} {}</title></circle>",
+			                 (lon + 180.0) / SCALE, (90.0 - lat) / SCALE, escape(call), escape(grid));
+		}
+	}
+
+	/// Add a QSO
+	pub fn write(&mut self, qso: &Qso) -> io::Result<()> {
+		self.stats.add(qso);
+		let band = map::band(qso);
+
+		let _ = writeln!(self.rows, "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td>\
+		                             <td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+		                 qso.datetime_on().format("%Y-%m-%d %H:%M"), qso.datetime_off().format("%H:%M"),
+		                 escape(&qso.call_op), escape(&qso.grid_op), escape(&qso.call_ct), escape(&qso.grid_ct),
+		                 escape(&qso.band_description()), qso.snr_op, qso.snr_ct, escape(&qso.power_ct.to_string()),
+		                 qso.distance, qso.spots.len());
+
+		let key = (qso.call_op.clone(), qso.grid_op.to_string(), qso.call_ct.clone(), qso.grid_ct.to_string(), band.clone());
+		if let Some(path) = map::path(qso).filter(|_| self.drawn.insert(key)) {
+			let mut points = std::string::String::new();
+			for (lat, lon) in map::continuous(path) {
+				let _ = write!(points, "{:.1},{:.1} ", (lon + 180.0) / SCALE, (90.0 - lat) / SCALE);
+			}
+
+			let _ = writeln!(self.paths, "<polyline points=\"{}\" stroke=\"#{}\"><title>{} – {}, {} km</title></polyline>",
+			                 points.trim_end(), map::color(band.as_deref()), escape(&qso.call_op), escape(&qso.call_ct),
+			                 qso.distance);
+		}
+
+		self.station(&qso.call_op, &qso.grid_op);
+		self.station(&qso.call_ct, &qso.grid_ct);
+		Ok(())
+	}
+
+	/// Write the map with a Maidenhead field grid
+	fn map(&mut self) -> io::Result<()> {
+		let (width, height) = (360.0 / SCALE, 180.0 / SCALE);
+		writeln!(self.out, "<svg class=\"map\" viewBox=\"0 0 {width} {height}\" xmlns=\"http://www.w3.org/2000/svg\">")?;
+		writeln!(self.out, "<rect width=\"{width}\" height=\"{height}\" fill=\"#eef4f8\"/>")?;
+		writeln!(self.out, "<g class=\"grid\">")?;
+
+		for field in 0..18 {
+			let letter = char::from(b'A' + field);
+			let (x, y) = (f64::from(field) * 20.0 / SCALE, f64::from(field) * 10.0 / SCALE);
+			writeln!(self.out, "<line x1=\"{x}\" y1=\"0\" x2=\"{x}\" y2=\"{height}\"/><line x1=\"0\" y1=\"{y}\" x2=\"{width}\" y2=\"{y}\"/>")?;
+			writeln!(self.out, "<text x=\"{}\" y=\"{}\">{letter}</text><text x=\"4\" y=\"{}\">{letter}</text>",
+			         x + 4.0, height - 4.0, height - y - 4.0)?;
+		}
+
+		writeln!(self.out, "</g>")?;
+		writeln!(self.out, "<g class=\"paths\">\n{}</g>", self.paths)?;
+		writeln!(self.out, "</svg>")
+	}
+
+	/// Write the report
+	pub fn finish(mut self) -> io::Result<W> {
+		let title = escape(&self.title);
+		writeln!(self.out, "<!DOCTYPE html>")?;
+		writeln!(self.out, "<html lang=\"en\">")?;
+		writeln!(self.out, "<head>")?;
+		writeln!(self.out, "<meta charset=\"utf-8\">")?;
+		writeln!(self.out, "<title>{title}</title>")?;
+		writeln!(self.out, "<style>{}</style>", STYLE)?;
+		writeln!(self.out, "</head>")?;
+		writeln!(self.out, "<body>")?;
+		writeln!(self.out, "<h1>{title}</h1>")?;
+
+		let period = match self.stats.period {
+			Some((first, last)) => format!(" from {} to {} UTC", first.format("%Y-%m-%d %H:%M"), last.format("%Y-%m-%d %H:%M")),
+			None => std::string::String::new(),
+		};
+
+		writeln!(self.out, "<p>{} QSOs with {} unique call signs{period}</p>", self.stats.num_qsos, self.stats.contacts.len())?;
+
+		writeln!(self.out, "<h2>Map</h2>")?;
+		self.map()?;
+
+		let bands = self.stats.bands();
+
+		writeln!(self.out, "<h2>Bands</h2>")?;
+		writeln!(self.out, "<table>")?;
+		writeln!(self.out, "<tr><th>Band</th><th>QSOs</th><th>Contacts</th><th>Longest</th><th>Operator</th><th>Contact</th><th>Locator</th><th>Start (UTC)</th></tr>")?;
+		for (band, stats) in &bands {
+			write!(self.out, "<tr><td><span class=\"band\" style=\"background: #{}\"></span>{}</td><td>{}</td><td>{}</td>",
+			       map::color(band.map(Band::name).as_deref()), band_name(*band),
+			       stats.num_qsos, stats.contacts.len())?;
+
+			if let Some(record) = &stats.record {
+				write!(self.out, "<td>{} km</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td>", record.distance,
+				       escape(&record.call_op), escape(&record.call_ct), escape(&record.grid_ct),
+				       record.time.format("%Y-%m-%d %H:%M"))?;
+			}
+
+			writeln!(self.out, "</tr>")?;
+		}
+		writeln!(self.out, "</table>")?;
+
+		// Shade the hours relative to the busiest one on any band
+		let busiest = bands.iter().flat_map(|(_, stats)| stats.hours).max().unwrap_or(0).max(1);
+
+		writeln!(self.out, "<h2>Activity by hour</h2>")?;
+		writeln!(self.out, "<table class=\"heat\">")?;
+		write!(self.out, "<tr><th>UTC</th>")?;
+		for (band, _) in &bands {
+			write!(self.out, "<th>{}</th>", band_name(*band))?;
+		}
+		writeln!(self.out, "</tr>")?;
+
+		for hour in 0..24 {
+			write!(self.out, "<tr><th>{hour:02}</th>")?;
+			for (_, stats) in &bands {
+				let count = stats.hours[hour];
+				let alpha = count as f64 / busiest as f64;
+				if count > 0 {
+					write!(self.out, "<td style=\"background: rgba(215, 48, 39, {alpha:.2})\">{count}</td>")?;
+				} else {
+					write!(self.out, "<td></td>")?;
+				}
+			}
+			writeln!(self.out, "</tr>")?;
+		}
+		writeln!(self.out, "</table>")?;
+
+		writeln!(self.out, "<h2>QSOs</h2>")?;
+		writeln!(self.out, "<table>")?;
+		writeln!(self.out, "<tr><th>Start (UTC)</th><th>End</th><th>Operator</th><th>Locator</th><th>Contact</th><th>Locator</th>\
+		                    <th>Band</th><th>My SNR</th><th>SNR</th><th>Power</th><th>Distance</th><th>Spots</th></tr>")?;
+		self.out.write_all(self.rows.as_bytes())?;
+		writeln!(self.out, "</table>")?;
+
+		writeln!(self.out, "</body>")?;
+		writeln!(self.out, "</html>")?;
+		Ok(self.out)
+	}
+}
+
+/// Concise summary of the QSOs written in Markdown, for blog posts and newsletters
+pub struct Summary<W: Write> {
+	out: W,
+	title: std::string::String,
+	stats: Statistics,
+}
+
+impl<W: Write> Summary<W> {
+	/// Start a new summary, which is written once finished
+	pub fn new(out: W, title: &str) -> Self {
+		Summary { out, title: title.to_owned(), stats: Statistics::default() }
+	}
+
+	/// Add a QSO
+	pub fn write(&mut self, qso: &Qso) -> io::Result<()> {
+		self.stats.add(qso);
+		Ok(())
+	}
+
+	/// Write the summary
+	pub fn finish(mut self) -> io::Result<W> {
+		writeln!(self.out, "# {}", self.title)?;
+		writeln!(self.out)?;
+
+		match self.stats.period {
+			Some((first, last)) => writeln!(self.out, "{} QSOs with {} unique call signs from {} to {} UTC.",
+			                                self.stats.num_qsos, self.stats.contacts.len(),
+			                                first.format("%Y-%m-%d %H:%M"), last.format("%Y-%m-%d %H:%M"))?,
+			None => writeln!(self.out, "No QSOs.")?,
+		}
+
+		let bands = self.stats.bands();
+		if !bands.is_empty() {
+			writeln!(self.out)?;
+			writeln!(self.out, "| Band | QSOs | Contacts | Best DX |")?;
+			writeln!(self.out, "|------|-----:|---------:|--------:|")?;
+			for (band, stats) in &bands {
+				writeln!(self.out, "| {} | {} | {} | {} km |", band_name(*band), stats.num_qsos, stats.contacts.len(),
+				         stats.record.as_ref().map_or(0, |record| record.distance))?;
+			}
+		}
+
+		if let Some((band, record)) = self.stats.record() {
+			writeln!(self.out)?;
+			writeln!(self.out, "- **Best DX:** {} km between {} and {} ({}) on {}, {} UTC",
+			         record.distance, record.call_op, record.call_ct, record.grid_ct, band_name(band),
+			         record.time.format("%Y-%m-%d %H:%M"))?;
+		}
+
+		if let Some(session) = &self.stats.session {
+			let minutes = (session.end - session.start).num_minutes();
+			let duration = match minutes / 60 {
+				0 => format!("{minutes} min"),
+				hours => format!("{hours} h {:02} min", minutes % 60),
+			};
+
+			writeln!(self.out, "- **Longest session:** {duration} between {} and {} on {}, from {} to {} UTC",
+			         session.call_op, session.call_ct, session.band,
+			         session.start.format("%Y-%m-%d %H:%M"), session.end.format("%H:%M"))?;
+		}
+
+		Ok(self.out)
+	}
+}
+
+/// Style sheet of reports
+const STYLE: &str = "
+body { font-family: sans-serif; margin: 2em; color: #222; }
+table { border-collapse: collapse; margin-bottom: 2em; }
+th, td { padding: 0.2em 0.6em; border-bottom: 1px solid #ddd; text-align: left; }
+td { font-variant-numeric: tabular-nums; }
+.heat td { text-align: center; min-width: 2.5em; }
+.band { display: inline-block; width: 0.8em; height: 0.8em; margin-right: 0.4em; }
+.map { width: 100%; max-width: 1440px; border: 1px solid #ccc; }
+.map .grid line { stroke: #c8d4dc; stroke-width: 1; }
+.map .grid text { fill: #9aa8b2; font-size: 12px; }
+.map polyline { fill: none; stroke-width: 1.5; stroke-opacity: 0.7; }
+.map circle { fill: #222; }
+";
@@ -0,0 +1,500 @@
+use std::borrow::Cow;
+use std::str::FromStr;
+
+use chrono::NaiveDateTime;
+
+use crate::Mode;
+
+/// Number of columns of the WSPRnet CSV format including the mode
+const COLUMNS: usize = 16;
+
+/// Number of columns required for a spot
+const REQUIRED: usize = 11;
+
+/// Names of the columns of the WSPRnet CSV format including the mode
+const NAMES: [&str; COLUMNS] = [
+	"id", "timestamp", "reporter", "reporter_grid", "snr", "frequency", "call", "grid", "power", "drift",
+	"distance", "azimuth", "band", "version", "code", "mode",
+];
+
+/// Alternative column names, as used by wspr.live among others
+const ALIASES: &[(&str, usize)] = &[
+	("spot_id", 0),
+	("time", 1),
+	("unix_time", 1),
+	("rx_sign", 2),
+	("rx_call", 2),
+	("rx_loc", 3),
+	("rx_grid", 3),
+	("reporter_locator", 3),
+	("freq", 5),
+	("hz", 5),
+	("frequency_hz", 5),
+	("khz", 5),
+	("frequency_khz", 5),
+	("mhz", 5),
+	("frequency_mhz", 5),
+	("tx_sign", 6),
+	("tx_call", 6),
+	("call_sign", 6),
+	("callsign", 6),
+	("tx_loc", 7),
+	("tx_grid", 7),
+	("locator", 7),
+	("dbm", 8),
+	("km", 10),
+];
+
+/// Column names only used by wspr.live, whose frequencies are in Hz
+const WSPR_LIVE_NAMES: [&str; 2] = ["rx_sign", "tx_sign"];
+
+/// Column name in lowercase with spaces and hyphens taken for underscores
+fn normalize(name: &str) -> String {
+	name.trim().to_ascii_lowercase().replace([' ', '-'], "_")
+}
+
+/// Position of a column in the WSPRnet CSV format by name or alias
+pub fn position(name: &str) -> Option<usize> {
+	let name = normalize(name);
+
+	NAMES.iter().position(|&known| known == name)
+		.or_else(|| ALIASES.iter().find(|&&(alias, _)| alias == name).map(|&(_, idx)| idx))
+}
+
+/// Unit of frequencies in a spot file
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Unit {
+	Hz,
+	KHz,
+	MHz,
+}
+
+impl Unit {
+	/// Unit of a frequency column by name
+	///
+	/// Plain frequency columns are in MHz as in WSPRnet archives, except in files with the column
+	/// names of wspr.live, which gives them in Hz.
+	fn of(name: &str, wspr_live: bool) -> Unit {
+		match normalize(name).as_str() {
+			"hz" | "frequency_hz" => Unit::Hz,
+			"khz" | "frequency_khz" => Unit::KHz,
+			"mhz" | "frequency_mhz" => Unit::MHz,
+			_ if wspr_live => Unit::Hz,
+			_ => Unit::MHz,
+		}
+	}
+
+	/// Whether any of the given column names is one of wspr.live
+	fn wspr_live<'a>(mut names: impl Iterator<Item = &'a str>) -> bool {
+		names.any(|name| WSPR_LIVE_NAMES.contains(&normalize(name).as_str()))
+	}
+
+	/// Number of this unit per MHz
+	fn per_mhz(self) -> f64 {
+		match self {
+			Unit::Hz => 1e6,
+			Unit::KHz => 1e3,
+			Unit::MHz => 1.0,
+		}
+	}
+}
+
+/// Column layout of a spot file
+///
+/// Rows are converted into the WSPRnet CSV format, followed by the mode and any extra columns.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Schema {
+	/// Source column of each column of the WSPRnet CSV format, if present
+	columns: [Option<usize>; COLUMNS],
+	/// Source columns of extra columns
+	extra: Vec<usize>,
+	/// Source column from which on all columns are extra columns
+	rest: Option<usize>,
+	/// Unit of the frequency column
+	unit: Unit,
+	/// Field delimiter of the source
+	delimiter: char,
+}
+
+impl Schema {
+	/// WSPRnet archives of up to 15 columns, followed by extra columns in wsprdaemon archives
+	pub const WSPRNET: Schema = Schema {
+		columns: [Some(0), Some(1), Some(2), Some(3), Some(4), Some(5), Some(6), Some(7), Some(8), Some(9),
+		          Some(10), Some(11), Some(12), Some(13), Some(14), None],
+		extra: Vec::new(),
+		rest: Some(15),
+		unit: Unit::MHz,
+		delimiter: ',',
+	};
+
+	/// Recent WSPRnet rows with the mode following the code
+	pub const WSPRNET_MODE: Schema = Schema {
+		columns: [Some(0), Some(1), Some(2), Some(3), Some(4), Some(5), Some(6), Some(7), Some(8), Some(9),
+		          Some(10), Some(11), Some(12), Some(13), Some(14), Some(15)],
+		extra: Vec::new(),
+		rest: Some(16),
+		unit: Unit::MHz,
+		delimiter: ',',
+	};
+
+	/// Rows of the wspr.live `rx` table, with the time as date and the frequency in Hz
+	pub const WSPR_LIVE: Schema = Schema {
+		columns: [Some(0), Some(1), Some(3), Some(6), Some(16), Some(14), Some(7), Some(10), Some(15), Some(17),
+		          Some(11), Some(12), Some(2), Some(18), Some(19), None],
+		extra: Vec::new(),
+		rest: Some(20),
+		unit: Unit::Hz,
+		delimiter: ',',
+	};
+
+	/// Guess the layout from the first row of a file without header
+	///
+	/// A mode following the code is only recognised by name, as the numbers of modes cannot be told
+	/// apart from the small integers in the extra columns of wsprdaemon.
+	pub fn detect(row: &str, delimiter: char) -> Schema {
+		let fields = fields(row, delimiter);
+
+		let schema = if fields.len() >= 20 && fields[1].contains('-') {
+			Schema::WSPR_LIVE
+		} else if fields.get(15).is_some_and(|mode| !mode.bytes().all(|ch| ch.is_ascii_digit()) && mode.parse::<Mode>().is_ok()) {
+			Schema::WSPRNET_MODE
+		} else {
+			Schema::WSPRNET
+		};
+
+		schema.delimiter(delimiter)
+	}
+
+	/// Build the layout from a header row, if the row is one
+	///
+	/// Columns of unknown names are kept as extra columns.
+	pub fn header(row: &str, delimiter: char) -> Option<Result<Schema, String>> {
+		let fields = fields(row, delimiter);
+		position(fields.first()?)?;
+
+		Some(Schema::from_names(fields.iter().map(AsRef::as_ref), true).map(|schema| schema.delimiter(delimiter)))
+	}
+
+	/// Use the given field delimiter
+	pub fn delimiter(mut self, delimiter: char) -> Schema {
+		self.delimiter = delimiter;
+		self
+	}
+
+	/// Build the layout from column names, with `-` denoting columns to be ignored
+	///
+	/// Columns of unknown names are kept as extra columns if `lenient`, and rejected otherwise.
+	fn from_names<'a>(names: impl Iterator<Item = &'a str> + Clone, lenient: bool) -> Result<Schema, String> {
+		let mut columns = [None; COLUMNS];
+		let mut extra = Vec::new();
+		let mut unit = Unit::MHz;
+		let wspr_live = Unit::wspr_live(names.clone());
+
+		for (idx, name) in names.enumerate() {
+			match position(name) {
+				Some(5) if columns[5].is_none() => {
+					columns[5] = Some(idx);
+					unit = Unit::of(name, wspr_live);
+				},
+				Some(pos) if columns[pos].is_none() => columns[pos] = Some(idx),
+				Some(_) => return Err(format!("duplicate column {name}")),
+				None if name == "-" => (),
+				None if lenient => extra.push(idx),
+				None => return Err(format!("unknown column {name}, expected one of {}", NAMES.join(", "))),
+			}
+		}
+
+		if let Some(missing) = (0..REQUIRED).find(|&pos| columns[pos].is_none()) {
+			return Err(format!("missing column {}", NAMES[missing]));
+		}
+
+		Ok(Schema { columns, extra, rest: None, unit, delimiter: ',' })
+	}
+
+	/// Convert a row into the WSPRnet CSV format followed by the mode and any extra columns
+	///
+	/// Returns `None` if the row is in that format already.
+	pub fn convert(&self, row: &str) -> Option<String> {
+		let fields = fields(row, self.delimiter);
+		let clean = fields.iter().all(|field| matches!(field, Cow::Borrowed(_)));
+		let in_order = self.columns.iter().enumerate().all(|(idx, &column)| column.is_none_or(|column| column == idx));
+
+		if clean && in_order && self.extra.is_empty() && self.unit == Unit::MHz && self.rest.is_some_and(|rest| rest == COLUMNS || fields.len() <= rest) {
+			return None;
+		}
+
+		let mut out = Vec::with_capacity(COLUMNS + fields.len().saturating_sub(COLUMNS));
+
+		for (idx, column) in self.columns.iter().enumerate() {
+			let value = column.and_then(|column| fields.get(column)).map_or("", AsRef::as_ref);
+
+			out.push(match idx {
+				1 => timestamp(value),
+				5 => frequency(value, self.unit),
+				_ => value.to_owned(),
+			});
+		}
+
+		out.extend(self.extra.iter().filter_map(|&column| fields.get(column)).map(|value| value.to_string()));
+
+		if let Some(rest) = self.rest {
+			out.extend(fields.iter().skip(rest).map(|value| value.to_string()));
+		}
+
+		Some(out.join(","))
+	}
+}
+
+impl FromStr for Schema {
+	type Err = String;
+
+	/// Parse a comma‐separated list of column names, with any further columns kept as extra columns
+	fn from_str(names: &str) -> Result<Self, Self::Err> {
+		let mut schema = Schema::from_names(names.split(','), false)?;
+		schema.rest = Some(names.split(',').count());
+		Ok(schema)
+	}
+}
+
+/// Build a row in the WSPRnet CSV format followed by the mode from values given by column name
+///
+/// Values of unknown names are ignored. Frequencies are converted once all names are known, as
+/// their unit depends on them.
+pub fn row<'a>(values: impl Iterator<Item = (&'a str, String)>) -> String {
+	let mut out: [String; COLUMNS] = Default::default();
+	let (mut freq_name, mut wspr_live) = ("", false);
+
+	for (name, value) in values {
+		wspr_live |= Unit::wspr_live(std::iter::once(name));
+
+		if let Some(pos) = position(name) {
+			out[pos] = match pos {
+				1 => timestamp(&value),
+				5 => {
+					freq_name = name;
+					value
+				},
+				_ => value,
+			};
+		}
+	}
+
+	out[5] = frequency(&out[5], Unit::of(freq_name, wspr_live));
+	out.join(",")
+}
+
+/// Convert a row of any layout detected from files without header into the WSPRnet CSV format,
+/// as for rows received one at a time
+///
+/// Returns `None` for header rows, which are skipped.
+pub fn convert_row(row: &str) -> Option<String> {
+	let delimiter = delimiter(row);
+	if Schema::header(row, delimiter).is_some() {
+		return None;
+	}
+
+	Some(Schema::detect(row, delimiter).convert(row).unwrap_or_else(|| row.to_owned()))
+}
+
+/// Field delimiters recognised in files
+const DELIMITERS: [char; 4] = [',', ';', '\t', '|'];
+
+/// Guess the field delimiter from the first row of a file, being the most frequent candidate
+pub fn delimiter(row: &str) -> char {
+	DELIMITERS.into_iter()
+		.rev()
+		.max_by_key(|&delimiter| row.matches(delimiter).count())
+		.unwrap_or(',')
+}
+
+/// Split a CSV row into fields
+///
+/// Rows separated by commas without quotes or whitespace are split as they are. Otherwise, fields
+/// are stripped of surrounding whitespace and quotes, with doubled quotes standing for one. Commas
+/// within quotes are replaced by semicolons, as the fields are passed on unquoted, while commas in
+/// unquoted fields of other delimiters are taken for decimal commas.
+fn fields(row: &str, delimiter: char) -> Vec<Cow<'_, str>> {
+	if delimiter == ',' && !row.contains(['"', ' ', '\t']) {
+		return row.split(',').map(Cow::Borrowed).collect();
+	}
+
+	let mut fields = Vec::new();
+	let mut chars = row.chars().peekable();
+
+	loop {
+		let mut field = String::new();
+
+		while chars.next_if(|&ch| ch.is_whitespace() && ch != delimiter).is_some() {}
+
+		if chars.next_if_eq(&'"').is_some() {
+			loop {
+				match chars.next() {
+					Some('"') if chars.next_if_eq(&'"').is_some() => field.push('"'),
+					Some('"') | None => break,
+					Some(',') => field.push(';'),
+					Some(ch) => field.push(ch),
+				}
+			}
+		}
+
+		let mut more = false;
+		for ch in chars.by_ref() {
+			match ch {
+				ch if ch == delimiter => {
+					more = true;
+					break;
+				},
+				',' => field.push('.'),
+				ch => field.push(ch),
+			}
+		}
+
+		fields.push(Cow::Owned(field.trim().to_owned()));
+
+		if !more {
+			return fields;
+		}
+	}
+}
+
+/// Timestamp in seconds since the Unix epoch, converted from a date and time in UTC if necessary
+fn timestamp(value: &str) -> String {
+	["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M"].iter()
+		.find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+		.map_or_else(|| value.to_owned(), |time| time.and_utc().timestamp().to_string())
+}
+
+/// Frequency in MHz, converted from the unit of its column if necessary
+fn frequency(value: &str, unit: Unit) -> String {
+	match value.parse::<f64>() {
+		Ok(freq) if unit != Unit::MHz => format!("{:.6}", freq / unit.per_mhz()),
+		_ => value.to_owned(),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// WSPRnet archive row of 15 columns
+	const WSPRNET_ROW: &str = "2736249418,1610485200,DO5EU,JO62qm,-29,7.040022,DP0GVN,IB59ui,27,0,13805,189,7,2.2.2,0";
+
+	/// Row of the wspr.live `rx` table
+	const WSPR_LIVE_ROW: &str = "2736249418,2021-01-12 21:00:00,7,DO5EU,52.5,13.5,JO62qm,DP0GVN,-71.5,-1.5,IB59ui,13805,189,9,7040022,27,-29,0,2.2.2,0";
+
+	#[test]
+	fn detects_wsprnet_rows() {
+		assert_eq!(Schema::detect(WSPRNET_ROW, ','), Schema::WSPRNET);
+		assert_eq!(Schema::detect(&format!("{WSPRNET_ROW},WSPR-2"), ','), Schema::WSPRNET_MODE);
+		assert_eq!(Schema::detect(&format!("{WSPRNET_ROW},fst4w-120"), ','), Schema::WSPRNET_MODE);
+	}
+
+	#[test]
+	fn does_not_detect_mode_numbers() {
+		assert_eq!(Schema::detect(&format!("{WSPRNET_ROW},15,3,2"), ','), Schema::WSPRNET);
+	}
+
+	#[test]
+	fn converts_wspr_live_rows() {
+		let schema = Schema::detect(WSPR_LIVE_ROW, ',');
+		assert_eq!(schema, Schema::WSPR_LIVE);
+		assert_eq!(
+			schema.convert(WSPR_LIVE_ROW).unwrap(),
+			"2736249418,1610485200,DO5EU,JO62qm,-29,7.040022,DP0GVN,IB59ui,27,0,13805,189,7,2.2.2,0,"
+		);
+	}
+
+	#[test]
+	fn keeps_wsprnet_rows() {
+		assert_eq!(Schema::WSPRNET.convert(WSPRNET_ROW), None);
+		assert_eq!(Schema::WSPRNET_MODE.convert(&format!("{WSPRNET_ROW},WSPR-2")), None);
+	}
+
+	#[test]
+	fn reads_header_rows() {
+		assert_eq!(Schema::header(WSPRNET_ROW, ','), None);
+
+		let schema = Schema::header("Call,Grid,Reporter,Reporter Grid,SNR,Frequency,Timestamp,Power,Drift,Distance,ID,Note", ',')
+			.unwrap()
+			.unwrap();
+		assert_eq!(
+			schema.convert("DP0GVN,IB59ui,DO5EU,JO62qm,-29,7.040022,1610485200,27,0,13805,2736249418,test").unwrap(),
+			"2736249418,1610485200,DO5EU,JO62qm,-29,7.040022,DP0GVN,IB59ui,27,0,13805,,,,,,test"
+		);
+	}
+
+	#[test]
+	fn rejects_invalid_headers() {
+		assert_eq!(Schema::header("id,timestamp,reporter", ','), Some(Err("missing column reporter_grid".into())));
+		assert_eq!(
+			Schema::header("id,timestamp,reporter,reporter_grid,snr,frequency,call,grid,power,drift,distance,call", ','),
+			Some(Err("duplicate column call".into()))
+		);
+	}
+
+	#[test]
+	fn parses_column_lists() {
+		let schema: Schema = "id,timestamp,reporter,reporter_grid,snr,frequency,call,grid,power,drift,-,distance".parse().unwrap();
+		assert_eq!(
+			schema.convert("1,2,A,B,-10,14.0971,C,D,37,0,x,100,y").unwrap(),
+			"1,2,A,B,-10,14.0971,C,D,37,0,100,,,,,,y"
+		);
+
+		assert!("id,timestamp,unknown".parse::<Schema>().unwrap_err().starts_with("unknown column unknown"));
+	}
+
+	#[test]
+	fn splits_quoted_fields() {
+		assert_eq!(fields("1,2,,3", ','), ["1", "2", "", "3"]);
+		assert_eq!(fields(" 1 , \"DO5EU\" ,\"say \"\"hi\"\", there\",", ','), ["1", "DO5EU", "say \"hi\"; there", ""]);
+	}
+
+	#[test]
+	fn converts_quoted_rows() {
+		let row = "\"2736249418\", \"1610485200\", \"DO5EU\", \"JO62qm\", -29, 7.040022, \"DP0GVN\", \"IB59ui\", 27, 0, 13805, 189, 7, \"2.2.2\", 0";
+		assert_eq!(Schema::WSPRNET.convert(row).unwrap(), WSPRNET_ROW.to_owned() + ",");
+	}
+
+	#[test]
+	fn detects_delimiters() {
+		assert_eq!(delimiter(WSPRNET_ROW), ',');
+		assert_eq!(delimiter(&WSPRNET_ROW.replace(',', "\t")), '\t');
+		assert_eq!(delimiter("1;2;DO5EU;JO62qm;-29;7,040022"), ';');
+		assert_eq!(delimiter("1|2|3"), '|');
+	}
+
+	#[test]
+	fn reads_decimal_commas() {
+		let row = WSPRNET_ROW.replace(',', ";").replace("7.040022", "7,040022");
+		assert_eq!(Schema::detect(&row, ';').convert(&row).unwrap(), WSPRNET_ROW.to_owned() + ",");
+	}
+
+	#[test]
+	fn takes_frequency_unit_from_header() {
+		let header = |freq: &str| format!("id,timestamp,reporter,reporter_grid,snr,{freq},call,grid,power,drift,distance");
+		let convert = |header: &str, freq: &str| {
+			let schema = Schema::header(header, ',').unwrap().unwrap();
+			let row = format!("1,2,DO5EU,JO62qm,-29,{freq},DP0GVN,IB59ui,27,0,13805");
+			schema.convert(&row).unwrap_or(row).split(',').nth(5).unwrap().to_owned()
+		};
+
+		assert_eq!(convert(&header("frequency"), "0.137500"), "0.137500");
+		assert_eq!(convert(&header("frequency"), "14097100"), "14097100");
+		assert_eq!(convert(&header("hz"), "137500"), "0.137500");
+		assert_eq!(convert(&header("frequency_khz"), "14097.1"), "14.097100");
+		assert_eq!(convert(&header("MHz"), "14.0971"), "14.0971");
+		assert_eq!(convert(&header("frequency").replace("reporter,", "rx_sign,"), "14097100"), "14.097100");
+	}
+
+	#[test]
+	fn takes_frequency_unit_from_names() {
+		let frequency = |names: [&str; 2]| {
+			let row = row([(names[0], "DO5EU".to_owned()), (names[1], "14097100".to_owned())].into_iter());
+			row.split(',').nth(5).unwrap().to_owned()
+		};
+
+		assert_eq!(frequency(["reporter", "frequency"]), "14097100");
+		assert_eq!(frequency(["rx_sign", "frequency"]), "14.097100");
+		assert_eq!(frequency(["reporter", "hz"]), "14.097100");
+	}
+}
@@ -0,0 +1,143 @@
+use std::cell::Cell;
+use std::fs;
+use std::io;
+use std::io::prelude::*;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::mpsc;
+use std::thread;
+
+use log::{debug, info, warn};
+
+use crate::json;
+use crate::schema;
+
+/// Line sent by a client to have the QSOs logged right away
+pub const FLUSH: &str = "FLUSH";
+
+/// Number of rows received ahead of processing, beyond which clients are held up
+const BUFFER: usize = 4096;
+
+/// Line received from a client
+enum Message {
+	/// Spot in WSPRnet CSV format
+	Row(String),
+	/// Request to log the QSOs right away
+	Flush,
+}
+
+/// Listen at the given path, replacing a socket left behind by a previous run
+fn bind(path: &Path) -> io::Result<UnixListener> {
+	match UnixListener::bind(path) {
+		Err(err) if err.kind() == io::ErrorKind::AddrInUse && UnixStream::connect(path).is_err() => {
+			fs::remove_file(path)?;
+			UnixListener::bind(path)
+		},
+		result => result,
+	}
+}
+
+/// Pass on the lines sent by a client until it disconnects
+///
+/// Lines hold a spot as a JSON object or as CSV in any layout detected from files without header.
+fn serve(stream: UnixStream, path: &Path, tx: &mpsc::SyncSender<Message>) {
+	info!("Client connected to {}", path.display());
+
+	for line in io::BufReader::new(stream).lines() {
+		let line = match line {
+			Ok(line) => line,
+			Err(err) => {
+				warn!("Connection to {} failed: {err}", path.display());
+				return;
+			},
+		};
+
+		let line = line.trim();
+		let message = match line {
+			"" => continue,
+			FLUSH => Message::Flush,
+			line if line.starts_with('{') => match json::convert(line) {
+				Some(row) => Message::Row(row),
+				None => {
+					debug!("Skipping undecodable spot “{line}”");
+					continue;
+				},
+			},
+			line => match schema::convert_row(line) {
+				Some(row) => Message::Row(row),
+				None => continue,
+			},
+		};
+
+		// Blocks while the buffer is full
+		if tx.send(message).is_err() {
+			return;
+		}
+	}
+
+	info!("Client disconnected from {}", path.display());
+}
+
+/// Rows received over Unix sockets
+struct Rows {
+	rx: mpsc::Receiver<Message>,
+	/// End iteration whenever no row has been received yet, and on requests to flush
+	follow: bool,
+	/// Set on requests to flush
+	flush: Rc<Cell<bool>>,
+}
+
+impl Iterator for Rows {
+	type Item = io::Result<String>;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		loop {
+			let message = if self.follow {
+				self.rx.try_recv().ok()?
+			} else {
+				self.rx.recv().ok()?
+			};
+
+			match message {
+				Message::Row(row) => return Some(Ok(row)),
+				Message::Flush if self.follow => {
+					self.flush.set(true);
+					return None;
+				},
+				Message::Flush => (),
+			}
+		}
+	}
+}
+
+/// Receive spots sent to Unix sockets at the given paths as rows in WSPRnet CSV format
+///
+/// Clients may connect and disconnect at any time, each served by a separate thread, and rows are
+/// yielded in order of arrival. When following, iteration ends whenever no row is available, and
+/// a client sending [FLUSH] sets the given flag. Otherwise, iteration never ends.
+pub fn rows(paths: &[PathBuf], follow: bool, flush: Rc<Cell<bool>>) -> io::Result<Box<dyn Iterator<Item = io::Result<String>>>> {
+	let (tx, rx) = mpsc::sync_channel(BUFFER);
+
+	for path in paths {
+		let listener = bind(path).map_err(|err| io::Error::new(err.kind(), format!("{}: {}", path.display(), err)))?;
+		info!("Listening for spots at {}", path.display());
+
+		let path = path.clone();
+		let tx = tx.clone();
+		thread::spawn(move || {
+			for stream in listener.incoming() {
+				match stream {
+					Ok(stream) => {
+						let path = path.clone();
+						let tx = tx.clone();
+						thread::spawn(move || serve(stream, &path, &tx));
+					},
+					Err(err) => warn!("Accepting connection at {} failed: {err}", path.display()),
+				}
+			}
+		});
+	}
+
+	Ok(Box::new(Rows { rx, follow, flush }))
+}
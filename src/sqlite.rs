@@ -0,0 +1,178 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
+
+use rusqlite::{Connection, OpenFlags, params};
+use rusqlite::types::ValueRef;
+
+use crate::{Band, Power, Qso};
+use crate::schema;
+
+/// Default query for spots
+pub const DEFAULT_QUERY: &str = "SELECT * FROM spots ORDER BY timestamp, id";
+
+/// Number of rows fetched ahead of processing
+const BUFFER: usize = 4096;
+
+/// Value of a column as in CSV
+fn value(value: ValueRef) -> String {
+	match value {
+		ValueRef::Null | ValueRef::Blob(_) => String::new(),
+		ValueRef::Integer(value) => value.to_string(),
+		ValueRef::Real(value) => value.to_string(),
+		ValueRef::Text(value) => String::from_utf8_lossy(value).into_owned(),
+	}
+}
+
+/// Run the query, sending the resulting rows in WSPRnet CSV format until the receiver hangs up
+fn query(path: &Path, sql: &str, tx: &mpsc::SyncSender<io::Result<String>>) -> rusqlite::Result<()> {
+	let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+	let mut stmt = conn.prepare(sql)?;
+	let names: Vec<String> = stmt.column_names().into_iter().map(String::from).collect();
+	let mut rows = stmt.query([])?;
+
+	while let Some(row) = rows.next()? {
+		let row = schema::row(names.iter().enumerate()
+			.map(|(idx, name)| (name.as_str(), row.get_ref(idx).map_or_else(|_| String::new(), value))));
+
+		if tx.send(Ok(row)).is_err() {
+			break;
+		}
+	}
+
+	Ok(())
+}
+
+/// Query spots from a SQLite database as rows in WSPRnet CSV format
+///
+/// Result columns are looked up by the same names as in a header row. The query runs on a separate
+/// thread, so that rows are fetched while earlier ones are processed.
+pub fn rows(path: PathBuf, sql: String) -> Box<dyn Iterator<Item = io::Result<String>>> {
+	let (tx, rx) = mpsc::sync_channel(BUFFER);
+
+	thread::spawn(move || {
+		if let Err(err) = query(&path, &sql, &tx) {
+			let _ = tx.send(Err(io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), err))));
+		}
+	});
+
+	Box::new(rx.into_iter())
+}
+
+/// Tables of QSO databases
+const SCHEMA: &str = "
+	CREATE TABLE IF NOT EXISTS qsos (
+		id INTEGER PRIMARY KEY,
+		my_call TEXT NOT NULL,
+		my_grid TEXT NOT NULL,
+		call TEXT NOT NULL,
+		grid TEXT NOT NULL,
+		band TEXT NOT NULL,
+		time_on TEXT NOT NULL,
+		time_off TEXT NOT NULL,
+		frequency REAL NOT NULL,
+		rx_frequency REAL NOT NULL,
+		mode TEXT NOT NULL,
+		my_snr INTEGER NOT NULL,
+		snr INTEGER NOT NULL,
+		my_power_dbm INTEGER,
+		power_dbm INTEGER,
+		my_drift INTEGER NOT NULL,
+		drift INTEGER NOT NULL,
+		distance INTEGER NOT NULL,
+		rx_spots INTEGER NOT NULL,
+		tx_spots INTEGER NOT NULL,
+		UNIQUE (my_call, call, band, time_on)
+	);
+
+	CREATE TABLE IF NOT EXISTS qso_spots (
+		qso_id INTEGER NOT NULL REFERENCES qsos (id),
+		spot_id INTEGER NOT NULL,
+		PRIMARY KEY (qso_id, spot_id)
+	) WITHOUT ROWID;
+";
+
+/// Insert a QSO or update the one with the same operator, contact, band and start time
+const UPSERT: &str = "
+	INSERT INTO qsos (my_call, my_grid, call, grid, band, time_on, time_off, frequency, rx_frequency, mode,
+	                  my_snr, snr, my_power_dbm, power_dbm, my_drift, drift, distance, rx_spots, tx_spots)
+	VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19)
+	ON CONFLICT (my_call, call, band, time_on) DO UPDATE SET
+		my_grid = excluded.my_grid, grid = excluded.grid, time_off = excluded.time_off,
+		frequency = excluded.frequency, rx_frequency = excluded.rx_frequency, mode = excluded.mode,
+		my_snr = excluded.my_snr, snr = excluded.snr, my_power_dbm = excluded.my_power_dbm,
+		power_dbm = excluded.power_dbm, my_drift = excluded.my_drift, drift = excluded.drift,
+		distance = excluded.distance, rx_spots = excluded.rx_spots, tx_spots = excluded.tx_spots
+	RETURNING id
+";
+
+/// SQLite database of QSOs and the IDs of the spots they consist of
+///
+/// QSOs are written in a transaction that is committed at checkpoints.
+pub struct Database {
+	conn: Connection,
+	path: PathBuf,
+}
+
+impl Database {
+	/// Open or create a database, creating the tables if missing
+	pub fn open(path: &Path) -> io::Result<Self> {
+		let db = Connection::open(path)
+			.and_then(|conn| {
+				conn.execute_batch(SCHEMA)?;
+				conn.execute_batch("BEGIN")?;
+				Ok(conn)
+			})
+			.map(|conn| Database { conn, path: path.to_owned() });
+
+		db.map_err(|err| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), err)))
+	}
+
+	/// Convert an error, naming the database
+	fn error(&self, err: rusqlite::Error) -> io::Error {
+		io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", self.path.display(), err))
+	}
+
+	/// Insert or update a QSO, replacing its spots
+	pub fn write(&mut self, qso: &Qso) -> io::Result<()> {
+		let band = match Band::try_from(qso.freq_op) {
+			Ok(band) => band.name(),
+			Err(_) => String::new(),
+		};
+
+		let dbm = |power: Power| power.is_known().then_some(power.0);
+
+		let result = self.conn.prepare_cached(UPSERT)
+			.and_then(|mut stmt| stmt.query_row(params![
+				qso.call_op.as_str(), qso.grid_op.as_str(), qso.call_ct.as_str(), qso.grid_ct.as_str(), band,
+				qso.datetime_on().format("%Y-%m-%d %H:%M:%S").to_string(),
+				qso.datetime_off().format("%Y-%m-%d %H:%M:%S").to_string(),
+				qso.freq_op.mhz(), qso.freq_ct.mhz(), qso.mode.to_string(), qso.snr_op, qso.snr_ct,
+				dbm(qso.power_op), dbm(qso.power_ct), qso.drift_op, qso.drift_ct, qso.distance,
+				qso.num_rx as i64, qso.num_tx as i64,
+			], |row| row.get::<_, i64>(0)))
+			.and_then(|id| {
+				self.conn.prepare_cached("DELETE FROM qso_spots WHERE qso_id = ?1")?.execute([id])?;
+				let mut stmt = self.conn.prepare_cached("INSERT INTO qso_spots (qso_id, spot_id) VALUES (?1, ?2)")?;
+				for &spot in &qso.spots {
+					stmt.execute(params![id, spot as i64])?;
+				}
+
+				Ok(())
+			});
+
+		result.map_err(|err| self.error(err))
+	}
+
+	/// Commit the QSOs written so far
+	pub fn checkpoint(self) -> io::Result<Self> {
+		self.conn.execute_batch("COMMIT; BEGIN").map_err(|err| self.error(err))?;
+		Ok(self)
+	}
+
+	/// Commit the QSOs written so far and close the database
+	pub fn finish(self) -> io::Result<()> {
+		self.conn.execute_batch("COMMIT").map_err(|err| self.error(err))
+	}
+}
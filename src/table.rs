@@ -0,0 +1,89 @@
+use std::borrow::Cow;
+use std::io;
+use std::io::prelude::*;
+
+use crate::{Band, Power, Qso};
+use crate::adif::RecordOptions;
+
+/// Columns of every row, followed by the extra columns
+const COLUMNS: &[&str] = &[
+	"my_call", "my_grid", "call", "grid", "time_on", "time_off", "band", "frequency", "rx_frequency", "mode",
+	"my_snr", "snr", "my_snr_min", "snr_min", "my_snr_mean", "snr_mean", "my_power_dbm", "power_dbm", "my_drift", "drift", "distance", "spots", "rx_spots",
+	"tx_spots", "spot_ids", "serial", "qso_id",
+];
+
+/// Quote a field if it contains delimiters or quotes
+pub fn quote(field: &str) -> Cow<'_, str> {
+	if field.contains([',', '"', '\n', '\r']) {
+		Cow::Owned(format!("\"{}\"", field.replace('"', "\"\"")))
+	} else {
+		Cow::Borrowed(field)
+	}
+}
+
+/// Power in dBm, or nothing if unknown
+fn dbm(power: Power) -> std::string::String {
+	if power.is_known() {
+		power.0.to_string()
+	} else {
+		std::string::String::new()
+	}
+}
+
+/// Table of QSOs in CSV format with a header row, one row per QSO
+pub struct Table<W: Write> {
+	out: W,
+}
+
+impl<W: Write> Table<W> {
+	/// Start a new table, writing the header row
+	///
+	/// Extra columns are named after the operator’s and the contact’s receiver.
+	pub fn new(mut out: W, options: &RecordOptions) -> io::Result<Self> {
+		let mut header: Vec<_> = COLUMNS.iter().map(|&name| name.to_owned()).collect();
+		for name in &options.extra_columns {
+			let name = name.to_ascii_lowercase();
+			header.push(format!("my_{name}"));
+			header.push(name);
+		}
+
+		writeln!(out, "{}", header.join(","))?;
+		Ok(Table { out })
+	}
+
+	/// Continue an existing table that already has a header row
+	pub fn resume(out: W) -> Self {
+		Table { out }
+	}
+
+	/// Write a QSO row
+	pub fn write(&mut self, qso: &Qso, options: &RecordOptions) -> io::Result<()> {
+		let band = match Band::try_from(qso.freq_op) {
+			Ok(band) => band.name(),
+			Err(_) => std::string::String::new(),
+		};
+
+		let ids = qso.spots.iter().map(u64::to_string).collect::<Vec<_>>().join(" ");
+
+		write!(self.out, "{},{},{},{},{},{},{},{:.6},{:.6},{},{},{},{},{},{:.1},{:.1},{},{},{},{},{},{},{},{},{},{},{}",
+		       quote(&qso.call_op), quote(&qso.grid_op), quote(&qso.call_ct), quote(&qso.grid_ct),
+		       qso.datetime_on().format("%Y-%m-%dT%H:%M:%SZ"), qso.datetime_off().format("%Y-%m-%dT%H:%M:%SZ"),
+		       band, qso.freq_op.mhz(), qso.freq_ct.mhz(), qso.mode,
+		       qso.snr_op, qso.snr_ct, qso.snr_op_min, qso.snr_ct_min, qso.snr_op_mean(), qso.snr_ct_mean(),
+		       dbm(qso.power_op), dbm(qso.power_ct), qso.drift_op, qso.drift_ct,
+		       qso.distance, qso.spots.len(), qso.num_rx, qso.num_tx, ids, qso.serial, qso.id())?;
+
+		// Extra columns as measured by the operator and the contact, respectively
+		for idx in 0..options.extra_columns.len() {
+			let op = qso.extra_op.get(idx).map_or("", |value| value.as_str());
+			let ct = qso.extra_ct.get(idx).map_or("", |value| value.as_str());
+			write!(self.out, ",{},{}", quote(op), quote(ct))?;
+		}
+
+		writeln!(self.out)
+	}
+
+	pub fn into_inner(self) -> W {
+		self.out
+	}
+}
@@ -0,0 +1,302 @@
+use std::fmt;
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::io::prelude::*;
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use chrono::format::{Item, StrftimeItems};
+use smartstring::alias::String;
+
+use crate::Qso;
+use crate::output::Sink;
+
+/// Default QSL message and comment
+pub const DEFAULT: &str =
+	"2-way WSPR spot on {band} with {power} ({power_dbm} dBm), SNR {snr} dB, drift {drift} Hz/s, distance {distance} km";
+
+/// Placeholder in a message template
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Field {
+	/// Operator call sign
+	MyCall,
+	/// Contact call sign
+	Call,
+	/// Operator Maidenhead locator
+	MyGrid,
+	/// Contact Maidenhead locator
+	Grid,
+	/// Band, including the receive band if different
+	Band,
+	/// Contact’s transmission power
+	Power,
+	/// Contact’s transmission power in dBm
+	PowerDbm,
+	/// Contact’s SNR
+	Snr,
+	/// Operator’s SNR
+	MySnr,
+	/// Contact’s frequency drift
+	Drift,
+	/// Distance in km
+	Distance,
+	/// Number of spots
+	Spots,
+	/// Operator’s transmission power
+	MyPower,
+	/// Operator’s transmission power in dBm
+	MyPowerDbm,
+	/// Operator’s frequency drift
+	MyDrift,
+	/// Operator’s lowest SNR
+	MySnrMin,
+	/// Contact’s lowest SNR
+	SnrMin,
+	/// Operator’s mean SNR
+	MySnrMean,
+	/// Contact’s mean SNR
+	SnrMean,
+	/// Operator’s transmit frequency in MHz
+	Frequency,
+	/// Contact’s transmit frequency in MHz
+	RxFrequency,
+	/// Contact’s transmission mode
+	Mode,
+	/// Start of the QSO
+	TimeOn,
+	/// End of the QSO
+	TimeOff,
+	/// Number of spots with the operator as reporter
+	RxSpots,
+	/// Number of spots with the operator as transmitter
+	TxSpots,
+	/// Spot IDs separated by spaces
+	SpotIds,
+	/// Serial number in order of logging
+	Serial,
+	/// Stable ID of the QSO
+	QsoId,
+}
+
+impl Field {
+	const NAMES: &'static [(&'static str, Field)] = &[
+		("my_call", Field::MyCall),
+		("call", Field::Call),
+		("my_grid", Field::MyGrid),
+		("grid", Field::Grid),
+		("band", Field::Band),
+		("power", Field::Power),
+		("power_dbm", Field::PowerDbm),
+		("snr", Field::Snr),
+		("my_snr", Field::MySnr),
+		("drift", Field::Drift),
+		("distance", Field::Distance),
+		("spots", Field::Spots),
+		("my_power", Field::MyPower),
+		("my_power_dbm", Field::MyPowerDbm),
+		("my_drift", Field::MyDrift),
+		("my_snr_min", Field::MySnrMin),
+		("snr_min", Field::SnrMin),
+		("my_snr_mean", Field::MySnrMean),
+		("snr_mean", Field::SnrMean),
+		("frequency", Field::Frequency),
+		("rx_frequency", Field::RxFrequency),
+		("mode", Field::Mode),
+		("time_on", Field::TimeOn),
+		("time_off", Field::TimeOff),
+		("rx_spots", Field::RxSpots),
+		("tx_spots", Field::TxSpots),
+		("spot_ids", Field::SpotIds),
+		("serial", Field::Serial),
+		("qso_id", Field::QsoId),
+	];
+}
+
+/// Format of times without a format given in the placeholder
+const TIME_FORMAT: &str = "%Y-%m-%dT%H:%M:%SZ";
+
+#[derive(Clone, Debug)]
+enum Segment {
+	Text(String),
+	Field(Field),
+	/// Time with a strftime format, as in `{time_on:%H%M}`
+	Time(Field, std::string::String),
+}
+
+/// Message text with `{name}` placeholders filled in from a QSO
+#[derive(Clone, Debug)]
+pub struct Template(Vec<Segment>);
+
+impl Template {
+	/// Render the template for a QSO
+	pub fn render(&self, qso: &Qso) -> String {
+		let mut out = String::new();
+
+		for segment in &self.0 {
+			// Writing to a string cannot fail
+			let _ = match segment {
+				Segment::Text(text) => write!(out, "{text}"),
+				Segment::Field(Field::MyCall) => write!(out, "{}", qso.call_op),
+				Segment::Field(Field::Call) => write!(out, "{}", qso.call_ct),
+				Segment::Field(Field::MyGrid) => write!(out, "{}", qso.grid_op),
+				Segment::Field(Field::Grid) => write!(out, "{}", qso.grid_ct),
+				Segment::Field(Field::Band) => write!(out, "{}", qso.band_description()),
+				Segment::Field(Field::Power) => write!(out, "{}", qso.power_ct),
+				Segment::Field(Field::PowerDbm) if !qso.power_ct.is_known() => write!(out, "?"),
+				Segment::Field(Field::PowerDbm) => write!(out, "{}", qso.power_ct.0),
+				Segment::Field(Field::Snr) => write!(out, "{}", qso.snr_ct),
+				Segment::Field(Field::MySnr) => write!(out, "{}", qso.snr_op),
+				Segment::Field(Field::Drift) => write!(out, "{:+}", qso.drift_ct),
+				Segment::Field(Field::Distance) => write!(out, "{}", qso.distance),
+				Segment::Field(Field::Spots) => write!(out, "{}", qso.spots.len()),
+				Segment::Field(Field::MyPower) => write!(out, "{}", qso.power_op),
+				Segment::Field(Field::MyPowerDbm) if !qso.power_op.is_known() => write!(out, "?"),
+				Segment::Field(Field::MyPowerDbm) => write!(out, "{}", qso.power_op.0),
+				Segment::Field(Field::MyDrift) => write!(out, "{:+}", qso.drift_op),
+				Segment::Field(Field::MySnrMin) => write!(out, "{}", qso.snr_op_min),
+				Segment::Field(Field::SnrMin) => write!(out, "{}", qso.snr_ct_min),
+				Segment::Field(Field::MySnrMean) => write!(out, "{:.1}", qso.snr_op_mean()),
+				Segment::Field(Field::SnrMean) => write!(out, "{:.1}", qso.snr_ct_mean()),
+				Segment::Field(Field::Frequency) => write!(out, "{:.6}", qso.freq_op.mhz()),
+				Segment::Field(Field::RxFrequency) => write!(out, "{:.6}", qso.freq_ct.mhz()),
+				Segment::Field(Field::Mode) => write!(out, "{}", qso.mode),
+				Segment::Field(field @ (Field::TimeOn | Field::TimeOff)) => write!(out, "{}", time(qso, *field).format(TIME_FORMAT)),
+				Segment::Time(field, format) => write!(out, "{}", time(qso, *field).format(format)),
+				Segment::Field(Field::RxSpots) => write!(out, "{}", qso.num_rx),
+				Segment::Field(Field::TxSpots) => write!(out, "{}", qso.num_tx),
+				Segment::Field(Field::SpotIds) => write!(out, "{}",
+					qso.spots.iter().map(u64::to_string).collect::<Vec<_>>().join(" ")),
+				Segment::Field(Field::Serial) => write!(out, "{}", qso.serial),
+				Segment::Field(Field::QsoId) => write!(out, "{}", qso.id()),
+			};
+		}
+
+		out
+	}
+}
+
+/// Start or end of a QSO
+fn time(qso: &Qso, field: Field) -> DateTime<Utc> {
+	match field {
+		Field::TimeOff => qso.datetime_off(),
+		_ => qso.datetime_on(),
+	}
+}
+
+/// Read a template from a file
+pub fn read(path: &str) -> Result<Template, std::string::String> {
+	let text = fs::read_to_string(path).map_err(|err: io::Error| format!("{path}: {err}"))?;
+	text.parse().map_err(|err: ParseError| format!("{path}: {err}"))
+}
+
+/// Log with every QSO rendered through a user‐supplied template, without header
+pub struct Rendered<W: Write> {
+	out: W,
+	template: Template,
+}
+
+impl<W: Write> Rendered<W> {
+	pub fn new(out: W, template: Template) -> Self {
+		Rendered { out, template }
+	}
+
+	/// Write a QSO as rendered, with a line break only if the template ends with one
+	pub fn write(&mut self, qso: &Qso) -> io::Result<()> {
+		self.out.write_all(self.template.render(qso).as_bytes())
+	}
+
+	pub fn into_inner(self) -> W {
+		self.out
+	}
+}
+
+impl Rendered<Sink> {
+	/// Make the QSOs written so far available
+	pub fn checkpoint(self) -> io::Result<Self> {
+		Ok(Rendered { out: self.out.checkpoint()?, ..self })
+	}
+}
+
+/// Error parsing a template
+#[derive(Debug)]
+pub struct ParseError(std::string::String);
+
+impl fmt::Display for ParseError {
+	fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+		write!(fmtr, "{}", self.0)
+	}
+}
+
+impl std::error::Error for ParseError { }
+
+impl FromStr for Template {
+	type Err = ParseError;
+
+	/// Parse a template, with `{{` and `}}` denoting literal braces
+	///
+	/// Times may be followed by a strftime format, as in `{time_on:%Y%m%d}`.
+	fn from_str(text: &str) -> Result<Self, Self::Err> {
+		let mut segments = Vec::new();
+		let mut literal = String::new();
+		let mut chars = text.chars().peekable();
+
+		while let Some(ch) = chars.next() {
+			match ch {
+				'{' if chars.peek() == Some(&'{') => {
+					chars.next();
+					literal.push('{');
+				},
+				'}' if chars.peek() == Some(&'}') => {
+					chars.next();
+					literal.push('}');
+				},
+				'{' => {
+					let mut name = String::new();
+					loop {
+						match chars.next() {
+							Some('}') => break,
+							Some(ch) => name.push(ch),
+							None => return Err(ParseError("unmatched {".into())),
+						}
+					}
+
+					let (name, format) = match name.split_once(':') {
+						Some((name, format)) => (name, Some(format)),
+						None => (name.as_str(), None),
+					};
+
+					let field = Field::NAMES.iter()
+						.find(|(known, _)| *known == name)
+						.map(|&(_, field)| field)
+						.ok_or_else(|| ParseError(format!("unknown placeholder {{{name}}}, expected one of {}",
+							Field::NAMES.iter().map(|(known, _)| *known).collect::<Vec<_>>().join(", "))))?;
+
+					if !literal.is_empty() {
+						segments.push(Segment::Text(std::mem::take(&mut literal)));
+					}
+
+					segments.push(match format {
+						None => Segment::Field(field),
+						Some(format) if matches!(field, Field::TimeOn | Field::TimeOff) => {
+							if StrftimeItems::new(format).any(|item| item == Item::Error) {
+								return Err(ParseError(format!("invalid time format in {{{name}:{format}}}")));
+							}
+
+							Segment::Time(field, format.to_owned())
+						},
+						Some(_) => return Err(ParseError(format!("only times take a format, not {{{name}}}"))),
+					});
+				},
+				'}' => return Err(ParseError("unmatched }".into())),
+				_ => literal.push(ch),
+			}
+		}
+
+		if !literal.is_empty() {
+			segments.push(Segment::Text(literal));
+		}
+
+		Ok(Template(segments))
+	}
+}
@@ -0,0 +1,44 @@
+use std::io;
+use std::io::prelude::*;
+
+use crate::{Power, Qso};
+
+/// Power in dBm, or a question mark if unknown
+fn dbm(power: Power) -> std::string::String {
+	if power.is_known() {
+		format!("{} dBm", power.0)
+	} else {
+		"?".to_owned()
+	}
+}
+
+/// Plain text log with one aligned line per QSO, for reading
+pub struct Text<W: Write> {
+	out: W,
+}
+
+impl<W: Write> Text<W> {
+	/// Start a new log, writing the column headings
+	pub fn new(mut out: W) -> io::Result<Self> {
+		writeln!(out, "{:<10} {:<5} {:<5} {:<10} {:<10} {:<6} {:<8} {:>6} {:>4} {:>8} {:>8} {:>8}",
+		         "Date", "On", "Off", "Operator", "Contact", "Grid", "Band", "My SNR", "SNR", "My power", "Power", "Distance")?;
+		Ok(Text { out })
+	}
+
+	/// Continue an existing log that already has column headings
+	pub fn resume(out: W) -> Self {
+		Text { out }
+	}
+
+	/// Write a QSO line
+	pub fn write(&mut self, qso: &Qso) -> io::Result<()> {
+		writeln!(self.out, "{} {} {} {:<10} {:<10} {:<6} {:<8} {:>6} {:>4} {:>8} {:>8} {:>5} km",
+		         qso.datetime_on().format("%Y-%m-%d"), qso.datetime_on().format("%H:%M"),
+		         qso.datetime_off().format("%H:%M"), qso.call_op.as_str(), qso.call_ct.as_str(), qso.grid_ct.as_str(),
+		         qso.band_description(), qso.snr_op, qso.snr_ct, dbm(qso.power_op), dbm(qso.power_ct), qso.distance)
+	}
+
+	pub fn into_inner(self) -> W {
+		self.out
+	}
+}
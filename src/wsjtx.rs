@@ -0,0 +1,237 @@
+use std::io;
+use std::net::UdpSocket;
+use std::sync::mpsc;
+use std::thread;
+
+use chrono::{Duration, NaiveTime, Utc};
+use log::{debug, info};
+
+use crate::decodes;
+
+/// Default address WSJT-X sends its messages to
+pub const DEFAULT_ADDR: &str = "127.0.0.1:2237";
+
+/// Magic number at the start of every message
+const MAGIC: u32 = 0xadbc_cbda;
+
+/// Type of the status message
+const STATUS: u32 = 1;
+
+/// Type of the WSPR decode message
+const WSPR_DECODE: u32 = 10;
+
+/// Bit set in the IDs assigned to decodes received from WSJT-X, besides [decodes::LOCAL_ID]
+const WSJTX_ID: u64 = 1 << 62;
+
+/// Reader of the fields of a message as serialised by Qt’s `QDataStream`
+struct Message<'a>(&'a [u8]);
+
+impl Message<'_> {
+	fn bytes<const N: usize>(&mut self) -> Option<[u8; N]> {
+		let (head, tail) = self.0.split_first_chunk::<N>()?;
+		self.0 = tail;
+		Some(*head)
+	}
+
+	fn bool(&mut self) -> Option<bool> {
+		Some(self.bytes::<1>()?[0] != 0)
+	}
+
+	fn u32(&mut self) -> Option<u32> {
+		self.bytes().map(u32::from_be_bytes)
+	}
+
+	fn i32(&mut self) -> Option<i32> {
+		self.bytes().map(i32::from_be_bytes)
+	}
+
+	fn u64(&mut self) -> Option<u64> {
+		self.bytes().map(u64::from_be_bytes)
+	}
+
+	fn f64(&mut self) -> Option<f64> {
+		self.bytes().map(f64::from_be_bytes)
+	}
+
+	/// UTF‐8 string, which is empty if null
+	fn string(&mut self) -> Option<String> {
+		let len = match self.u32()? {
+			u32::MAX => 0,
+			len => len as usize,
+		};
+
+		if self.0.len() < len {
+			return None;
+		}
+
+		let (head, tail) = self.0.split_at(len);
+		self.0 = tail;
+		Some(String::from_utf8_lossy(head).into_owned())
+	}
+}
+
+/// Listener state
+struct Listener {
+	/// Call sign and locator of the station as last reported by WSJT-X
+	station: Option<(String, String)>,
+	/// Number of decodes received
+	num_decodes: u64,
+}
+
+impl Listener {
+	/// Handle a message, returning a row in WSPRnet CSV format for new WSPR decodes
+	fn handle(&mut self, packet: &[u8]) -> Option<String> {
+		let mut msg = Message(packet);
+
+		if msg.u32()? != MAGIC {
+			return None;
+		}
+
+		let _schema = msg.u32()?;
+		let kind = msg.u32()?;
+		let _id = msg.string()?;
+
+		match kind {
+			STATUS => {
+				let _dial_frequency = msg.u64()?;
+				let _mode = msg.string()?;
+				let _dx_call = msg.string()?;
+				let _report = msg.string()?;
+				let _tx_mode = msg.string()?;
+				let _tx_enabled = msg.bool()?;
+				let _transmitting = msg.bool()?;
+				let _decoding = msg.bool()?;
+				let _rx_df = msg.u32()?;
+				let _tx_df = msg.u32()?;
+				let call = msg.string()?;
+				let grid = msg.string()?;
+
+				if !call.is_empty() && self.station.as_ref().is_none_or(|station| *station != (call.clone(), grid.clone())) {
+					info!("Receiving WSJT-X decodes of {call} in {grid}");
+					self.station = Some((call, grid));
+				}
+
+				None
+			},
+			WSPR_DECODE => {
+				let _new = msg.bool()?;
+				let time = msg.u32()?;
+				let snr = msg.i32()?;
+				let _dt = msg.f64()?;
+				let frequency = msg.u64()?;
+				let drift = msg.i32()?;
+				let call = msg.string()?;
+				let grid = msg.string()?;
+				let power = msg.i32()?;
+				let off_air = msg.bool()?;
+
+				if off_air {
+					return None;
+				}
+
+				let Some((call_rx, grid_rx)) = &self.station else {
+					debug!("Dropping decode of {call} before the station is known");
+					return None;
+				};
+
+				// Only the time of day is given, which is at most a cycle ago
+				let now = Utc::now().naive_utc();
+				let mut timestamp = now.date().and_time(NaiveTime::from_num_seconds_from_midnight_opt(time / 1000, 0)?);
+				if timestamp > now + Duration::minutes(1) {
+					timestamp -= Duration::days(1);
+				}
+
+				self.num_decodes += 1;
+				let call = call.trim_start_matches('<').trim_end_matches('>');
+				Some(decodes::row((call_rx, grid_rx), WSJTX_ID | self.num_decodes, timestamp.and_utc().timestamp(),
+					snr.try_into().ok()?, frequency as f64 / 1e6, (call, &grid, power.try_into().ok()?), drift.try_into().ok()?))
+			},
+			_ => None,
+		}
+	}
+}
+
+/// Listen for WSPR decodes sent by WSJT-X over UDP on a separate thread
+///
+/// Decodes are converted into rows in WSPRnet CSV format, with the receiving station taken from
+/// the status messages.
+pub fn listen(addr: &str) -> io::Result<mpsc::Receiver<io::Result<String>>> {
+	let socket = UdpSocket::bind(addr)?;
+	let (tx, rx) = mpsc::channel();
+	info!("Listening for WSJT-X messages on {}", socket.local_addr()?);
+
+	thread::spawn(move || {
+		let mut listener = Listener { station: None, num_decodes: 0 };
+		let mut buf = [0; 65536];
+
+		loop {
+			let row = match socket.recv(&mut buf) {
+				Ok(len) => match listener.handle(&buf[..len]) {
+					Some(row) => Ok(row),
+					None => continue,
+				},
+				Err(err) => Err(err),
+			};
+
+			let failed = row.is_err();
+			if tx.send(row).is_err() || failed {
+				break;
+			}
+		}
+	});
+
+	Ok(rx)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// Message of the given type with the fields serialised as by `QDataStream`
+	fn message(kind: u32, fields: &[&[u8]]) -> Vec<u8> {
+		let mut msg = [MAGIC.to_be_bytes(), 2u32.to_be_bytes(), kind.to_be_bytes()].concat();
+		msg.extend(string("WSJT-X"));
+		fields.iter().for_each(|field| msg.extend_from_slice(field));
+		msg
+	}
+
+	fn string(value: &str) -> Vec<u8> {
+		[&(value.len() as u32).to_be_bytes(), value.as_bytes()].concat()
+	}
+
+	fn status(call: &str, grid: &str) -> Vec<u8> {
+		message(STATUS, &[&14_095_600u64.to_be_bytes(), &string("WSPR"), &string(""), &string(""), &string("WSPR"),
+			&[0], &[0], &[0], &1500u32.to_be_bytes(), &1500u32.to_be_bytes(), &string(call), &string(grid)])
+	}
+
+	fn decode(call: &str, off_air: bool) -> Vec<u8> {
+		message(WSPR_DECODE, &[&[1], &0u32.to_be_bytes(), &(-21i32).to_be_bytes(), &0.5f64.to_be_bytes(),
+			&14_097_100u64.to_be_bytes(), &(-1i32).to_be_bytes(), &string(call), &string("FN42"), &37i32.to_be_bytes(), &[off_air as u8]])
+	}
+
+	#[test]
+	fn converts_decodes() {
+		let mut listener = Listener { station: None, num_decodes: 0 };
+
+		assert_eq!(listener.handle(&decode("K1ABC", false)), None);
+		assert_eq!(listener.handle(&status("DO5EU", "JO62qm")), None);
+		assert_eq!(listener.station, Some(("DO5EU".into(), "JO62qm".into())));
+
+		let row = listener.handle(&decode("<K1ABC>", false)).unwrap();
+		let fields: Vec<_> = row.split(',').collect();
+		assert_eq!(fields[0].parse::<u64>().unwrap(), decodes::LOCAL_ID | WSJTX_ID | 1);
+		assert_eq!(fields[1].parse::<i64>().unwrap() % 86400, 0);
+		assert_eq!(fields[2..10], ["DO5EU", "JO62qm", "-21", "14.097100", "K1ABC", "FN42", "37", "-1"]);
+	}
+
+	#[test]
+	fn skips_other_messages() {
+		let mut listener = Listener { station: Some(("DO5EU".into(), "JO62qm".into())), num_decodes: 0 };
+
+		assert_eq!(listener.handle(&decode("K1ABC", true)), None);
+		assert_eq!(listener.handle(&message(WSPR_DECODE, &[&[1]])), None);
+		assert_eq!(listener.handle(&message(2, &[])), None);
+		assert_eq!(listener.handle(&[0; 16]), None);
+		assert_eq!(listener.num_decodes, 0);
+	}
+}
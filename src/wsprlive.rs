@@ -0,0 +1,60 @@
+use std::io;
+use std::io::prelude::*;
+
+use log::info;
+
+use crate::Call;
+
+/// Default URL of the wspr.live ClickHouse HTTP interface
+pub const DEFAULT_URL: &str = "https://db1.wspr.live/";
+
+/// Columns in the order of the WSPRnet CSV format
+const COLUMNS: &str = "id, toUnixTimestamp(time), rx_sign, rx_loc, snr, round(frequency / 1000000, 6), \
+                       tx_sign, tx_loc, power, drift, distance, azimuth, band, version, code";
+
+/// Build a query for the spots involving any of the given call signs within a time window
+fn query(calls: &[Call], since: u64, until: Option<u64>, after: Option<u64>) -> io::Result<String> {
+	let mut list = Vec::new();
+	for call in calls {
+		// Call signs are interpolated into the query
+		if call.is_empty() || !call.bytes().all(|ch| ch.is_ascii_alphanumeric() || ch == b'/') {
+			return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid call sign {call}")));
+		}
+
+		list.push(format!("'{}'", call.to_ascii_uppercase()));
+	}
+
+	let list = list.join(", ");
+	let mut query = format!("SELECT {COLUMNS} FROM wspr.rx \
+	                         WHERE (rx_sign IN ({list}) OR tx_sign IN ({list})) \
+	                         AND time >= toDateTime({since})");
+
+	if let Some(until) = until {
+		query.push_str(&format!(" AND time < toDateTime({until})"));
+	}
+
+	if let Some(after) = after {
+		query.push_str(&format!(" AND id > {after}"));
+	}
+
+	query.push_str(" ORDER BY time, id FORMAT CustomSeparated");
+	Ok(query)
+}
+
+/// Query the spots involving any of the given call signs, in WSPRnet CSV format
+///
+/// Only spots with an ID greater than `after` are returned, if given.
+pub fn spots(url: &str, calls: &[Call], since: u64, until: Option<u64>, after: Option<u64>) -> io::Result<Box<dyn BufRead>> {
+	let query = query(calls, since, until, after)?;
+	info!("Querying {url}");
+
+	// Plain comma‐separated values without quoting, as in WSPRnet dumps
+	let response = ureq::post(url)
+		.set("User-Agent", concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION")))
+		.query("format_custom_escaping_rule", "Raw")
+		.query("format_custom_field_delimiter", ",")
+		.send_string(&query)
+		.map_err(|err| io::Error::other(err.to_string()))?;
+
+	Ok(Box::new(io::BufReader::new(response.into_reader())))
+}